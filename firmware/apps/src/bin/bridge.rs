@@ -0,0 +1,57 @@
+//! Zero-copy, async bridge between the USB HID interface and the IEEE 802.15.4 radio
+//!
+//! HID packets sent by the host are transmitted over the radio and the radio packets received with
+//! a valid CRC are forwarded to the host. Both directions allocate from the same packet pool and
+//! packets are converted between the two kinds without copying their data.
+//!
+//! Prerequisites: the other end must listen and transmit on the same channel (`CHANNEL`). The HAL
+//! sets the transmit power (+8 dBm) and the IEEE 802.15.4 mode at boot. Radio packets larger than
+//! a HID packet (64 bytes) are dropped
+
+#![deny(unused_must_use)]
+#![no_main]
+#![no_std]
+
+use core::convert::TryFrom;
+
+use hal::{
+    radio::{self, Channel},
+    usbd::{self, Packet},
+};
+use panic_abort as _;
+
+const CHANNEL: Channel = Channel::_20;
+
+#[no_mangle]
+fn main() -> ! {
+    let (mut hidout, mut hidin) = usbd::hid();
+    let (mut tx, mut rx) = radio::claim(CHANNEL);
+
+    let usb2radio = async {
+        loop {
+            let mut packet = Packet::new().await;
+            hidout.read(&mut packet).await;
+
+            if tx.write(&packet.into()).await.is_err() {
+                semidap::error!("radio: packet could not be sent");
+            }
+        }
+    };
+
+    let radio2usb = async {
+        loop {
+            let mut packet = radio::Packet::new().await;
+            if rx.read(&mut packet).await.is_err() {
+                semidap::warn!("radio: CRC error; packet dropped");
+                continue;
+            }
+
+            match Packet::try_from(packet) {
+                Ok(packet) => hidin.write(&packet).await,
+                Err(packet) => semidap::warn!("radio: {}-byte packet dropped", packet.len()),
+            }
+        }
+    };
+
+    executor::run!(usb2radio, radio2usb)
+}
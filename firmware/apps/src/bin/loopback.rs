@@ -0,0 +1,15 @@
+#![deny(unused_must_use)]
+#![no_main]
+#![no_std]
+
+use hal::usbd;
+use panic_abort as _;
+
+#[no_mangle]
+fn main() -> ! {
+    let (hidout, hidin) = usbd::hid();
+
+    let task = usbd::loopback(hidout, hidin);
+
+    executor::run!(task)
+}
@@ -20,6 +20,45 @@ __cpsidi:
   .cfi_endproc
   .size __cpsidi, . - __cpsidi
 
+  .global __dsb
+  .cfi_sections .debug_frame
+  .section .text.__dsb, "ax"
+  .thumb_func
+  .cfi_startproc
+__dsb:
+  dsb sy
+  bx lr
+  .cfi_endproc
+  .size __dsb, . - __dsb
+
+  .global __paint_stack
+  .cfi_sections .debug_frame
+  .section .text.__paint_stack, "ax"
+  .thumb_func
+  .cfi_startproc
+__paint_stack:
+  mov r2, sp
+1:
+  cmp r0, r2
+  bhs 2f
+  str r1, [r0], #4
+  b 1b
+2:
+  bx lr
+  .cfi_endproc
+  .size __paint_stack, . - __paint_stack
+
+  .global __primask
+  .cfi_sections .debug_frame
+  .section .text.__primask, "ax"
+  .thumb_func
+  .cfi_startproc
+__primask:
+  mrs r0, PRIMASK
+  bx lr
+  .cfi_endproc
+  .size __primask, . - __primask
+
   .global __sev
   .cfi_sections .debug_frame
   .section .text.__sev, "ax"
@@ -22,6 +22,38 @@ pub fn enable_irq() {
     unsafe { __cpsiei() }
 }
 
+/// Data Synchronization Barrier
+pub fn dsb() {
+    extern "C" {
+        fn __dsb();
+    }
+    unsafe { __dsb() }
+}
+
+/// Fills the memory from `bottom` up to, but not including, the current stack pointer with
+/// `pattern`
+///
+/// # Safety
+///
+/// `bottom` must be 4-byte aligned and the memory between `bottom` and the stack pointer must not
+/// be in use
+pub unsafe fn paint_stack(bottom: *mut u32, pattern: u32) {
+    extern "C" {
+        fn __paint_stack(bottom: *mut u32, pattern: u32);
+    }
+    __paint_stack(bottom, pattern)
+}
+
+/// Reads the PRIMASK register
+///
+/// Interrupts are masked when bit 0 is set
+pub fn primask() -> u32 {
+    extern "C" {
+        fn __primask() -> u32;
+    }
+    unsafe { __primask() }
+}
+
 /// Send EVent
 pub fn sev() {
     #[cfg(target_arch = "arm")]
@@ -0,0 +1,66 @@
+//! Future combinators
+
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// The output of the future that completed first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Either<L, R> {
+    /// The first future completed first
+    Left(L),
+    /// The second future completed first
+    Right(R),
+}
+
+/// Future returned by `select`
+pub struct Select<A, B> {
+    a: A,
+    b: B,
+}
+
+/// Waits for the first of two futures to complete
+///
+/// Both futures are polled every time the executor wakes up; `a` is polled before `b` so if both
+/// are ready at the same time `a` wins.
+///
+/// # Drop semantics
+///
+/// Once one future completes the other one is dropped without being polled again, in the middle
+/// of whatever it was doing. Dropping a future does *not* undo the side effects it has already
+/// caused: e.g. a hardware transfer (EasyDMA) that the losing future started keeps running and
+/// will still write to, or read from, the buffer the future was operating on. Only use futures
+/// that can be safely abandoned at any `.await` point as the losing side, or make sure the
+/// operation has not started yet, as it's the case when waiting for data to arrive
+pub fn select<A, B>(a: A, b: B) -> Select<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    Select { a, b }
+}
+
+impl<A, B> Future for Select<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    type Output = Either<A::Output, B::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // NOTE(unsafe) structural pinning: the futures are never moved out of `Select`
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if let Poll::Ready(a) = unsafe { Pin::new_unchecked(&mut this.a) }.poll(cx) {
+            return Poll::Ready(Either::Left(a));
+        }
+
+        if let Poll::Ready(b) = unsafe { Pin::new_unchecked(&mut this.b) }.poll(cx) {
+            return Poll::Ready(Either::Right(b));
+        }
+
+        Poll::Pending
+    }
+}
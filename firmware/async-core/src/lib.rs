@@ -7,5 +7,6 @@
 #![deny(warnings)]
 #![no_std]
 
+pub mod future;
 pub mod task;
 pub mod unsync;
@@ -5,6 +5,17 @@
 //! - No heap allocations
 //! - No trait objects
 //! - Tasks do NOT need to satisfy the `: 'static` bound
+//!
+//! # Wake-ups
+//!
+//! The executor sleeps (`WFE`) when none of its tasks can make progress and polls *all* of them
+//! when it wakes up. `Waker`s are no-ops; instead, the executor is woken up by the "event" signal:
+//!
+//! - Returning from *any* interrupt handler wakes up the executor. An async driver only needs to
+//!   update some shared state (e.g. an atomic) from its interrupt handler; the future that polls
+//!   that state will be re-polled after the handler returns.
+//! - Code that runs in thread mode (e.g. another task) must call [`wake`] after it makes progress
+//!   possible for a different task
 
 #![deny(missing_docs)]
 #![deny(rust_2018_idioms)]
@@ -28,6 +39,15 @@ pub use asm::wfe;
 #[proc_macro_hack(support_nested)]
 pub use executor_macros::run;
 
+/// Wakes up the executor so that it re-polls all its tasks
+///
+/// It's safe to call this from interrupt handlers but it's not required; see the [crate-level
+/// documentation](index.html#wake-ups)
+#[inline(always)]
+pub fn wake() {
+    asm::sev()
+}
+
 /// Implementation detail
 #[doc(hidden)]
 #[inline(always)]
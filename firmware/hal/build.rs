@@ -10,8 +10,10 @@ fn main() -> Result<(), Box<dyn Error>> {
     let flash = env::var_os("CARGO_FEATURE_FLASH").is_some();
 
     descs(&out_dir)?;
+    check_vectors()?;
 
     // put the linker script somewhere the linker can find it
+    fs::write(out_dir.join("memory.x"), memory(device()?, flash))?;
     fs::copy("interrupts.x", out_dir.join("interrupts.x"))?;
     let suffix = if flash { "flash" } else { "ram" };
     fs::copy(format!("link-{}.x", suffix), out_dir.join("link.x"))?;
@@ -20,11 +22,115 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[derive(Clone, Copy)]
+enum Device {
+    Nrf52833,
+    Nrf52840,
+}
+
+impl Device {
+    // in KB
+    fn ram_size(self) -> u32 {
+        match self {
+            Device::Nrf52833 => 128,
+            Device::Nrf52840 => 256,
+        }
+    }
+}
+
+// the device picked with the Cargo features
+fn device() -> Result<Device, Box<dyn Error>> {
+    let nrf52833 = env::var_os("CARGO_FEATURE_NRF52833").is_some();
+    let nrf52840 = env::var_os("CARGO_FEATURE_NRF52840").is_some();
+
+    match (nrf52833, nrf52840) {
+        (true, false) => Ok(Device::Nrf52833),
+        (false, true) => Ok(Device::Nrf52840),
+        _ => Err("exactly one of the `nrf52833` and `nrf52840` features must be enabled".into()),
+    }
+}
+
+fn memory(device: Device, flash: bool) -> String {
+    let (regions, ram_origin) = if flash {
+        (
+            // NOTE both devices have at least 512 KB of Flash
+            concat!(
+                "  /* FLASH : ORIGIN = 0x1000, LENGTH = 0x7F000 */\n",
+                "  FLASH : ORIGIN = 0, LENGTH = 0x7F000\n",
+            ),
+            "0x20000008",
+        )
+    } else {
+        ("", "0x20000000")
+    };
+    let ram_size = device.ram_size();
+
+    format!(
+        "MEMORY
+{{
+  /* NOTE RAM is split as follows */
+  /* - 8 AHB slaves, each connected to a 2x4 KB RAM sections */
+  /* - the 9th AHB slave is connected to 6x32 KB (nRF52840) or 2x32 KB (nRF52833) RAM sections */
+  /* NOTE all RAM is aliased at address 0x0080_0000 for use as Code RAM */
+{}  RAM : ORIGIN = {}, LENGTH = {}K
+}}
+",
+        regions, ram_origin, ram_size,
+    )
+}
+
+// check that every handler referenced in `VECTORS` has a default definition in `interrupts.x`;
+// otherwise a firmware that doesn't define the handler fails to link with an opaque error
+fn check_vectors() -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-changed=src/reset.rs");
+    println!("cargo:rerun-if-changed=interrupts.x");
+
+    let reset = fs::read_to_string("src/reset.rs")?;
+    let linker_script = fs::read_to_string("interrupts.x")?;
+
+    let provided = linker_script
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim().strip_prefix("PROVIDE(")?;
+            Some(line.split('=').next()?.trim())
+        })
+        .collect::<Vec<_>>();
+
+    let vectors = reset
+        .split("static mut VECTORS")
+        .nth(1)
+        .ok_or("`VECTORS` not found in src/reset.rs")?;
+    let mut missing = vec![];
+    for part in vectors.split("handler:").skip(1) {
+        let handler = part
+            .trim_start()
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .next()
+            .unwrap_or("");
+
+        // `Reset` is defined in `src/reset.rs`
+        if handler != "Reset" && !provided.contains(&handler) {
+            missing.push(handler);
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(format!(
+            "the following handlers are referenced in `VECTORS` but have no default definition \
+             (`PROVIDE`) in interrupts.x: {}",
+            missing.join(", ")
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
 // generate USB descriptors
 fn descs(out_dir: &Path) -> Result<(), Box<dyn Error>> {
     use core::num::NonZeroU8;
 
-    use quote::quote;
+    use quote::{format_ident, quote};
     use usb2::{
         cdc::{self, acm, call, header, union},
         configuration::{self, bmAttributes},
@@ -33,43 +139,227 @@ fn descs(out_dir: &Path) -> Result<(), Box<dyn Error>> {
     };
 
     const PACKET_SIZE: bMaxPacketSize0 = bMaxPacketSize0::B64;
+    // `bConfigurationValue` of the first configuration; the others follow it
     const CONFIG_VAL: u8 = 1;
     const CDC_IFACE: u8 = 0;
-    const HID_IFACE: u8 = 2;
+    const VENDOR_CLASS: u8 = 0xFF;
+    // `bDescriptorType` of string descriptors
+    const STRING_DESCRIPTOR: u8 = 3;
+
+    // DFU 1.1 run-time interface
+    const DFU_CLASS: u8 = 0xFE;
+    const DFU_SUBCLASS: u8 = 1;
+    const DFU_PROTOCOL: u8 = 1;
+    const DFU_FUNCTIONAL: u8 = 0x21;
+    const DFU_DETACH_TIMEOUT: u16 = 1_000; // ms
+
+    // the USBD's only isochronous IN endpoint
+    const ISO_EP: u8 = 8;
+    // `bDescriptorType` of endpoint descriptors
+    const ENDPOINT_DESCRIPTOR: u8 = 5;
+
+    // maximum current draw from the bus, in mA, of each configuration; set with the
+    // `USB_MAX_POWER` env var, a comma-separated list with one entry per configuration
+    fn max_power() -> Vec<u16> {
+        println!("cargo:rerun-if-env-changed=USB_MAX_POWER");
+
+        let ma = env::var("USB_MAX_POWER")
+            .ok()
+            .map(|s| {
+                s.split(',')
+                    .map(|s| {
+                        s.trim()
+                            .parse::<u16>()
+                            .expect("USB_MAX_POWER must be a list of integer numbers of mA")
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_else(|| vec![500]);
+        assert!(
+            ma.iter().all(|ma| *ma <= 500),
+            "USB_MAX_POWER can't be larger than 500 (mA)"
+        );
+        assert!(
+            ma.len() <= usize::from(u8::max_value() - CONFIG_VAL) + 1,
+            "too many configurations"
+        );
+        ma
+    }
+
+    // whether the device has its own power supply; set with the `USB_SELF_POWERED` env var
+    fn self_powered() -> bool {
+        println!("cargo:rerun-if-env-changed=USB_SELF_POWERED");
+
+        env::var_os("USB_SELF_POWERED").is_some()
+    }
+
+    // number of back to back stalled control requests after which the USB peripheral is reset;
+    // set with the `USB_MAX_EP0_STALLS` env var; 0 disables the reset
+    fn max_ep0_stalls() -> u8 {
+        println!("cargo:rerun-if-env-changed=USB_MAX_EP0_STALLS");
+
+        env::var("USB_MAX_EP0_STALLS")
+            .ok()
+            .map(|s| {
+                s.parse::<u8>()
+                    .expect("USB_MAX_EP0_STALLS must be an integer in the range 0..=255")
+            })
+            .unwrap_or(16)
+    }
+
+    // polling interval of the CDC notification endpoint, in ms; set with the
+    // `USB_CDC_NOTIFY_INTERVAL` env var. Full-speed interrupt endpoints accept values in the range
+    // 1..=255
+    fn cdc_notify_interval() -> u8 {
+        println!("cargo:rerun-if-env-changed=USB_CDC_NOTIFY_INTERVAL");
+
+        let ms = env::var("USB_CDC_NOTIFY_INTERVAL")
+            .ok()
+            .map(|s| {
+                s.parse::<u8>()
+                    .expect("USB_CDC_NOTIFY_INTERVAL must be an integer in the range 1..=255")
+            })
+            .unwrap_or(32);
+        assert!(ms != 0, "USB_CDC_NOTIFY_INTERVAL can't be 0");
+        ms
+    }
+
+    // size of the isochronous IN buffers, in bytes; set with the `USB_ISO_MAX_PACKET_SIZE` env
+    // var. One such packet is sent every frame (1 ms)
+    fn iso_max_packet_size() -> u16 {
+        println!("cargo:rerun-if-env-changed=USB_ISO_MAX_PACKET_SIZE");
+
+        let size = env::var("USB_ISO_MAX_PACKET_SIZE")
+            .ok()
+            .map(|s| {
+                s.parse::<u16>()
+                    .expect("USB_ISO_MAX_PACKET_SIZE must be an integer number of bytes")
+            })
+            // 48 kHz, 16-bit stereo
+            .unwrap_or(192);
+        assert!(
+            size != 0 && size <= 1023,
+            "USB_ISO_MAX_PACKET_SIZE must be in the range 1..=1023"
+        );
+        size
+    }
+
+    fn cdc() -> bool {
+        env::var_os("CARGO_FEATURE_CLASS_CDC").is_some()
+    }
+
+    fn hid() -> bool {
+        env::var_os("CARGO_FEATURE_HID").is_some()
+    }
+
+    fn iso() -> bool {
+        env::var_os("CARGO_FEATURE_ISO").is_some()
+    }
+
+    // a vendor-specific interface is used when no class interface has been enabled
+    fn vendor() -> bool {
+        !cdc() && !hid()
+    }
+
+    // NOTE interface numbers must be contiguous and start at 0
+    // the CDC ACM function uses two interfaces: 0 and 1
+    fn hid_iface() -> u8 {
+        if cdc() {
+            2
+        } else {
+            0
+        }
+    }
+
+    // the isochronous interface goes after the HID or vendor-specific interface
+    fn iso_iface() -> u8 {
+        if hid() || vendor() {
+            hid_iface() + 1
+        } else {
+            hid_iface()
+        }
+    }
+
+    // the DFU interface goes after all the other interfaces
+    fn dfu_iface() -> u8 {
+        if iso() {
+            iso_iface() + 1
+        } else {
+            iso_iface()
+        }
+    }
+
+    // string descriptors; indices are assigned in order of insertion, starting at 1, because
+    // index 0 is reserved for the LANGID table
+    struct Strings {
+        // (name, string)
+        table: Vec<(&'static str, &'static str)>,
+    }
 
+    impl Strings {
+        fn add(&mut self, name: &'static str, string: &'static str) -> Option<NonZeroU8> {
+            assert!(
+                self.table.iter().all(|(n, _)| *n != name),
+                "string {} has been added twice",
+                name
+            );
+            self.table.push((name, string));
+            let index = self.table.len();
+            assert!(index <= 255, "too many string descriptors");
+            NonZeroU8::new(index as u8)
+        }
+    }
+
+    let mut strings = Strings { table: vec![] };
+
+    // IAD model
+    let (class, subclass, protocol) = if cdc() { (0xEF, 2, 1) } else { (0, 0, 0) };
     let device_desc = device::Descriptor {
-        // IAD model
-        bDeviceClass: 0xEF,
-        bDeviceSubClass: 2,
-        bDeviceProtocol: 1,
+        bDeviceClass: class,
+        bDeviceSubClass: subclass,
+        bDeviceProtocol: protocol,
 
         bMaxPacketSize0: bMaxPacketSize0::B64,
-        bNumConfigurations: NonZeroU8::new(1).unwrap(),
+        bNumConfigurations: NonZeroU8::new(max_power().len() as u8).unwrap(),
         bcdDevice: 0x01_00,
-        iManufacturer: None,
-        iProduct: None,
+        iManufacturer: strings.add("MANUFACTURER", "embedded2020"),
+        iProduct: strings.add("PRODUCT", "nRF52840 USB device"),
         iSerialNumber: None,
         idProduct: consts::PID,
         idVendor: consts::VID,
     };
 
-    fn full_config_desc() -> Vec<u8> {
-        let hid = env::var_os("CARGO_FEATURE_HID").is_some();
+    fn full_config_desc(strings: &mut Strings) -> Vec<u8> {
+        let cdc = cdc();
+        let hid = hid();
+        let vendor = vendor();
+        let iso = iso();
+        let dfu = env::var_os("CARGO_FEATURE_DFU").is_some();
 
         let mut bytes = vec![];
 
-        let mut nifaces = 2;
-        if hid {
+        let mut nifaces = 0;
+        if cdc {
+            nifaces += 2;
+        }
+        if hid || vendor {
+            nifaces += 1;
+        }
+        if iso {
+            nifaces += 1;
+        }
+        if dfu {
             nifaces += 1;
         }
 
         let config = configuration::Descriptor {
             bConfigurationValue: NonZeroU8::new(CONFIG_VAL).unwrap(),
-            bMaxPower: 250, // 500 mA
+            // in units of 2 mA; patched for the other configurations
+            bMaxPower: ((max_power()[0] + 1) / 2) as u8,
             bNumInterfaces: NonZeroU8::new(nifaces).unwrap(),
             bmAttributes: bmAttributes {
-                remote_wakeup: false,
-                self_powered: false,
+                remote_wakeup: env::var_os("CARGO_FEATURE_REMOTE_WAKEUP").is_some(),
+                self_powered: self_powered(),
             },
             iConfiguration: None,
             // NOTE this will be fixed at the end of this function
@@ -78,7 +368,7 @@ fn descs(out_dir: &Path) -> Result<(), Box<dyn Error>> {
 
         bytes.extend_from_slice(&config.bytes());
 
-        {
+        if cdc {
             let comm = cdc::Class::Communications {
                 subclass: cdc::SubClass::AbstractControlModel,
                 protocol: cdc::Protocol::ATCommands,
@@ -102,7 +392,7 @@ fn descs(out_dir: &Path) -> Result<(), Box<dyn Error>> {
                 bInterfaceSubClass: comm.subclass(),
                 bInterfaceProtocol: comm.protocol(),
                 bNumEndpoints: 1,
-                iInterface: None,
+                iInterface: strings.add("CDC_COMM", "CDC ACM control"),
             };
 
             bytes.extend_from_slice(&iface0.bytes());
@@ -144,7 +434,7 @@ fn descs(out_dir: &Path) -> Result<(), Box<dyn Error>> {
                     direction: Direction::In,
                     number: 1,
                 },
-                bInterval: 32, // ??
+                bInterval: cdc_notify_interval(),
                 ty: endpoint::Type::Interrupt {
                     transactions_per_microframe: endpoint::Transactions::_1,
                 },
@@ -154,7 +444,7 @@ fn descs(out_dir: &Path) -> Result<(), Box<dyn Error>> {
             bytes.extend_from_slice(&ep1in.bytes());
         }
 
-        {
+        if cdc {
             let cdc_data = cdc::Class::CdcData;
 
             let iface1 = interface::Descriptor {
@@ -164,7 +454,7 @@ fn descs(out_dir: &Path) -> Result<(), Box<dyn Error>> {
                 bInterfaceSubClass: cdc_data.subclass(),
                 bInterfaceProtocol: cdc_data.protocol(),
                 bNumEndpoints: 2,
-                iInterface: None,
+                iInterface: strings.add("CDC_DATA", "CDC ACM data"),
             };
 
             bytes.extend_from_slice(&iface1.bytes());
@@ -199,12 +489,12 @@ fn descs(out_dir: &Path) -> Result<(), Box<dyn Error>> {
 
             let iface2 = interface::Descriptor {
                 bAlternativeSetting: 0,
-                bInterfaceNumber: HID_IFACE,
+                bInterfaceNumber: hid_iface(),
                 bInterfaceClass: hid.class().get(),
                 bInterfaceSubClass: hid.subclass(),
                 bInterfaceProtocol: hid.protocol(),
                 bNumEndpoints: 2,
-                iInterface: None,
+                iInterface: strings.add("HID", "HID"),
             };
 
             bytes.extend_from_slice(&iface2.bytes());
@@ -245,6 +535,85 @@ fn descs(out_dir: &Path) -> Result<(), Box<dyn Error>> {
             bytes.extend_from_slice(&ep3in.bytes());
         }
 
+        if vendor {
+            let iface = interface::Descriptor {
+                bAlternativeSetting: 0,
+                bInterfaceNumber: 0,
+                bInterfaceClass: VENDOR_CLASS,
+                bInterfaceSubClass: 0,
+                bInterfaceProtocol: 0,
+                bNumEndpoints: 0,
+                iInterface: strings.add("VENDOR", "Vendor"),
+            };
+
+            bytes.extend_from_slice(&iface.bytes());
+        }
+
+        if iso {
+            // alternate setting 0 has no endpoints so the interface doesn't reserve any bus
+            // bandwidth until the host selects alternate setting 1
+            let iface = strings.add("ISO", "Isochronous");
+            for alt in 0..=1 {
+                let desc = interface::Descriptor {
+                    bAlternativeSetting: alt,
+                    bInterfaceNumber: iso_iface(),
+                    bInterfaceClass: VENDOR_CLASS,
+                    bInterfaceSubClass: 0,
+                    bInterfaceProtocol: 0,
+                    bNumEndpoints: alt,
+                    iInterface: iface,
+                };
+
+                bytes.extend_from_slice(&desc.bytes());
+            }
+
+            // isochronous, asynchronous, data endpoint
+            let bm_attributes = 0b0000_0101;
+            let size = iso_max_packet_size();
+            bytes.extend_from_slice(&[
+                7,
+                ENDPOINT_DESCRIPTOR,
+                1 << 7 | ISO_EP,
+                bm_attributes,
+                size as u8,
+                (size >> 8) as u8,
+                // one packet per frame
+                1,
+            ]);
+        }
+
+        if dfu {
+            let iface = interface::Descriptor {
+                bAlternativeSetting: 0,
+                bInterfaceNumber: dfu_iface(),
+                bInterfaceClass: DFU_CLASS,
+                bInterfaceSubClass: DFU_SUBCLASS,
+                bInterfaceProtocol: DFU_PROTOCOL,
+                bNumEndpoints: 0,
+                iInterface: strings.add("DFU", "DFU runtime"),
+            };
+
+            bytes.extend_from_slice(&iface.bytes());
+
+            // DFU functional descriptor
+            // bitWillDetach = 1: the device detaches itself from the bus
+            // bitCanDnload = 1
+            let bm_attributes = 0b1001;
+            let w_transfer_size = PACKET_SIZE as u16;
+            let bcd_dfu_version = 0x01_10u16;
+            bytes.extend_from_slice(&[
+                9,
+                DFU_FUNCTIONAL,
+                bm_attributes,
+                DFU_DETACH_TIMEOUT as u8,
+                (DFU_DETACH_TIMEOUT >> 8) as u8,
+                w_transfer_size as u8,
+                (w_transfer_size >> 8) as u8,
+                bcd_dfu_version as u8,
+                (bcd_dfu_version >> 8) as u8,
+            ]);
+        }
+
         let total_length = bytes.len();
         assert!(
             total_length <= usize::from(u16::max_value()),
@@ -282,23 +651,142 @@ fn descs(out_dir: &Path) -> Result<(), Box<dyn Error>> {
     let ssl = ssb.len();
     let ddb = device_desc.bytes();
     let ddl = ddb.len();
-    let cdb = full_config_desc();
+    let cdb = full_config_desc(&mut strings);
     let cdl = cdb.len();
+    // `wMaxPacketSize` of each endpoint, as declared in the endpoint descriptors; 0 means the
+    // endpoint is not used. The isochronous endpoint (8) is not included; its size is
+    // `ISO_MAX_PACKET_SIZE`
+    let mut epin_mps = [0u16; 8];
+    let mut epout_mps = [0u16; 8];
+    epin_mps[0] = u16::from(max_packet_size0);
+    epout_mps[0] = u16::from(max_packet_size0);
+    let mut rest = &cdb[..];
+    while !rest.is_empty() {
+        let (len, ty) = (usize::from(rest[0]), rest[1]);
+        if ty == ENDPOINT_DESCRIPTOR && rest[2] & 0xf != ISO_EP {
+            let addr = rest[2];
+            // bits 10:0 of `wMaxPacketSize`
+            let mps = u16::from_le_bytes([rest[4], rest[5]]) & 0x7ff;
+            let table = if addr & 0x80 != 0 {
+                &mut epin_mps
+            } else {
+                &mut epout_mps
+            };
+            table[usize::from(addr & 0xf)] = mps;
+        }
+        rest = &rest[len..];
+    }
+    // all the configurations expose the same interfaces and endpoints; they only differ in
+    // `bConfigurationValue` and `bMaxPower`
+    let mut config_descs = vec![];
+    let mut configs = vec![];
+    // only the endpoints declared in the configuration descriptor are enabled; this includes EP0.
+    // The isochronous endpoint is enabled by SET_INTERFACE
+    let enabled = |mps: &[u16; 8]| {
+        mps.iter()
+            .enumerate()
+            .filter(|(_, mps)| **mps != 0)
+            .fold(0u32, |mask, (i, _)| mask | 1 << i)
+    };
+    let (epinen, epouten) = (enabled(&epin_mps), enabled(&epout_mps));
+    for (i, ma) in max_power().into_iter().enumerate() {
+        let value = CONFIG_VAL + i as u8;
+        let mut desc = cdb.clone();
+        // offset of `bConfigurationValue`
+        desc[5] = value;
+        // offset of `bMaxPower`, in units of 2 mA
+        desc[8] = ((ma + 1) / 2) as u8;
+        config_descs.push(quote!([#(#desc,)*]));
+        configs.push(quote!(control::Config {
+            value: unsafe { core::num::NonZeroU8::new_unchecked(#value) },
+            epinen: #epinen,
+            epouten: #epouten,
+        }));
+    }
+    let nconfigs = configs.len();
+    let config_indices = 0..nconfigs;
+    // offset of `bNumInterfaces` in the configuration descriptor
+    let nifaces = usize::from(cdb[4]);
+    // only the isochronous interface has alternate settings
+    let mut max_alt_setting = vec![0u8; nifaces];
+    let iso_iface = iso_iface();
+    if iso() {
+        max_alt_setting[usize::from(iso_iface)] = 1;
+    }
+    let iso_max_packet_size = iso_max_packet_size();
+    let hid_iface = hid_iface();
+    let dfu_iface = dfu_iface();
+    let self_powered = self_powered();
+    let max_ep0_stalls = max_ep0_stalls();
+    let cdc_notify_interval = u32::from(cdc_notify_interval());
+
+    // all the string descriptors, back to back, starting with the LANGID table (US English)
+    let mut sdb = vec![4, STRING_DESCRIPTOR, 0x09, 0x04];
+    // offset of each string descriptor into `sdb`
+    let mut sdo = vec![0u16];
+    let mut string_consts = vec![];
+    for (i, (name, string)) in strings.table.iter().enumerate() {
+        let utf16 = string.encode_utf16().collect::<Vec<_>>();
+        let len = 2 + 2 * utf16.len();
+        assert!(len <= 255, "string {} is too long", name);
+
+        sdo.push(sdb.len() as u16);
+        sdb.push(len as u8);
+        sdb.push(STRING_DESCRIPTOR);
+        for unit in utf16 {
+            sdb.extend_from_slice(&unit.to_le_bytes());
+        }
+
+        let name = format_ident!("{}_STRING", name);
+        let index = i as u8 + 1;
+        string_consts.push(quote!(
+            #[allow(dead_code)]
+            const #name: u8 = #index;
+        ));
+    }
+    sdo.push(sdb.len() as u16);
+    // string descriptor 0, requested by hosts during enumeration, must be the LANGID table: a
+    // 4-byte STRING descriptor that lists US English (0x0409)
+    assert!(
+        sdo[..2] == [0, 4] && sdb[..4] == [4, STRING_DESCRIPTOR, 0x09, 0x04],
+        "string descriptor 0 must be the LANGID table [4, {}, 0x09, 0x04] (US English) but it's {:?}",
+        STRING_DESCRIPTOR,
+        &sdb[..usize::from(sdo[1])],
+    );
+    let sdl = sdb.len();
+    let sdol = sdo.len();
     fs::write(
         out_dir.join("descs.rs"),
         quote!(
-            const CONFIG_VAL: core::num::NonZeroU8 = unsafe {
-                core::num::NonZeroU8::new_unchecked(#CONFIG_VAL)
-            };
+            // the first entry is the default configuration, the one most hosts select
+            static CONFIGS: [control::Config; #nconfigs] = [#(#configs,)*];
             const MAX_PACKET_SIZE0: u8 = #max_packet_size0;
+            // `wMaxPacketSize` of IN / OUT endpoints 0 to 7; must match the endpoint descriptors
+            #[allow(dead_code)]
+            const EPIN_MAX_PACKET_SIZE: [u16; 8] = [#(#epin_mps,)*];
+            #[allow(dead_code)]
+            const EPOUT_MAX_PACKET_SIZE: [u16; 8] = [#(#epout_mps,)*];
+            // NOTE all the data served through EasyDMA must live in RAM so these are placed in
+            // `.data`, at the cost of `CONFIG_DESCS.len() + DEVICE_DESC.len()` bytes of RAM (plus
+            // `STRING_DESCS.len()`; see below)
+            // NOTE the ith entry is the descriptor of `CONFIGS[i]`
             #[allow(dead_code)]
-            #[link_section = ".data.CONFIG_DESC"]
-            static CONFIG_DESC: [u8; #cdl] = [#(#cdb,)*];
+            #[link_section = ".data.CONFIG_DESCS"]
+            static CONFIG_DESCS: [[u8; #cdl]; #nconfigs] = [#(#config_descs,)*];
+            // `CONFIG_DESCS` as slices, for `control::Descriptors`
+            static CONFIG_DESC_SLICES: [&[u8]; #nconfigs] = [#(&CONFIG_DESCS[#config_indices],)*];
 
             #[allow(dead_code)]
             #[link_section = ".data.DEVICE_DESC"]
             static DEVICE_DESC: [u8; #ddl] = [#(#ddb,)*];
 
+            // NOTE these live in RAM as well; the ith string descriptor is
+            // `STRING_DESCS[STRING_OFFSETS[i]..STRING_OFFSETS[i + 1]]`
+            #[link_section = ".data.STRING_DESCS"]
+            static STRING_DESCS: [u8; #sdl] = [#(#sdb,)*];
+            const STRING_OFFSETS: [u16; #sdol] = [#(#sdo,)*];
+            #(#string_consts)*
+
             #[allow(dead_code)]
             static mut LINE_CODING: [u8; #lcl] = [#(#lcb,)*];
 
@@ -306,10 +794,29 @@ fn descs(out_dir: &Path) -> Result<(), Box<dyn Error>> {
             #[link_section = ".data.SERIAL_STATE"]
             static SERIAL_STATE: crate::util::Align4<[u8; #ssl]> = crate::util::Align4([#(#ssb,)*]);
 
+            // must match `bmAttributes` in the configuration descriptor
+            const SELF_POWERED: bool = #self_powered;
+
+            const MAX_EP0_STALLS: u8 = #max_ep0_stalls;
+
+            // must match `bInterval` of the CDC notification endpoint; in ms
+            #[allow(dead_code)]
+            const CDC_NOTIFY_INTERVAL: u32 = #cdc_notify_interval;
+
+            const NIFACES: usize = #nifaces;
+            // the highest alternate setting of each interface
+            const MAX_ALT_SETTING: [u8; #nifaces] = [#(#max_alt_setting,)*];
+
             #[allow(dead_code)]
             const CDC_IFACE: u8 = #CDC_IFACE;
             #[allow(dead_code)]
-            const HID_IFACE: u8 = #HID_IFACE;
+            const HID_IFACE: u8 = #hid_iface;
+            #[allow(dead_code)]
+            const ISO_IFACE: u8 = #iso_iface;
+            #[allow(dead_code)]
+            const ISO_MAX_PACKET_SIZE: u16 = #iso_max_packet_size;
+            #[allow(dead_code)]
+            const DFU_IFACE: u8 = #dfu_iface;
         )
         .to_string(),
     )?;
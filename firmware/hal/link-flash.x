@@ -1,13 +1,5 @@
-MEMORY
-{
-  /* NOTE RAM is split as follows */
-  /* - 8 AHB slaves, each connected to a 2x4 KB RAM sections */
-  /* - the 9th AHB slave is connected to 6x32 KB RAM sections */
-  /* NOTE all RAM is aliased at address 0x0080_0000 for use as Code RAM */
-  /* FLASH : ORIGIN = 0x1000, LENGTH = 0x7F000 */
-  FLASH : ORIGIN = 0, LENGTH = 0x7F000
-  RAM : ORIGIN = 0x20000008, LENGTH = 256K
-}
+/* `MEMORY`; generated by `build.rs` for the selected device */
+INCLUDE memory.x
 
 ENTRY(Reset);
 PROVIDE(__stack_top__ = ORIGIN(RAM) + LENGTH(RAM));
@@ -63,6 +55,9 @@ SECTIONS
 
   _sidata = LOADADDR(.data);
 
+  /* the stack grows downwards, from `__stack_top__`, towards the end of the static variables */
+  __stack_bottom__ = ADDR(.data) + SIZEOF(.data);
+
   .binfmt (INFO) :
   {
     *(.binfmt.*);
@@ -14,7 +14,7 @@ mod task {
 
     fn init() {
         CLOCK::borrow_unchecked(|clock| {
-            clock.TASKS_HFCLKSTART.write(|w| w.TASKS_HFCLKSTART(1));
+            clock.TASKS_HFCLKSTART.trigger();
             semidap::info!("started HFXO");
 
             unsafe { clock.INTENSET.write(|w| w.HFCLKSTARTED(1)) }
@@ -0,0 +1,28 @@
+//! `critical-section` implementation
+
+use core::sync::atomic::{self, Ordering};
+
+use critical_section::RawRestoreState;
+
+struct Primask;
+
+critical_section::set_impl!(Primask);
+
+unsafe impl critical_section::Impl for Primask {
+    unsafe fn acquire() -> RawRestoreState {
+        // NOTE critical sections can be nested so we must remember whether interrupts were
+        // already masked on entry
+        let masked = asm::primask() & 1 != 0;
+        asm::disable_irq();
+        atomic::compiler_fence(Ordering::SeqCst);
+        masked
+    }
+
+    unsafe fn release(masked: RawRestoreState) {
+        atomic::compiler_fence(Ordering::SeqCst);
+        // only the outermost critical section unmasks interrupts
+        if !masked {
+            asm::enable_irq();
+        }
+    }
+}
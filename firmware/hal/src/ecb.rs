@@ -0,0 +1,102 @@
+//! AES-128 block encryption
+//!
+//! The ECB peripheral encrypts a single 16-byte block at a time; it does no chaining, and can't
+//! decrypt, so any block cipher mode (e.g. CTR or CCM) must be implemented on top of
+//! `Ecb::encrypt_block`
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use binfmt::derive::binDebug;
+use pac::ECB;
+
+use crate::NotSendOrSync;
+
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Error returned when the encryption was aborted
+///
+/// This happens when the CCM or AAR peripheral, which have higher priority on the AES core, start
+/// an operation while the ECB is in use; the operation can be retried
+#[derive(Clone, Copy, PartialEq, binDebug)]
+pub struct Aborted;
+
+// the layout the hardware expects; see the `ECBDATAPTR` register
+// NOTE must live in RAM, like every EasyDMA buffer
+#[repr(C, align(4))]
+struct EcbData {
+    key: [u8; 16],
+    cleartext: [u8; 16],
+    ciphertext: [u8; 16],
+}
+
+/// Claims the ECB peripheral
+///
+/// # Panics
+///
+/// This function panics if the peripheral has already been claimed
+pub fn claim() -> Ecb {
+    if TAKEN
+        .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+        .is_ok()
+    {
+        // reserve the peripheral for HAL use
+        ECB::seal();
+
+        Ecb {
+            _not_send_or_sync: NotSendOrSync::new(),
+        }
+    } else {
+        semidap::panic!("`ecb` peripheral has already been claimed")
+    }
+}
+
+/// AES-128 block encryption engine
+pub struct Ecb {
+    _not_send_or_sync: NotSendOrSync,
+}
+
+impl Ecb {
+    /// Encrypts `block` in place using `key`
+    ///
+    /// Both the key and the block are in big endian order: the first byte is the most significant
+    /// one. The operation takes a few microseconds and busy waits until it's done
+    pub fn encrypt_block(&mut self, key: &[u8; 16], block: &mut [u8; 16]) -> Result<(), Aborted> {
+        let mut data = EcbData {
+            key: *key,
+            cleartext: *block,
+            ciphertext: [0; 16],
+        };
+
+        let res = ECB::borrow_unchecked(|ecb| {
+            ecb.EVENTS_ENDECB.zero();
+            ecb.EVENTS_ERRORECB.zero();
+
+            unsafe {
+                ecb.ECBDATAPTR
+                    .write(|w| w.ECBDATAPTR(&mut data as *mut EcbData as u32));
+                crate::dma_start();
+                ecb.TASKS_STARTECB.trigger();
+            }
+
+            let res = loop {
+                if ecb.EVENTS_ENDECB.read().bits() != 0 {
+                    ecb.EVENTS_ENDECB.zero();
+                    break Ok(());
+                }
+
+                if ecb.EVENTS_ERRORECB.read().bits() != 0 {
+                    ecb.EVENTS_ERRORECB.zero();
+                    break Err(Aborted);
+                }
+            };
+            crate::dma_end();
+            res
+        });
+
+        if res.is_ok() {
+            *block = data.ciphertext;
+        }
+
+        res
+    }
+}
@@ -0,0 +1,61 @@
+//! Standard bring-up sequences of peripherals
+//!
+//! Some peripherals must be configured in a specific order, or need errata workarounds, before
+//! they can be used. `InitSequence` captures those steps so bring-up code can call, e.g.,
+//! `USBD::init_sequence()` instead of re-deriving them.
+//!
+//! These peripherals provide a sequence:
+//!
+//! - `CLOCK`: selects the external crystal (LFXO) as the low-frequency clock source and starts it
+//! - `RTC0`: clears the counter and starts it
+//! - `USBD` (requires the `usb` feature): applies the workaround for errata 187 and enables the
+//!   peripheral
+//!
+//! `Reset` runs the `CLOCK` and `RTC0` sequences before `main`; the `usbd` module runs the `USBD`
+//! sequence when the cable is attached. Applications only need these when they bring up a
+//! peripheral without going through the HAL
+
+use pac::{CLOCK, RTC0};
+
+#[cfg(feature = "usb")]
+use pac::USBD;
+
+/// A peripheral with a standard bring-up sequence
+pub trait InitSequence {
+    /// Runs the bring-up sequence of the peripheral
+    ///
+    /// # Safety
+    /// The sequence reconfigures the peripheral from scratch. The caller must ensure that the
+    /// peripheral is not in use, either by the application or by the HAL
+    unsafe fn init_sequence();
+}
+
+impl InitSequence for CLOCK {
+    unsafe fn init_sequence() {
+        CLOCK::borrow_unchecked(|clock| {
+            // use the external crystal (LFXO) as the low-frequency clock (LFCLK) source
+            clock.LFCLKSRC.write(|w| w.SRC(1));
+
+            // start the LFXO
+            clock.TASKS_LFCLKSTART.trigger();
+        });
+    }
+}
+
+impl InitSequence for RTC0 {
+    unsafe fn init_sequence() {
+        // start the RTC with a counter of 0
+        RTC0::borrow_unchecked(|rtc| {
+            rtc.TASKS_CLEAR.trigger();
+            rtc.TASKS_START.trigger();
+        });
+    }
+}
+
+#[cfg(feature = "usb")]
+impl InitSequence for USBD {
+    unsafe fn init_sequence() {
+        crate::errata::e187a();
+        USBD::borrow_unchecked(|usbd| usbd.ENABLE.write(|w| w.ENABLE(1)));
+    }
+}
@@ -0,0 +1,112 @@
+//! Panic handler that falls back to ITM / SWO
+//!
+//! The panic is first reported through `semidap`, which only reaches the host when the `semidap`
+//! tool is attached. The panic message is then written to stimulus port 0 of the Instrumentation
+//! Trace Macrocell (ITM) so that any SWO-capable debug probe can capture it.
+//!
+//! The handler sets up the trace hardware itself, right before writing the message:
+//!
+//! - the trace port of the nRF52840 (`CLOCK.TRACECONFIG`) is put in serial mode, which outputs
+//!   SWO on pin P1.00, with a trace clock of 32 MHz
+//! - the TPIU is configured as a 1-bit port with NRZ (UART) encoding, no formatter and a baud rate
+//!   of `SWO_BAUD`
+//! - the ITM and its stimulus port 0 are enabled
+//!
+//! The probe must capture SWO in UART / NRZ mode at `SWO_BAUD` bits per second. The message is
+//! followed by a newline. After writing it the handler stops the program like `panic-abort` does
+//!
+//! NOTE the `panic-capture` feature installs a different panic handler; only one of the two can be
+//! enabled
+
+use core::{
+    fmt::{self, Write as _},
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+#[cfg(feature = "panic-capture")]
+compile_error!("the `itm-panic` and `panic-capture` features can't be enabled at the same time");
+
+/// Baud rate of the SWO output, in bits per second
+pub const SWO_BAUD: u32 = 1_000_000;
+
+// frequency of the trace clock selected by `TRACECONFIG_SERIAL`
+const TRACECLK: u32 = 32_000_000;
+
+// NOTE the ITM and TPIU are not part of the `cm` crate
+const TRACECONFIG: *mut u32 = 0x4000_055C as *mut u32;
+// TRACEMUX = Serial; TRACEPORTSPEED = 32 MHz
+const TRACECONFIG_SERIAL: u32 = 1 << 16;
+
+const TPIU_CSPSR: *mut u32 = 0xE004_0004 as *mut u32;
+const TPIU_ACPR: *mut u32 = 0xE004_0010 as *mut u32;
+const TPIU_SPPR: *mut u32 = 0xE004_00F0 as *mut u32;
+const TPIU_FFCR: *mut u32 = 0xE004_0304 as *mut u32;
+// NRZ (UART) encoding
+const SPPR_NRZ: u32 = 2;
+// TrigIn = 1; EnFCont = 0 (formatter bypassed)
+const FFCR_BYPASS: u32 = 1 << 8;
+
+const ITM_STIM0: *mut u32 = 0xE000_0000 as *mut u32;
+const ITM_TER: *mut u32 = 0xE000_0E00 as *mut u32;
+const ITM_TCR: *mut u32 = 0xE000_0E80 as *mut u32;
+const ITM_LAR: *mut u32 = 0xE000_0FB0 as *mut u32;
+const LAR_UNLOCK: u32 = 0xC5AC_CE55;
+// ITMENA = 1; TraceBusID = 1
+const TCR_ENABLE: u32 = (1 << 16) | 1;
+
+// how many times to poll the stimulus port before giving up on a byte
+const MAX_SPINS: u32 = 100_000;
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    static PANICKING: AtomicBool = AtomicBool::new(false);
+
+    asm::disable_irq();
+
+    // formatting `info` runs user code; if that panics go straight to the end
+    if !PANICKING.swap(true, Ordering::Relaxed) {
+        semidap::error!("panicked; the message is on ITM stimulus port 0");
+
+        unsafe { enable() }
+        let _ = writeln!(Stim0, "{}", info);
+    }
+
+    semidap::abort()
+}
+
+// NOTE the `DEMCR.TRCENA` bit, which powers the ITM and TPIU, is set by `Reset`
+unsafe fn enable() {
+    TRACECONFIG.write_volatile(TRACECONFIG_SERIAL);
+
+    TPIU_CSPSR.write_volatile(1);
+    TPIU_ACPR.write_volatile(TRACECLK / SWO_BAUD - 1);
+    TPIU_SPPR.write_volatile(SPPR_NRZ);
+    TPIU_FFCR.write_volatile(FFCR_BYPASS);
+
+    ITM_LAR.write_volatile(LAR_UNLOCK);
+    ITM_TCR.write_volatile(TCR_ENABLE);
+    ITM_TER.write_volatile(1);
+}
+
+// ITM stimulus port 0
+struct Stim0;
+
+impl fmt::Write for Stim0 {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            // the FIFO may never drain, e.g. if the trace clock is not running; don't hang
+            let mut spins = 0;
+            while unsafe { ITM_STIM0.read_volatile() } & 1 == 0 {
+                spins += 1;
+                if spins == MAX_SPINS {
+                    return Err(fmt::Error);
+                }
+            }
+
+            unsafe { (ITM_STIM0 as *mut u8).write_volatile(byte) }
+        }
+
+        Ok(())
+    }
+}
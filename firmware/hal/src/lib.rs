@@ -12,19 +12,52 @@ use core::{
     task::{Context, Poll},
 };
 
-use cm::{DWT, NVIC};
+use cm::{DWT, NVIC, SCB};
 use pac::FICR;
 
+// `{}` formats the given types using their `uDebug` implementation, in `text` mode; in `binfmt`
+// mode `{}` uses `binDebug`
+#[cfg(feature = "text")]
+macro_rules! display {
+    ($($ty:ident),+) => {
+        $(
+            impl ufmt::uDisplay for $ty {
+                fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+                where
+                    W: ufmt::uWrite + ?Sized,
+                {
+                    ufmt::uDebug::fmt(self, f)
+                }
+            }
+        )+
+    };
+}
+
+#[cfg(not(feature = "text"))]
+macro_rules! display {
+    ($($ty:ident),+) => {};
+}
+
 #[cfg(any(feature = "radio", feature = "usb"))]
 #[macro_use]
 mod atomic;
 
 #[cfg(any(feature = "radio", feature = "usb"))]
 mod clock;
+#[cfg(feature = "critical-section")]
+mod cs;
+#[cfg(feature = "ecb")]
+pub mod ecb;
 mod errata;
+pub mod init;
+#[cfg(feature = "itm-panic")]
+pub mod itm;
 pub mod led;
 mod mem;
 pub mod p0;
+pub mod power;
+#[cfg(feature = "ppi")]
+pub mod ppi;
 #[cfg(feature = "radio")]
 pub mod radio;
 mod reset;
@@ -41,6 +74,42 @@ pub fn cyccnt() -> u32 {
     DWT::borrow_unchecked(|dwt| dwt.CYCCNT.read())
 }
 
+/// Busy waits until the `bit` returned by the closure is set (`1`)
+///
+/// `bit` is meant to be a volatile read of a register field, e.g. `||
+/// clock.EVENTS_HFCLKSTARTED.read().EVENTS_HFCLKSTARTED()`. If a `timeout`, in CPU cycles (see
+/// `cyccnt`), is given the wait is aborted with an error once it has elapsed
+pub fn spin_until_set(
+    mut bit: impl FnMut() -> u8,
+    timeout: Option<u32>,
+) -> Result<(), time::Timeout> {
+    spin_until(|| bit() != 0, timeout)
+}
+
+/// Busy waits until the `bit` returned by the closure is clear (`0`)
+///
+/// See `spin_until_set` for details
+pub fn spin_until_clear(
+    mut bit: impl FnMut() -> u8,
+    timeout: Option<u32>,
+) -> Result<(), time::Timeout> {
+    spin_until(|| bit() == 0, timeout)
+}
+
+fn spin_until(mut done: impl FnMut() -> bool, timeout: Option<u32>) -> Result<(), time::Timeout> {
+    let start = cyccnt();
+    while !done() {
+        if let Some(timeout) = timeout {
+            // NOTE(wrapping_sub) the cycle counter overflows every ~67 seconds at 64 MHz
+            if cyccnt().wrapping_sub(start) >= timeout {
+                return Err(time::Timeout);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Returns the device identifier
 pub fn deviceid() -> u64 {
     u64::from(deviceid0()) | u64::from(deviceid1()) << 32
@@ -58,6 +127,74 @@ pub fn deviceid1() -> u32 {
     FICR::borrow_unchecked(|ficr| ficr.DEVICEID1.read().bits())
 }
 
+#[cfg(feature = "stack-usage")]
+pub use reset::stack_high_water;
+#[cfg(feature = "panic-capture")]
+pub use reset::{last_panic, PANIC_MESSAGE_SIZE};
+
+/// Resets the device
+///
+/// To reset only a peripheral see the peripheral-level resets; these are currently provided by
+/// the USB device (`usbd::reset`)
+pub fn reset() -> ! {
+    // NOTE(borrow_unchecked) single-instruction write
+    SCB::borrow_unchecked(|scb| scb.AIRCR.write(|w| w.VECTKEY(0x05FA).SYSRESETREQ(1)));
+
+    // the reset is not instantaneous
+    loop {
+        continue;
+    }
+}
+
+/// Hexadecimal view into a byte slice, e.g. `usbd::Packet::hex`
+///
+/// Formats as lowercase, space separated, bytes, e.g. `00 1f a0`
+#[cfg(feature = "text")]
+pub struct Hex<'a> {
+    bytes: &'a [u8],
+}
+
+#[cfg(feature = "text")]
+impl<'a> Hex<'a> {
+    /// Wraps the given `bytes`
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Hex { bytes }
+    }
+}
+
+#[cfg(feature = "text")]
+impl semidap::ufmt::uDisplay for Hex<'_> {
+    fn fmt<W>(&self, f: &mut semidap::ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: semidap::ufmt::uWrite + ?Sized,
+    {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+        for (i, byte) in self.bytes.iter().enumerate() {
+            let buf = [
+                b' ',
+                DIGITS[usize::from(byte >> 4)],
+                DIGITS[usize::from(byte & 0xf)],
+            ];
+            let start = if i == 0 { 1 } else { 0 };
+            // NOTE(unsafe) `buf` only contains ASCII characters
+            f.write_str(unsafe { core::str::from_utf8_unchecked(&buf[start..]) })?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "text")]
+impl semidap::ufmt::uDebug for Hex<'_> {
+    fn fmt<W>(&self, f: &mut semidap::ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: semidap::ufmt::uWrite + ?Sized,
+    {
+        semidap::ufmt::uDisplay::fmt(self, f)
+    }
+}
+
 struct NotSync {
     inner: PhantomData<*mut ()>,
 }
@@ -82,9 +219,27 @@ impl NotSendOrSync {
     }
 }
 
+// Memory ordering and peripherals
+//
+// Register accesses (`read`, `write`, `rmw`, etc.) are volatile operations; the compiler never
+// reorders volatile operations with respect to each other and the Cortex-M4 core performs them in
+// program order so e.g. writing `EPIN3_PTR` and then `TASKS_STARTEPIN3` needs no barrier. What's
+// *not* ordered are *normal* memory operations (e.g. filling a buffer) with respect to volatile
+// ones; that's what `dma_start` and `dma_end` are for
+//
+// A write to a peripheral may still be in flight when the next instruction executes. Only
+// `trigger` and `clear_all_events` end with a DSB, which waits for the write to complete; the other
+// generated register methods include no barrier. This matters when an interrupt handler clears an
+// event: if the write has not completed when the handler returns the interrupt fires again.
+// Handlers that read a register of the same peripheral after clearing the event are safe because
+// the read can't complete before the preceding write; e.g. the `Event::next` loops, which run until
+// no event is left. Handlers that return right after clearing an event need an explicit `asm::dsb`
+
 // NOTE must be followed by a volatile STORE operation
 fn dma_start() {
-    sync::atomic::compiler_fence(Ordering::Release)
+    sync::atomic::compiler_fence(Ordering::Release);
+    // ensure all the buffer writes have completed before EasyDMA is started
+    asm::dsb();
 }
 
 // NOTE must be preced by a volatile LOAD operation
@@ -92,6 +247,9 @@ fn dma_end() {
     sync::atomic::compiler_fence(Ordering::Acquire)
 }
 
+// Drivers wait on interrupt-driven state machines with this: the interrupt handler updates some
+// shared state and the closure checks it. No explicit wake-up is needed because returning from an
+// interrupt handler wakes up the executor (see `executor::wake`)
 #[allow(dead_code)]
 async fn poll_fn<T, F>(f: F) -> T
 where
@@ -139,6 +297,11 @@ unsafe fn atomic1<T>(interrupt: Interrupt1, f: impl FnOnce() -> T) -> T {
     r
 }
 
+#[allow(dead_code)]
+fn pend0(interrupt: Interrupt0) {
+    NVIC::borrow_unchecked(|nvic| nvic.ISPR0.write(1 << interrupt as u8));
+}
+
 #[allow(dead_code)]
 fn pend1(interrupt: Interrupt1) {
     NVIC::borrow_unchecked(|nvic| nvic.ISPR1.write(1 << (interrupt as u8 - 32)));
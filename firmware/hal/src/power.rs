@@ -0,0 +1,86 @@
+//! Power supply
+//!
+//! The nRF52840 has two regulator stages: REG0, which is only used when the chip is supplied
+//! through the VDDH pin (e.g. from VBUS), converts VDDH down to VDD; REG1 converts VDD down to the
+//! core voltage. Each stage can use either a LDO regulator (default) or a more efficient DC/DC
+//! converter; the DC/DC converters need external inductors so they are only enabled when the
+//! `dcdc` (REG1) and `dcdc0` (REG0) Cargo features are set. `init` applies this configuration
+//! right after reset, before any GPIO is used.
+//!
+//! # REG0 output voltage
+//!
+//! In high voltage mode (supply on VDDH) REG0 outputs 1.8 V by default, which may be too low to
+//! drive e.g. the LEDs of some boards. The output voltage is *not* a POWER register: it's stored
+//! in the `REGOUT0` register of the UICR, which lives in non-volatile memory. Changing it requires
+//! erasing / writing Flash through the NVMC and takes effect after the next reset; this module only
+//! reports its value (see `regout0`)
+
+use pac::POWER;
+
+/// Output voltage of REG0
+#[derive(Clone, Copy, PartialEq)]
+pub enum Regout0 {
+    /// 1.8 V
+    V1_8,
+    /// 2.1 V
+    V2_1,
+    /// 2.4 V
+    V2_4,
+    /// 2.7 V
+    V2_7,
+    /// 3.0 V
+    V3_0,
+    /// 3.3 V
+    V3_3,
+    /// Not configured; same as 1.8 V
+    Default,
+}
+
+/// Main supply
+#[derive(Clone, Copy, PartialEq)]
+pub enum Supply {
+    /// The chip is supplied through the VDD pin; REG0 is not in use
+    Normal,
+    /// The chip is supplied through the VDDH pin; REG0 is in use
+    High,
+}
+
+// configures the regulators; called before any GPIO is used
+pub(crate) fn init() {
+    // NOTE(borrow_unchecked) runs before `main` with interrupts disabled
+    POWER::borrow_unchecked(|power| {
+        if cfg!(feature = "dcdc") {
+            power.DCDCEN.write(|w| w.DCDCEN(1));
+        }
+
+        if cfg!(feature = "dcdc0") {
+            power.DCDCEN0.write(|w| w.DCDCEN(1));
+        }
+    });
+}
+
+/// Returns the main supply the chip is running from
+pub fn supply() -> Supply {
+    // NOTE(borrow_unchecked) single-instruction read with no side effects
+    if POWER::borrow_unchecked(|power| power.MAINREGSTATUS.read().MAINREGSTATUS()) == 0 {
+        Supply::Normal
+    } else {
+        Supply::High
+    }
+}
+
+/// Returns the REG0 output voltage stored in the UICR
+pub fn regout0() -> Regout0 {
+    const UICR_REGOUT0: *const u32 = 0x1000_1304 as *const u32;
+
+    // NOTE(unsafe) read-only register with no side effects
+    match unsafe { UICR_REGOUT0.read_volatile() } & 0b111 {
+        0 => Regout0::V1_8,
+        1 => Regout0::V2_1,
+        2 => Regout0::V2_4,
+        3 => Regout0::V2_7,
+        4 => Regout0::V3_0,
+        5 => Regout0::V3_3,
+        _ => Regout0::Default,
+    }
+}
@@ -0,0 +1,143 @@
+//! Programmable Peripheral Interconnect
+//!
+//! The PPI lets a peripheral event trigger a task of another (or the same) peripheral in hardware,
+//! without CPU involvement and with a fixed latency of one 16 MHz clock cycle. For example, a
+//! `TIMER` compare event can start a `RADIO` transmission, or an RTC tick can sample an input pin
+//! through the `GPIOTE`.
+//!
+//! # PPI vs DPPI
+//!
+//! The nRF52 family, including the nRF52840, has a PPI: each channel has a single event end point
+//! (EEP) and task end point (TEP) (plus an optional fork TEP) that hold the *addresses* of the
+//! event and task registers. The nRF53 and nRF91 families replace it with the DPPI, where events
+//! *publish* to and tasks *subscribe* to a channel number through per-register `PUBLISH_*` and
+//! `SUBSCRIBE_*` registers, and a channel can fan out to any number of tasks. This module only
+//! supports the PPI.
+//!
+//! # Channel allocation
+//!
+//! The nRF52840 has 32 PPI channels. Channels 0 to 19 are programmable and can be claimed with
+//! `claim`; channels 20 to 31 are pre-programmed by the hardware (e.g. `TIMER0.EVENTS_COMPARE[0]`
+//! -> `RADIO.TASKS_TXEN`) and are not exposed here. The HAL itself uses none of the programmable
+//! channels so all 20 are available to the application; each can be claimed only once at a time
+//! and is released, and disabled, when its `Channel` handle is dropped.
+//!
+//! # Example
+//!
+//! ``` ignore
+//! // toggle a GPIOTE-controlled pin on every RTC tick
+//! let mut ch = ppi::claim(0);
+//! GPIOTE::borrow_unchecked(|gpiote| {
+//!     RTC0::borrow_unchecked(|rtc| ch.connect(&rtc.EVENTS_TICK, &gpiote.TASKS_OUT0))
+//! });
+//! ch.enable();
+//! ```
+//!
+//! NOTE some peripherals, like the RTC, only route an event to the PPI when the event is enabled
+//! in their `EVTEN` register
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use pac::{Event, SafeTask, Task};
+
+use crate::NotSendOrSync;
+
+/// Number of programmable PPI channels
+pub const CHANNELS: u8 = 20;
+
+// bitmask of the claimed channels
+static TAKEN: AtomicU32 = AtomicU32::new(0);
+
+/// Claims the programmable PPI channel `n`
+///
+/// The channel starts disabled and disconnected
+///
+/// # Panics
+///
+/// This function panics if `n` is not a programmable channel (see `CHANNELS`) or if the channel
+/// has already been claimed
+pub fn claim(n: u8) -> Channel {
+    if n >= CHANNELS {
+        semidap::panic!("PPI channel {} is not programmable", n);
+    }
+
+    let mask = 1 << n;
+    if TAKEN.fetch_or(mask, Ordering::Relaxed) & mask != 0 {
+        semidap::panic!("PPI channel {} has already been claimed", n);
+    }
+
+    let ch = Channel {
+        n,
+        _not_send_or_sync: NotSendOrSync::new(),
+    };
+    ch.disable();
+    ch
+}
+
+/// A programmable PPI channel
+pub struct Channel {
+    n: u8,
+    _not_send_or_sync: NotSendOrSync,
+}
+
+impl Channel {
+    /// Returns the number of this channel
+    pub fn number(&self) -> u8 {
+        self.n
+    }
+
+    /// Connects `event` to `task`: once the channel is enabled each occurrence of the event
+    /// triggers the task
+    ///
+    /// This replaces the previous connection of the channel, if any. The channel is disabled
+    /// while it's being reconfigured and left disabled; call `enable` to start it
+    pub fn connect<E, T>(&mut self, event: &E, task: &T)
+    where
+        E: Event,
+        T: SafeTask,
+    {
+        unsafe { self.connect_unchecked(event, task) }
+    }
+
+    /// Like `connect` but also accepts tasks that start EasyDMA transfers
+    ///
+    /// # Safety
+    /// The hardware triggers `task` behind the back of the driver that owns its peripheral. The
+    /// caller must ensure that the buffers the task operates on stay valid, and are not accessed
+    /// by the CPU, for as long as the channel is enabled
+    pub unsafe fn connect_unchecked<E, T>(&mut self, _event: &E, _task: &T)
+    where
+        E: Event,
+        T: Task,
+    {
+        self.disable();
+
+        let n = usize::from(self.n);
+        // NOTE the `CH[n]` clusters are 8 bytes apart: `EEP` then `TEP`
+        pac::ppi::CH0_EEP::address()
+            .add(2 * n)
+            .write_volatile(E::ADDRESS);
+        pac::ppi::CH0_TEP::address()
+            .add(2 * n)
+            .write_volatile(T::ADDRESS);
+    }
+
+    /// Enables the channel
+    pub fn enable(&self) {
+        // NOTE(unsafe) write-1-to-set register; only affects this channel
+        unsafe { pac::ppi::CHENSET::address().write_volatile(1 << self.n) }
+    }
+
+    /// Disables the channel
+    pub fn disable(&self) {
+        // NOTE(unsafe) write-1-to-clear register; only affects this channel
+        unsafe { pac::ppi::CHENCLR::address().write_volatile(1 << self.n) }
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        self.disable();
+        TAKEN.fetch_and(!(1 << self.n), Ordering::Relaxed);
+    }
+}
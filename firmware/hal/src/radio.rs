@@ -237,6 +237,7 @@ mod task {
     }
 }
 
+#[cfg_attr(feature = "text", derive(ufmt::derive::uDebug))]
 #[derive(binDebug)]
 enum Event {
     CCABUSY,
@@ -246,6 +247,8 @@ enum Event {
     READY,
 }
 
+display!(Event);
+
 impl Event {
     fn next() -> Option<Self> {
         RADIO::borrow_unchecked(|radio| {
@@ -615,6 +618,26 @@ impl Packet {
         packet
     }
 
+    /// Returns a new IEEE 802.15.4 packet filled with the given `src` data
+    ///
+    /// NOTE `src` data will be truncated to `Self::CAPACITY` bytes
+    pub async fn from_slice(src: &[u8]) -> Self {
+        let mut packet = Self::new().await;
+        packet.copy_from_slice(src);
+        packet
+    }
+
+    /// Returns a new IEEE 802.15.4 packet filled with the given `src` data
+    ///
+    /// Returns an error, without allocating a packet, if `src` is larger than `Self::CAPACITY`
+    pub async fn try_from_slice(src: &[u8]) -> Result<Self, ()> {
+        if src.len() > usize::from(Self::CAPACITY) {
+            return Err(());
+        }
+
+        Ok(Self::from_slice(src).await)
+    }
+
     /// Fills the packet with given `src` data
     ///
     /// NOTE `src` data will be truncated to `Self::CAPACITY` bytes
@@ -633,6 +656,12 @@ impl Packet {
         }
     }
 
+    /// Returns a view into the packet contents that formats as hexadecimal
+    #[cfg(feature = "text")]
+    pub fn hex(&self) -> crate::Hex<'_> {
+        crate::Hex::new(self)
+    }
+
     /// Changes the `len` of the packet
     ///
     /// NOTE `len` will be truncated to `Self::CAPACITY` bytes
@@ -648,13 +677,13 @@ impl Packet {
         unsafe { *self.data_ptr().add(self.len().into()) }
     }
 
+    /// # Safety
+    /// The first `len` bytes of data in `buffer` must be initialized and `len` must not exceed
+    /// `Self::CAPACITY`
     #[cfg(feature = "usb")]
-    #[cfg(TODO)]
-    pub(crate) fn from_parts(buffer: Box<P>, len: u8) -> Self {
+    pub(crate) unsafe fn from_parts(buffer: Box<P>, len: u8) -> Self {
         let mut packet = Packet { buffer };
-        unsafe {
-            packet.len_ptr_mut().write(len);
-        }
+        packet.len_ptr_mut().write(len + 2 /* CRC */);
         packet
     }
 
@@ -693,10 +722,14 @@ impl ops::DerefMut for Packet {
     }
 }
 
-#[cfg(TODO)]
+/// Zero-copy conversion; see `From<usbd::Packet> for Packet`
+///
+/// Returns the radio packet back if it doesn't fit in a HID packet
 #[cfg(feature = "usb")]
-impl crate::usbd::Packet {
-    pub fn try_from(packet: Packet) -> Result<crate::usbd::Packet, Packet> {
+impl core::convert::TryFrom<Packet> for crate::usbd::Packet {
+    type Error = Packet;
+
+    fn try_from(packet: Packet) -> Result<crate::usbd::Packet, Packet> {
         let len = packet.len();
         if len <= crate::usbd::Packet::CAPACITY {
             Ok(unsafe { crate::usbd::Packet::from_parts(packet.buffer, len) })
@@ -707,6 +740,7 @@ impl crate::usbd::Packet {
 }
 
 #[allow(dead_code)]
+#[cfg_attr(feature = "text", derive(ufmt::derive::uDebug))]
 #[derive(Clone, Copy, PartialEq, binDebug)]
 #[repr(u8)]
 enum State {
@@ -721,26 +755,28 @@ enum State {
     TxDisable = 12,
 }
 
+display!(State);
+
 // NOTE(borrow_unchecked) all these are either single instruction reads w/o side effects or single
 // instruction writes to registers won't be RMW-ed
 #[allow(non_snake_case)]
 fn TASKS_CCASTART() {
-    RADIO::borrow_unchecked(|radio| radio.TASKS_CCASTART.write(|w| w.TASKS_CCASTART(1)))
+    RADIO::borrow_unchecked(|radio| radio.TASKS_CCASTART.trigger())
 }
 
 #[allow(non_snake_case)]
 fn TASKS_RXEN() {
-    RADIO::borrow_unchecked(|radio| radio.TASKS_RXEN.write(|w| w.TASKS_RXEN(1)))
+    RADIO::borrow_unchecked(|radio| radio.TASKS_RXEN.trigger())
 }
 
 #[allow(non_snake_case)]
 fn TASKS_START() {
-    RADIO::borrow_unchecked(|radio| radio.TASKS_START.write(|w| w.TASKS_START(1)))
+    RADIO::borrow_unchecked(|radio| radio.TASKS_START.trigger())
 }
 
 #[allow(non_snake_case)]
 fn TASKS_STOP() {
-    RADIO::borrow_unchecked(|radio| radio.TASKS_STOP.write(|w| w.TASKS_STOP(1)))
+    RADIO::borrow_unchecked(|radio| radio.TASKS_STOP.trigger())
 }
 
 #[allow(non_snake_case)]
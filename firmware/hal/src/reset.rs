@@ -1,9 +1,12 @@
-use core::{mem, ptr};
+use core::{
+    mem, ptr,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use cm::{DCB, DWT, NVIC};
 use pac::{p0, CLOCK, P0, RTC0};
 
-use crate::led;
+use crate::{init::InitSequence as _, led};
 
 #[no_mangle]
 unsafe extern "C" fn Reset() {
@@ -15,19 +18,9 @@ unsafe extern "C" fn Reset() {
         dwt.CTRL.rmw(|_, w| w.CYCCNTENA(1));
     });
 
-    CLOCK::borrow_unchecked(|clock| {
-        // use the external crystal (LFXO) as the low-frequency clock (LFCLK) source
-        clock.LFCLKSRC.write(|w| w.SRC(1));
-
-        // start the LFXO
-        clock.TASKS_LFCLKSTART.write(|w| w.TASKS_LFCLKSTART(1));
-    });
-
-    // start the RTC with a counter of 0
-    RTC0::borrow_unchecked(|rtc| {
-        rtc.TASKS_CLEAR.write(|w| w.TASKS_CLEAR(1));
-        rtc.TASKS_START.write(|w| w.TASKS_START(1));
-    });
+    // start the LFXO and then the RTC
+    CLOCK::init_sequence();
+    RTC0::init_sequence();
 
     // zero .bss
     extern "C" {
@@ -62,9 +55,21 @@ unsafe extern "C" fn Reset() {
         );
     }
 
+    #[cfg(feature = "stack-usage")]
+    {
+        extern "C" {
+            static mut __stack_bottom__: u32;
+        }
+
+        asm::paint_stack(&mut __stack_bottom__, STACK_PAINT);
+    }
+
     // NOTE this is a memory barrier -- .bss will be zeroed before the code that comes after this
     asm::disable_irq();
 
+    // configure the power supply before touching the I/O pins
+    crate::power::init();
+
     // seal some peripherals so they cannot be used from the application
     CLOCK::seal();
     DCB::seal();
@@ -102,9 +107,64 @@ unsafe extern "C" fn Reset() {
     main()
 }
 
+#[cfg(feature = "stack-usage")]
+const STACK_PAINT: u32 = 0xAAAA_AAAA;
+
+/// Returns the maximum amount of stack, in bytes, that has been used since boot
+///
+/// Only available with the `stack-usage` feature. `Reset` fills the unused stack with a known
+/// pattern, which takes some time at boot; this function scans for the lowest word that has been
+/// overwritten. The result is a lower bound: a stack frame may have been reserved but not fully
+/// written. All the code, interrupt handlers included, runs on the main stack (MSP) so this
+/// measures the usage of the whole program
+#[cfg(feature = "stack-usage")]
+pub fn stack_high_water() -> usize {
+    extern "C" {
+        static __stack_bottom__: u32;
+        static __stack_top__: u32;
+    }
+
+    unsafe {
+        let top = &__stack_top__ as *const u32;
+        let mut p = &__stack_bottom__ as *const u32;
+        while p < top && p.read_volatile() == STACK_PAINT {
+            p = p.add(1);
+        }
+
+        top as usize - p as usize
+    }
+}
+
+// last observed value of the cycle counter and number of times it has wrapped around
+static LAST_CYCCNT: AtomicU32 = AtomicU32::new(0);
+static CYCCNT_WRAPS: AtomicU32 = AtomicU32::new(0);
+
+// Microseconds since boot (at 64 MHz)
+//
+// The 32-bit cycle counter wraps around every ~67 seconds; a wrap-around is detected when the
+// counter is observed to go backwards and accounted for so timestamps are monotonic for ~71
+// minutes. This needs a timestamp to be taken at least once every 67 seconds; after a longer
+// silence a wrap-around can go unnoticed and timestamps will appear to jump back
 #[no_mangle]
 fn __semidap_timestamp() -> u32 {
-    crate::cyccnt() >> 6
+    // NOTE critical section: a log message may be emitted from a higher priority interrupt
+    // handler in between the reads and writes below
+    let masked = asm::primask() & 1 != 0;
+    asm::disable_irq();
+
+    let now = crate::cyccnt();
+    if now < LAST_CYCCNT.load(Ordering::Relaxed) {
+        CYCCNT_WRAPS.fetch_add(1, Ordering::Relaxed);
+    }
+    LAST_CYCCNT.store(now, Ordering::Relaxed);
+    let wraps = CYCCNT_WRAPS.load(Ordering::Relaxed);
+
+    if !masked {
+        asm::enable_irq();
+    }
+
+    // `(wraps << 32 | now) >> 6`
+    wraps << 26 | now >> 6
 }
 
 #[repr(C)]
@@ -174,6 +234,7 @@ extern "C" {
     fn SPIM3();
 }
 
+// NOTE the nRF52833 and the nRF52840 have the same 48 interrupts so they share this table
 #[link_section = ".vectors"]
 #[no_mangle]
 static mut VECTORS: [Vector; 64] = [
@@ -336,3 +397,83 @@ static mut VECTORS: [Vector; 64] = [
         handler: SPIM3, // 47
     },
 ];
+
+/// Size of the `.uninit` RAM reserved for the panic message, in bytes
+#[cfg(feature = "panic-capture")]
+pub const PANIC_MESSAGE_SIZE: usize = 128;
+
+// marks a valid `PanicRecord`; RAM contents are random after a power cycle
+#[cfg(feature = "panic-capture")]
+const PANIC_MAGIC: u32 = 0xDEAD_C0DE;
+
+#[cfg(feature = "panic-capture")]
+#[repr(C)]
+struct PanicRecord {
+    magic: u32,
+    len: u32,
+    buf: [u8; PANIC_MESSAGE_SIZE],
+}
+
+// NOTE `.uninit` is not zeroed by `Reset` so the record survives a soft reset
+#[cfg(feature = "panic-capture")]
+#[link_section = ".uninit.PANIC_RECORD"]
+static mut PANIC_RECORD: mem::MaybeUninit<PanicRecord> = mem::MaybeUninit::uninit();
+
+#[cfg(feature = "panic-capture")]
+impl core::fmt::Write for PanicRecord {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let start = self.len as usize;
+        // truncate the message once the buffer is full
+        let n = core::cmp::min(s.len(), PANIC_MESSAGE_SIZE - start);
+        self.buf[start..start + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n as u32;
+        Ok(())
+    }
+}
+
+/// Returns the message of the panic that caused the last soft reset, if any
+///
+/// Only available with the `panic-capture` feature, which installs a panic handler that stores
+/// the first `PANIC_MESSAGE_SIZE` bytes of the panic message in a RAM section that `Reset` doesn't
+/// zero and then, unless a debugger is attached, resets the device (see `crate::reset`). The
+/// message is kept until the next panic and lost on a power cycle
+#[cfg(feature = "panic-capture")]
+pub fn last_panic() -> Option<&'static str> {
+    unsafe {
+        let record = &*PANIC_RECORD.as_ptr();
+        if record.magic != PANIC_MAGIC || record.len as usize > PANIC_MESSAGE_SIZE {
+            return None;
+        }
+
+        let bytes = &record.buf[..record.len as usize];
+        // the message may have been truncated in the middle of a UTF-8 sequence
+        Some(match core::str::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(e) => core::str::from_utf8_unchecked(&bytes[..e.valid_up_to()]),
+        })
+    }
+}
+
+#[cfg(feature = "panic-capture")]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write as _;
+
+    asm::disable_irq();
+
+    unsafe {
+        let record = &mut *PANIC_RECORD.as_mut_ptr();
+        // invalidate the record while it's being written
+        record.magic = 0;
+        record.len = 0;
+        let _ = write!(record, "{}", info);
+        record.magic = PANIC_MAGIC;
+    }
+
+    // NOTE(borrow_unchecked) single-instruction read with no side effects
+    if DCB::borrow_unchecked(|dcb| dcb.DHCSR.read().C_DEBUGEN()) != 0 {
+        semidap::abort()
+    } else {
+        crate::reset()
+    }
+}
@@ -20,6 +20,8 @@ fn SPIM0_SPIS0_TWIM0_TWIS0_SPI0_TWI0() {
 
     DONE.store(true, Ordering::Relaxed);
     SPIM0::borrow_unchecked(|spim| spim.EVENTS_END.zero());
+    // see "Memory ordering" in `lib.rs`
+    asm::dsb();
 }
 
 static DONE: AtomicBool = AtomicBool::new(false);
@@ -122,7 +124,7 @@ impl Spi {
             DONE.store(false, Ordering::Relaxed);
 
             crate::dma_start();
-            spim.TASKS_START.write(|w| w.TASKS_START(1));
+            spim.TASKS_START.trigger();
         });
 
         crate::poll_fn(|| {
@@ -1,13 +1,60 @@
 //! Temporal quantification
 
-use core::{ops, time::Duration};
+use core::{future::Future, ops, task::Poll, time::Duration};
 
+use async_core::future::{self, Either};
+use binfmt::derive::binDebug;
 use pac::RTC0;
 
+/// Error returned by operations that did not complete within the given amount of time
+#[derive(Clone, Copy, PartialEq, binDebug)]
+pub struct Timeout;
+
 pub(crate) fn now() -> u32 {
     RTC0::borrow_unchecked(|rtc| rtc.COUNTER.read().into())
 }
 
+/// Converts `dur` into RTC ticks (1 tick = 1/32,768 s), rounding down
+pub(crate) fn ticks(dur: Duration) -> u32 {
+    dur.as_secs() as u32 * 32_768
+        + dur
+            .subsec_nanos()
+            .wrapping_mul(4)
+            .wrapping_div(5)
+            .wrapping_mul(4)
+            .wrapping_div(5)
+            .wrapping_mul(4)
+            .wrapping_div(78125)
+}
+
+/// Drives `fut` to completion or until `ticks` RTC ticks (1 tick = 1/32,768 s) have elapsed,
+/// whichever happens first
+///
+/// On timeout `fut` is dropped, in the middle of whatever it was doing, so this is only sound to
+/// use with futures whose `Drop` implementation leaves things in a consistent state, e.g. by
+/// aborting an ongoing EasyDMA transfer; see `async_core::future::select` for details
+///
+/// NOTE the deadline is checked every time the executor wakes up; the RTC0 heartbeat wakes it up
+/// every 125 ms so the timeout may be exceeded by up to that amount
+pub async fn with_timeout<F>(ticks: u32, fut: F) -> Result<F::Output, Timeout>
+where
+    F: Future,
+{
+    let end = now().wrapping_add(ticks);
+    let deadline = crate::poll_fn(|| {
+        if now() >= end {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    });
+
+    match future::select(fut, deadline).await {
+        Either::Left(output) => Ok(output),
+        Either::Right(()) => Err(Timeout),
+    }
+}
+
 /// A measurement of a monotonically nondecreasing clock. Opaque and only useful
 /// with `core::time::Duration`
 pub struct Instant {
@@ -4,7 +4,7 @@ use core::{
     cmp,
     future::Future,
     pin::Pin,
-    sync::atomic::{AtomicU8, Ordering},
+    sync::atomic::{AtomicU32, AtomicU8, Ordering},
     task::{Context, Poll},
     time::Duration,
 };
@@ -66,22 +66,61 @@ mod task {
 
             if rtc.EVENTS_COMPARE1.read().EVENTS_COMPARE() != 0 {
                 rtc.EVENTS_COMPARE1.zero();
-                rtc.INTENCLR.write(|w| w.COMPARE1(1));
+
+                if let Some(f) = super::callback(1) {
+                    rtc.CC1
+                        .rmw(|r, w| w.COMPARE(r.COMPARE().wrapping_add(super::period(1))));
+                    f();
+                } else {
+                    rtc.INTENCLR.write(|w| w.COMPARE1(1));
+                }
             }
 
             if rtc.EVENTS_COMPARE2.read().EVENTS_COMPARE() != 0 {
                 rtc.EVENTS_COMPARE2.zero();
-                rtc.INTENCLR.write(|w| w.COMPARE2(1));
+
+                if let Some(f) = super::callback(2) {
+                    rtc.CC2
+                        .rmw(|r, w| w.COMPARE(r.COMPARE().wrapping_add(super::period(2))));
+                    f();
+                } else {
+                    rtc.INTENCLR.write(|w| w.COMPARE2(1));
+                }
             }
 
             if rtc.EVENTS_COMPARE3.read().EVENTS_COMPARE() != 0 {
                 rtc.EVENTS_COMPARE3.zero();
-                rtc.INTENCLR.write(|w| w.COMPARE3(1));
+
+                if let Some(f) = super::callback(3) {
+                    rtc.CC3
+                        .rmw(|r, w| w.COMPARE(r.COMPARE().wrapping_add(super::period(3))));
+                    f();
+                } else {
+                    rtc.INTENCLR.write(|w| w.COMPARE3(1));
+                }
             }
         });
     }
 }
 
+// periodic callbacks and their periods, indexed by compare channel
+static mut CALLBACKS: [Option<fn()>; 4] = [None; 4];
+static PERIODS: [AtomicU32; 4] = [
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+];
+
+fn callback(i: usize) -> Option<fn()> {
+    // NOTE(unsafe) only written while the compare interrupt is disabled
+    unsafe { CALLBACKS[i] }
+}
+
+fn period(i: usize) -> u32 {
+    PERIODS[i].load(Ordering::Relaxed)
+}
+
 /// [Singleton] timer
 pub struct Timer {
     i: u8,
@@ -109,17 +148,41 @@ impl Timer {
         semidap::panic!("no more `Timer` instances can be claimed")
     }
 
+    /// Calls `f` every `ticks` RTC ticks (1 tick = 1/32,768 s) from the RTC0 interrupt handler
+    ///
+    /// The periodic timer uses the compare register that backs this `Timer` so at most 3 periodic
+    /// timers can be active at the same time, and fewer if other `Timer`s have been claimed. The
+    /// compare register is reprogrammed on each fire so the period does not drift.
+    ///
+    /// NOTE `ticks` will be clamped to the range `2..=0x7F_FFFF`
+    pub fn every(self, ticks: u32, f: fn()) -> Periodic {
+        let i = usize::from(self.i);
+        let ticks = cmp::min(cmp::max(ticks, 2), 0x7F_FFFF);
+
+        PERIODS[i].store(ticks, Ordering::Relaxed);
+        // NOTE(unsafe) the compare interrupt is currently disabled
+        unsafe { CALLBACKS[i] = Some(f) }
+
+        let end = time::now().wrapping_add(ticks);
+        RTC0::borrow_unchecked(|rtc| {
+            if i == 1 {
+                rtc.CC1.write(|w| w.COMPARE(end));
+                unsafe { rtc.INTENSET.write(|w| w.COMPARE1(1)) }
+            } else if i == 2 {
+                rtc.CC2.write(|w| w.COMPARE(end));
+                unsafe { rtc.INTENSET.write(|w| w.COMPARE2(1)) }
+            } else {
+                rtc.CC3.write(|w| w.COMPARE(end));
+                unsafe { rtc.INTENSET.write(|w| w.COMPARE3(1)) }
+            }
+        });
+
+        Periodic { timer: self }
+    }
+
     /// Waits for the specified duration
     pub fn wait<'t>(&'t mut self, dur: Duration) -> impl Future<Output = ()> + 't {
-        let diff = dur.as_secs() as u32 * 32_768
-            + dur
-                .subsec_nanos()
-                .wrapping_mul(4)
-                .wrapping_div(5)
-                .wrapping_mul(4)
-                .wrapping_div(5)
-                .wrapping_mul(4)
-                .wrapping_div(78125);
+        let diff = time::ticks(dur);
 
         Wait {
             timer: self,
@@ -130,6 +193,44 @@ impl Timer {
     }
 }
 
+/// A periodic timer; see [`Timer::every`]
+///
+/// Dropping it stops the timer, like `stop` does, but the underlying `Timer` is not given back
+pub struct Periodic {
+    timer: Timer,
+}
+
+impl Periodic {
+    /// Stops the periodic timer and returns the underlying `Timer`
+    pub fn stop(self) -> Timer {
+        let timer = Timer {
+            i: self.timer.i,
+            _not_sync: NotSync::new(),
+        };
+        // `Drop` stops the timer
+        drop(self);
+        timer
+    }
+}
+
+impl Drop for Periodic {
+    fn drop(&mut self) {
+        let i = self.timer.i;
+        RTC0::borrow_unchecked(|rtc| {
+            if i == 1 {
+                rtc.INTENCLR.write(|w| w.COMPARE1(1));
+            } else if i == 2 {
+                rtc.INTENCLR.write(|w| w.COMPARE2(1));
+            } else {
+                rtc.INTENCLR.write(|w| w.COMPARE3(1));
+            }
+        });
+
+        // NOTE(unsafe) the compare interrupt has been disabled
+        unsafe { CALLBACKS[usize::from(i)] = None }
+    }
+}
+
 struct Wait<'a> {
     timer: &'a mut Timer,
     state: State,
@@ -1,9 +1,38 @@
 //! USB device
+//!
+//! The power attributes of the configuration descriptor are picked at build time with these
+//! environment variables:
+//!
+//! - `USB_MAX_POWER`, maximum current draw from the bus in mA; defaults to 500, which is also the
+//!   maximum allowed value
+//! - `USB_SELF_POWERED`, if set the device reports itself as self-powered
+//!
+//! `USB_MAX_POWER` can also be a comma-separated list, e.g. `500,100`, in which case the device
+//! offers one configuration per entry. All configurations have the same interfaces; the first one
+//! is the default, the one most hosts select
+//!
+//! The number of back to back stalled control requests after which the USB peripheral is reset
+//! (see `reset`) is set with the `USB_MAX_EP0_STALLS` environment variable; it defaults to 16 and
+//! a value of 0 disables the automatic reset
+//!
+//! The polling interval of the CDC ACM notification endpoint is set, in ms, with the
+//! `USB_CDC_NOTIFY_INTERVAL` environment variable; it defaults to 32 and must be in the range
+//! 1..=255, the valid range of `bInterval` for full-speed interrupt endpoints. Notifications are
+//! never sent more often than that; one that comes too early is sent once the interval has elapsed
+//!
+//! With the `iso` feature the size of the isochronous IN packets is set with the
+//! `USB_ISO_MAX_PACKET_SIZE` environment variable; it defaults to 192 bytes (48 kHz, 16-bit
+//! stereo audio) and can be at most 1023 bytes
+//!
+//! By default the device attaches to the bus (pulls D+ up) as soon as the USB peripheral is ready,
+//! so the host may start enumerating it right after boot. With the `deferred-connect` feature the
+//! device stays detached until the application calls `enable`
 
 use core::{
-    cmp, ops, ptr, slice,
-    sync::atomic::{AtomicBool, Ordering},
+    cmp, mem, ops, ptr, slice,
+    sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU8, Ordering},
     task::Poll,
+    time::Duration,
 };
 
 use binfmt::derive::binDebug;
@@ -12,12 +41,20 @@ use pac::{
     POWER, USBD,
 };
 use pool::Box;
-use usb2::{cdc::acm, hid, GetDescriptor, Request, StandardRequest};
+use usb2::GetDescriptor;
 
-use crate::{atomic::Atomic, mem::P, Interrupt1, NotSendOrSync};
+#[cfg(any(feature = "class-cdc", feature = "iso"))]
+use crate::util::Align4;
+#[cfg(feature = "class-cdc")]
+use crate::Interrupt1;
+use crate::{atomic::Atomic, mem::P, time, NotSendOrSync};
 
 include!(concat!(env!("OUT_DIR"), "/descs.rs"));
 
+// the hand-written interrupt table must agree with the SVD file
+const _: [(); 1] = [(); (USBD::IRQ == crate::Interrupt1::USBD as u8) as usize];
+
+#[cfg(feature = "class-cdc")]
 #[derive(Clone, Copy, PartialEq, binDebug)]
 #[repr(u8)]
 enum Ep2InState {
@@ -27,25 +64,498 @@ enum Ep2InState {
     InUse,
 }
 
+#[cfg(feature = "class-cdc")]
 derive!(Ep2InState);
 
+#[cfg(feature = "class-cdc")]
 static EP2IN_STATE: Atomic<Ep2InState> = Atomic::new();
 
+#[cfg(feature = "class-cdc")]
+#[link_section = ".uninit.EP2IN_BUF"]
+static mut EP2IN_BUF: Align4<[u8; 63]> = Align4([0; 63]);
+
+static BACKLOGGED: AtomicU32 = AtomicU32::new(0);
+static DISCARDED: AtomicU32 = AtomicU32::new(0);
+
+/// OUT endpoint statistics
+#[derive(Clone, Copy, binDebug)]
+pub struct Stats {
+    /// Number of `HidOut` and `HidOutRing` reads that found a packet already waiting in the
+    /// peripheral
+    ///
+    /// While an OUT packet is waiting to be read the endpoint NAKs the host, so the next packet is
+    /// held back on the host side, where the firmware can't see it. A growing value means the
+    /// application is not keeping up with the host. Only the reads issued by the application are
+    /// counted, not the packets `HidOutRing` takes in ahead of time
+    pub backlogged: u32,
+
+    /// Number of packets received on the CDC data OUT endpoint and discarded
+    pub discarded: u32,
+}
+
+/// Returns the OUT endpoint statistics collected since boot
+pub fn stats() -> Stats {
+    Stats {
+        backlogged: BACKLOGGED.load(Ordering::Relaxed),
+        discarded: DISCARDED.load(Ordering::Relaxed),
+    }
+}
+
+/// Number of entries kept by the USB event log; see `event_log`
+#[cfg(feature = "usb-trace")]
+pub const EVENT_LOG_SIZE: usize = 32;
+
+/// Event recorded in the USB event log
+#[cfg(feature = "usb-trace")]
+#[derive(Clone, Copy, PartialEq, binDebug)]
+#[repr(u8)]
+pub enum TraceEvent {
+    /// The device booted; the entries before this one belong to the previous boot
+    Boot = 0,
+    /// `reset` was called
+    Reset = 1,
+    /// `shutdown` was called
+    Shutdown = 2,
+    /// POWER event: the cable has been attached
+    USBDETECTED = 3,
+    /// POWER event: the cable has been removed
+    USBREMOVED = 4,
+    /// POWER event: the USB supply is ready
+    USBPWRRDY = 5,
+    /// USBD event
+    ENDEPOUT0 = 6,
+    /// USBD event
+    ENDEPOUT2 = 7,
+    /// USBD event
+    ENDEPOUT3 = 8,
+    /// USBD event
+    ENDEPIN3 = 9,
+    /// USBD event
+    EP0DATADONE = 10,
+    /// USBD event
+    EP0SETUP = 11,
+    /// USBD event
+    EPDATA = 12,
+    /// USBD event
+    USBEVENT = 13,
+    /// USBD event
+    USBRESET = 14,
+    /// Data was written into the CDC ACM transmit buffer
+    TxWrite = 15,
+    /// Control request; the SETUP packet is in the other fields of the entry
+    Request = 16,
+    /// USBD event
+    SOF = 17,
+    /// USBD event
+    ENDISOIN = 18,
+}
+
+#[cfg(feature = "usb-trace")]
+const TRACE_EVENTS: [TraceEvent; 19] = [
+    TraceEvent::Boot,
+    TraceEvent::Reset,
+    TraceEvent::Shutdown,
+    TraceEvent::USBDETECTED,
+    TraceEvent::USBREMOVED,
+    TraceEvent::USBPWRRDY,
+    TraceEvent::ENDEPOUT0,
+    TraceEvent::ENDEPOUT2,
+    TraceEvent::ENDEPOUT3,
+    TraceEvent::ENDEPIN3,
+    TraceEvent::EP0DATADONE,
+    TraceEvent::EP0SETUP,
+    TraceEvent::EPDATA,
+    TraceEvent::USBEVENT,
+    TraceEvent::USBRESET,
+    TraceEvent::TxWrite,
+    TraceEvent::Request,
+    TraceEvent::SOF,
+    TraceEvent::ENDISOIN,
+];
+
+/// Entry of the USB event log
+#[cfg(feature = "usb-trace")]
+#[derive(Clone, Copy, binDebug)]
+pub struct TraceEntry {
+    /// When the event was recorded, in ticks of the 32,768 Hz RTC since boot (24-bit counter)
+    pub timestamp: u32,
+    /// What happened
+    pub event: TraceEvent,
+    /// The `bmRequestType` field of the SETUP packet; zero unless `event` is `Request`
+    pub bmrequesttype: u8,
+    /// The `bRequest` field of the SETUP packet; zero unless `event` is `Request`
+    pub brequest: u8,
+    /// The `wValue` field of the SETUP packet; zero unless `event` is `Request`
+    pub wvalue: u16,
+    /// The `wIndex` field of the SETUP packet; zero unless `event` is `Request`
+    pub windex: u16,
+    /// The `wLength` field of the SETUP packet; zero unless `event` is `Request`
+    pub wlength: u16,
+}
+
+// NOTE only integers so that any bit pattern left in RAM is a valid value
+#[cfg(feature = "usb-trace")]
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct RawTraceEntry {
+    timestamp: u32,
+    event: u8,
+    bmrequesttype: u8,
+    brequest: u8,
+    wvalue: u16,
+    windex: u16,
+    wlength: u16,
+}
+
+// marks a valid `TraceLog`; RAM contents are random after a power cycle
+#[cfg(feature = "usb-trace")]
+const TRACE_MAGIC: u32 = 0x7ACE_05B0;
+
+#[cfg(feature = "usb-trace")]
+#[repr(C)]
+struct TraceLog {
+    magic: u32,
+    // total number of recorded entries; the next entry goes in `entries[next % EVENT_LOG_SIZE]`
+    next: AtomicU32,
+    entries: [RawTraceEntry; EVENT_LOG_SIZE],
+}
+
+// NOTE placed next to the panic record (see `crate::last_panic`) in `.uninit`, which `Reset`
+// doesn't zero, so the log survives a soft reset
+#[cfg(feature = "usb-trace")]
+#[link_section = ".uninit.USB_TRACE"]
+static mut TRACE_LOG: mem::MaybeUninit<TraceLog> = mem::MaybeUninit::uninit();
+
+// NOTE must be called before the USB interrupts are unmasked
+#[cfg(feature = "usb-trace")]
+fn trace_init() {
+    unsafe {
+        let log = &mut *TRACE_LOG.as_mut_ptr();
+        if log.magic != TRACE_MAGIC {
+            log.next = AtomicU32::new(0);
+            log.magic = TRACE_MAGIC;
+        }
+    }
+
+    trace(TraceEvent::Boot);
+}
+
+#[cfg(feature = "usb-trace")]
+fn trace(event: TraceEvent) {
+    trace_raw(RawTraceEntry {
+        timestamp: time::now(),
+        event: event as u8,
+        bmrequesttype: 0,
+        brequest: 0,
+        wvalue: 0,
+        windex: 0,
+        wlength: 0,
+    })
+}
+
+#[cfg(feature = "usb-trace")]
+fn trace_request(bmrequesttype: u8, brequest: u8, wvalue: u16, windex: u16, wlength: u16) {
+    trace_raw(RawTraceEntry {
+        timestamp: time::now(),
+        event: TraceEvent::Request as u8,
+        bmrequesttype,
+        brequest,
+        wvalue,
+        windex,
+        wlength,
+    })
+}
+
+// NOTE lock-free: the POWER and USBD interrupt handlers can preempt each other so each writer
+// first reserves a slot
+#[cfg(feature = "usb-trace")]
+fn trace_raw(entry: RawTraceEntry) {
+    unsafe {
+        let log = TRACE_LOG.as_mut_ptr();
+        let i = (*log).next.fetch_add(1, Ordering::Relaxed) as usize % EVENT_LOG_SIZE;
+        (*log).entries.as_mut_ptr().add(i).write_volatile(entry);
+    }
+}
+
+/// Snapshot of the USB event log
+#[cfg(feature = "usb-trace")]
+pub struct EventLog {
+    entries: [RawTraceEntry; EVENT_LOG_SIZE],
+    next: u32,
+}
+
+#[cfg(feature = "usb-trace")]
+impl EventLog {
+    /// Returns the recorded entries, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = TraceEntry> + '_ {
+        let len = cmp::min(self.next as usize, EVENT_LOG_SIZE);
+        let start = self.next as usize - len;
+        (start..start + len).filter_map(move |i| {
+            let raw = &self.entries[i % EVENT_LOG_SIZE];
+            // skip an entry that was being written when the device was reset
+            let event = *TRACE_EVENTS.get(usize::from(raw.event))?;
+            Some(TraceEntry {
+                timestamp: raw.timestamp,
+                event,
+                bmrequesttype: raw.bmrequesttype,
+                brequest: raw.brequest,
+                wvalue: raw.wvalue,
+                windex: raw.windex,
+                wlength: raw.wlength,
+            })
+        })
+    }
+
+    /// Returns the number of entries that have been overwritten by newer ones
+    pub fn dropped(&self) -> u32 {
+        self.next.saturating_sub(EVENT_LOG_SIZE as u32)
+    }
+}
+
+/// Returns a copy of the last `EVENT_LOG_SIZE` USB events and control requests
+///
+/// Only available with the `usb-trace` feature. Every POWER and USBD event, and every SETUP packet,
+/// is recorded together with a timestamp into a fixed-size ring buffer. The log survives soft
+/// resets (e.g. the one performed by the `panic-capture` panic handler) so it can be inspected
+/// after the fact; a `TraceEvent::Boot` entry separates the boots. The log is lost on a power
+/// cycle
+#[cfg(feature = "usb-trace")]
+pub fn event_log() -> EventLog {
+    // the interrupt handlers must not append entries while we copy them. `atomic0` and `atomic1`
+    // must not be nested so both interrupts are masked in a single critical section instead
+    crate::mask0(&[crate::Interrupt0::POWER_CLOCK]);
+    crate::mask1(&[crate::Interrupt1::USBD]);
+    core::sync::atomic::compiler_fence(Ordering::SeqCst);
+
+    // NOTE(unsafe) the only writers of `TRACE_LOG` are masked
+    let log = unsafe { &*TRACE_LOG.as_ptr() };
+    let copy = EventLog {
+        entries: log.entries,
+        next: log.next.load(Ordering::Relaxed),
+    };
+
+    core::sync::atomic::compiler_fence(Ordering::SeqCst);
+    // NOTE(unsafe) like `atomic0` and `atomic1`, must not be called from a critical section that
+    // masks these interrupts
+    unsafe {
+        crate::unmask0(&[crate::Interrupt0::POWER_CLOCK]);
+        crate::unmask1(&[crate::Interrupt1::USBD]);
+    }
+    copy
+}
+
+#[cfg(feature = "usb-trace")]
+impl From<PowerEvent> for TraceEvent {
+    fn from(event: PowerEvent) -> Self {
+        match event {
+            PowerEvent::Reset => TraceEvent::Reset,
+            PowerEvent::Shutdown => TraceEvent::Shutdown,
+            PowerEvent::USBDETECTED => TraceEvent::USBDETECTED,
+            PowerEvent::USBREMOVED => TraceEvent::USBREMOVED,
+            PowerEvent::USBPWRRDY => TraceEvent::USBPWRRDY,
+        }
+    }
+}
+
+#[cfg(feature = "usb-trace")]
+impl From<UsbdEvent> for TraceEvent {
+    fn from(event: UsbdEvent) -> Self {
+        match event {
+            UsbdEvent::ENDEPOUT0 => TraceEvent::ENDEPOUT0,
+            #[cfg(feature = "class-cdc")]
+            UsbdEvent::ENDEPOUT2 => TraceEvent::ENDEPOUT2,
+            UsbdEvent::ENDEPOUT3 => TraceEvent::ENDEPOUT3,
+            UsbdEvent::ENDEPIN3 => TraceEvent::ENDEPIN3,
+            #[cfg(feature = "iso")]
+            UsbdEvent::ENDISOIN => TraceEvent::ENDISOIN,
+            UsbdEvent::EP0DATADONE => TraceEvent::EP0DATADONE,
+            UsbdEvent::EP0SETUP => TraceEvent::EP0SETUP,
+            UsbdEvent::EPDATA => TraceEvent::EPDATA,
+            #[cfg(any(feature = "class-cdc", feature = "dfu", feature = "iso"))]
+            UsbdEvent::SOF => TraceEvent::SOF,
+            #[cfg(feature = "class-cdc")]
+            UsbdEvent::TxWrite => TraceEvent::TxWrite,
+            UsbdEvent::USBEVENT => TraceEvent::USBEVENT,
+            UsbdEvent::USBRESET => TraceEvent::USBRESET,
+        }
+    }
+}
+
+// whether the bus is suspended and, if so, since when (in RTC ticks)
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+static SUSPENDED_AT: AtomicU32 = AtomicU32::new(0);
+
+// whether the host has enabled the DEVICE_REMOTE_WAKEUP feature
+#[cfg(feature = "remote-wakeup")]
+static REMOTE_WAKEUP: AtomicBool = AtomicBool::new(false);
+
+/// Minimum time the bus must have been suspended before the device can signal remote wakeup
+///
+/// The USB 2.0 specification (section 7.1.7.7) requires the bus to be idle for at least 5 ms
+/// before the device drives resume signaling. The SUSPEND event is raised after 3 ms of idle bus
+/// so counting 5 ms from the event is a conservative bound
+#[cfg(feature = "remote-wakeup")]
+pub const MIN_SUSPEND: Duration = Duration::from_millis(5);
+
+/// Signals remote wakeup to the host
+///
+/// Returns `false`, and does nothing, unless all these preconditions hold:
+///
+/// - the device is in the Configured state and the host has enabled remote wakeup with
+///   SET_FEATURE(DEVICE_REMOTE_WAKEUP); the host clears the feature when it resets or
+///   de-configures the device
+/// - the bus is suspended, which means the USB peripheral is in low power mode
+/// - the bus has been suspended for at least `MIN_SUSPEND`
+///
+/// On success the USB peripheral leaves low power mode and drives the K state on the bus for the
+/// duration required by the specification; the host then resumes the bus and the device sees a
+/// RESUME event
+#[cfg(feature = "remote-wakeup")]
+pub fn remote_wakeup() -> bool {
+    // NOTE(unsafe) not nested; the USBD interrupt handler, which updates the suspend state, must
+    // not run while we signal
+    unsafe {
+        crate::atomic1(crate::Interrupt1::USBD, || {
+            if !REMOTE_WAKEUP.load(Ordering::Relaxed) {
+                semidap::debug!("remote wakeup has not been enabled by the host");
+                return false;
+            }
+
+            if !SUSPENDED.load(Ordering::Relaxed) {
+                semidap::debug!("the bus is not suspended");
+                return false;
+            }
+
+            // rounded up to not signal early; the RTC counter is 24-bit wide
+            let min_ticks = time::ticks(MIN_SUSPEND) + 1;
+            let elapsed =
+                time::now().wrapping_sub(SUSPENDED_AT.load(Ordering::Relaxed)) & 0xFF_FFFF;
+            if elapsed < min_ticks {
+                semidap::debug!("the bus has not been suspended long enough");
+                return false;
+            }
+
+            SUSPENDED.store(false, Ordering::Relaxed);
+            USBD::borrow_unchecked(|usbd| {
+                // the peripheral must leave low power mode before it can drive the bus
+                usbd.LOWPOWER.zero();
+                usbd.DPDMVALUE.write(|w| w.STATE(1 /* Resume */));
+                usbd.TASKS_DPDMDRIVE.trigger();
+            });
+            semidap::info!("signaled remote wakeup");
+
+            true
+        })
+    }
+}
+
+/// Signal edge that triggers remote wakeup
+#[cfg(feature = "remote-wakeup")]
+#[derive(Clone, Copy, PartialEq)]
+pub enum Edge {
+    /// High to low transition; e.g. pressing a button that shorts the pin to ground
+    Falling,
+    /// Low to high transition
+    Rising,
+}
+
+#[cfg(feature = "remote-wakeup")]
+static WAKE_ON_CLAIMED: AtomicBool = AtomicBool::new(false);
+
+/// Owned GPIOTE channel that signals remote wakeup on an edge of its input pin
+#[cfg(feature = "remote-wakeup")]
+pub struct WakeOn {
+    _pin: crate::p0::Pin,
+    _not_send_or_sync: NotSendOrSync,
+}
+
+/// Signals remote wakeup (see `remote_wakeup`) every time `edge` is seen on the given `pin`
+///
+/// Uses GPIOTE channel 0 in event mode; the pin is configured as an input with its internal
+/// pull-up (`Edge::Falling`) or pull-down (`Edge::Rising`) resistor enabled. The GPIOTE
+/// interrupt keeps working while the bus is suspended because the HAL does not stop the high
+/// frequency clock when the USB peripheral enters low power mode. Edges seen while
+/// `remote_wakeup`'s preconditions don't hold are ignored
+#[cfg(feature = "remote-wakeup")]
+pub fn wake_on(pin: crate::p0::Pin, edge: Edge) -> WakeOn {
+    if WAKE_ON_CLAIMED
+        .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+        .is_ok()
+    {
+        let (pull, polarity) = match edge {
+            Edge::Falling => (3 /* Pullup */, 2 /* HiToLo */),
+            Edge::Rising => (1 /* Pulldown */, 1 /* LoToHi */),
+        };
+
+        unsafe {
+            // input buffer connected; PULL field starts at bit 2
+            pac::p0::PIN_CNF0::address()
+                .add(usize::from(pin.0))
+                .write_volatile(pull << 2);
+        }
+
+        pac::GPIOTE::borrow_unchecked(|gpiote| {
+            gpiote
+                .CONFIG0
+                .write(|w| w.MODE(1 /* Event */).PSEL(pin.0).POLARITY(polarity));
+            gpiote.EVENTS_IN0.zero();
+
+            unsafe {
+                gpiote.INTENSET.write(|w| w.IN0(1));
+                crate::unmask0(&[crate::Interrupt0::GPIOTE]);
+            }
+        });
+
+        WakeOn {
+            _pin: pin,
+            _not_send_or_sync: NotSendOrSync::new(),
+        }
+    } else {
+        semidap::panic!("`wake_on` has already been claimed");
+    }
+}
+
+#[cfg(feature = "remote-wakeup")]
+#[allow(non_snake_case)]
+#[no_mangle]
+fn GPIOTE() {
+    semidap::trace!("GPIOTE");
+
+    pac::GPIOTE::borrow_unchecked(|gpiote| gpiote.EVENTS_IN0.zero());
+    // no other GPIOTE register is accessed before returning; see "Memory ordering" in `lib.rs`
+    asm::dsb();
+    let _signaled = remote_wakeup();
+}
+
 #[tasks::declare]
 mod task {
     use core::mem::MaybeUninit;
+    #[cfg(any(feature = "class-cdc", feature = "remote-wakeup"))]
+    use core::sync::atomic::Ordering;
 
     use pac::{CLOCK, USBD};
     use pool::Node;
 
-    use crate::{clock, errata, mem::P, util::Align4, Interrupt0, Interrupt1};
+    use crate::{clock, init::InitSequence as _, mem::P, Interrupt0, Interrupt1};
 
+    #[cfg(feature = "remote-wakeup")]
+    use super::REMOTE_WAKEUP;
+    use super::{
+        Ep0State, EpIn3State, EpOut3State, Epout3Slot, PowerEvent, PowerState, UsbdEvent,
+        EPIN3_STATE, EPOUT3_SLOT, EPOUT3_STATE,
+    };
+    #[cfg(feature = "class-cdc")]
     use super::{
-        Ep0State, Ep2InState, EpIn3State, EpOut3State, PowerEvent, PowerState, UsbdEvent,
-        EP2IN_STATE, EPIN3_STATE, EPOUT3_STATE, TX_BUF,
+        Ep2InState, EpOut2State, DISCARDED, EP2IN_BUF, EP2IN_STATE, EPOUT2_STATE, RX_CLAIMED,
+        TX_BUF,
     };
 
     static mut PCSTATE: PowerState = PowerState::Off;
+    // NOTE these are also reset from `POWER` when the cable is removed
+    static mut USB_STATE: usb2::State = usb2::State::Default;
+    static mut EP0_STATE: Ep0State = Ep0State::Setup;
+    // number of back to back control requests that ended in a STALL
+    static mut EP0_STALLS: u8 = 0;
 
     // NOTE(unsafe) all interrupts are still globally masked (`CPSID I`)
     fn init() {
@@ -69,6 +579,10 @@ mod task {
                 .INTENSET
                 .write(|w| w.USBDETECTED(1).USBREMOVED(1).USBPWRRDY(1));
         });
+        // NOTE every event enabled here must have a `UsbdEvent` variant. The END events of EPIN1
+        // and EPIN2 are not needed: their buffers are only reused after the EPDATA event, which
+        // the hardware raises after the host has acknowledged the data and hence after the
+        // EasyDMA transfer has ended
         pac::USBD::borrow_unchecked(|usbd| unsafe {
             usbd.INTENSET.write(|w| {
                 w.EP0DATADONE(1)
@@ -80,8 +594,19 @@ mod task {
                     .ENDEPOUT0(1)
                     .ENDEPOUT3(1)
             });
+
+            #[cfg(feature = "class-cdc")]
+            usbd.INTENSET.write(|w| w.ENDEPOUT2(1));
+
+            // NOTE the SOF event is only enabled while the isochronous endpoint is in use, a DFU
+            // detach is pending or a CDC notification has been deferred
+            #[cfg(feature = "iso")]
+            usbd.INTENSET.write(|w| w.ENDISOIN(1));
         });
 
+        #[cfg(feature = "usb-trace")]
+        super::trace_init();
+
         unsafe {
             crate::unmask0(&[Interrupt0::POWER_CLOCK]);
             crate::unmask1(&[Interrupt1::USBD]);
@@ -94,6 +619,55 @@ mod task {
         let event = PowerEvent::next();
         if let Some(_event) = event {
             semidap::debug!("-> {}", _event);
+            #[cfg(feature = "usb-trace")]
+            super::trace(_event.into());
+        }
+
+        if event == Some(PowerEvent::USBREMOVED)
+            || event == Some(PowerEvent::Reset)
+            || event == Some(PowerEvent::Shutdown)
+        {
+            match PCSTATE {
+                // nothing to turn off. NOTE after `shutdown` the cable may still be attached so
+                // USBREMOVED can arrive in this state
+                PowerState::Off => {}
+
+                PowerState::RampUp { .. } | PowerState::Ready => {
+                    // turn off the USB peripheral; this also cancels any ongoing transfer
+                    super::disconnect();
+                    super::disable_endpoints();
+                    USBD::borrow_unchecked(|usbd| {
+                        usbd.ENABLE.zero();
+                        // drop events raised before the peripheral was turned off
+                        usbd.clear_all_events();
+                    });
+                    semidap::info!("disabled the USB peripheral");
+
+                    // start from scratch on the next USBDETECTED event
+                    *USB_STATE = usb2::State::Default;
+                    *EP0_STATE = Ep0State::Setup;
+                    *EP0_STALLS = 0;
+                    super::reset_endpoints();
+                    *PCSTATE = PowerState::Off;
+                }
+            }
+
+            // on a software reset the cable may still be attached, also when `shutdown` turned the
+            // peripheral off earlier; turn the peripheral back on right away. The supply is already
+            // up so no USBPWRRDY event will be raised
+            if event == Some(PowerEvent::Reset) && super::vbus_detected() {
+                unsafe { USBD::init_sequence() }
+
+                semidap::info!("re-enabled the USB peripheral");
+
+                *PCSTATE = PowerState::RampUp {
+                    clock: clock::is_stable(),
+                    power: true,
+                    usb: false,
+                };
+            }
+
+            return None;
         }
 
         match PCSTATE {
@@ -104,8 +678,7 @@ mod task {
                 }
 
                 // turn on the USB peripheral
-                unsafe { errata::e187a() }
-                USBD::borrow_unchecked(|usbd| usbd.ENABLE.write(|w| w.ENABLE(1)));
+                unsafe { USBD::init_sequence() }
 
                 semidap::info!("enabled the USB peripheral");
 
@@ -129,7 +702,7 @@ mod task {
 
                 if *clock && *power && *usb {
                     *PCSTATE = PowerState::Ready;
-                    super::connect();
+                    super::connect_unless_deferred();
                 }
             }
 
@@ -141,16 +714,13 @@ mod task {
     }
 
     fn USBD() -> Option<()> {
-        static mut USB_STATE: usb2::State = usb2::State::Default;
-        static mut EP0_STATE: Ep0State = Ep0State::Idle;
-        #[uninit(unsafe)]
-        static mut EP2IN_BUF: Align4<[u8; 63]> = Align4([0; 63]);
-
         semidap::trace!("USBD");
 
         let event = UsbdEvent::next()?;
 
         semidap::debug!("-> {}", event);
+        #[cfg(feature = "usb-trace")]
+        super::trace(event.into());
 
         match PCSTATE {
             PowerState::Off =>
@@ -171,7 +741,7 @@ mod task {
 
                     if *clock && *power && *usb {
                         *PCSTATE = PowerState::Ready;
-                        super::connect();
+                        super::connect_unless_deferred();
                     }
                 } else {
                     #[cfg(debug_assertions)]
@@ -187,6 +757,10 @@ mod task {
                         super::suspend();
                     } else if eventcause.RESUME() != 0 {
                         super::resume()
+                    } else if eventcause.ISOOUTCRC() != 0 {
+                        // the only error condition the USBD reports; the data is still copied
+                        // to RAM so the application must discard it
+                        semidap::error!("ISO OUT: CRC error");
                     } else {
                         super::todo()
                     }
@@ -194,10 +768,14 @@ mod task {
 
                 UsbdEvent::USBRESET => {
                     semidap::info!("USB reset");
+                    // a bus reset aborts the control transfer in progress
+                    *EP0_STATE = Ep0State::Setup;
 
                     match USB_STATE {
                         usb2::State::Default | usb2::State::Address { .. } => {
                             *USB_STATE = usb2::State::Default;
+                            #[cfg(feature = "remote-wakeup")]
+                            REMOTE_WAKEUP.store(false, Ordering::Relaxed);
                         }
 
                         usb2::State::Configured { .. } => {
@@ -209,34 +787,68 @@ mod task {
                 }
 
                 UsbdEvent::EP0SETUP => {
+                    // the status stage raises no event; the next SETUP packet is what ends it
                     #[cfg(debug_assertions)]
-                    if *EP0_STATE != Ep0State::Idle {
+                    if *EP0_STATE != Ep0State::Setup && *EP0_STATE != Ep0State::Status {
                         super::unreachable()
                     }
+                    *EP0_STATE = Ep0State::Setup;
 
                     if super::ep0setup(USB_STATE, EP0_STATE).is_err() {
-                        super::EP0STALL()
+                        super::EP0STALL();
+                        *EP0_STATE = Ep0State::Setup;
+
+                        *EP0_STALLS = EP0_STALLS.saturating_add(1);
+                        if super::MAX_EP0_STALLS != 0 && *EP0_STALLS >= super::MAX_EP0_STALLS {
+                            // the host keeps retrying requests we can't serve; assume the two
+                            // sides are out of sync and start over
+                            semidap::error!(
+                                "EP0: {} requests stalled in a row; resetting the USB peripheral",
+                                *EP0_STALLS
+                            );
+                            *EP0_STALLS = 0;
+                            super::reset();
+                        }
+                    } else {
+                        *EP0_STALLS = 0;
+
+                        // no data stage: the request has been acknowledged
+                        if *EP0_STATE == Ep0State::Setup {
+                            *EP0_STATE = Ep0State::Status;
+                        }
                     }
                 }
 
                 UsbdEvent::EP0DATADONE => {
+                    #[cfg(feature = "ep0-debug")]
+                    super::ep0_debug_datadone(if let Ep0State::DataIn { .. } = EP0_STATE {
+                        true
+                    } else {
+                        false
+                    });
+
                     match EP0_STATE {
-                        Ep0State::Write { leftover } => {
+                        Ep0State::DataIn { leftover } => {
                             semidap::info!("EPIN0: data transmitted");
 
                             if *leftover != 0 {
                                 super::continue_epin0(leftover);
                             } else {
-                                *EP0_STATE = Ep0State::Idle;
+                                // the EP0DATADONE_EP0STATUS short started the status stage
+                                *EP0_STATE = Ep0State::Status;
                             }
                         }
 
-                        // nothing to do here; wait for ENDEPOUT0
-                        Ep0State::Read => {
-                            semidap::info!("EPIN0: data received");
+                        // the EP0DATADONE_STARTEPOUT0 short started copying the data into RAM;
+                        // wait for ENDEPOUT0
+                        Ep0State::DataOut { received: false } => {
+                            semidap::info!("EP0OUT: data received");
+                            *EP0_STATE = Ep0State::DataOut { received: true };
                         }
 
-                        Ep0State::Idle =>
+                        Ep0State::DataOut { received: true }
+                        | Ep0State::Setup
+                        | Ep0State::Status =>
                         {
                             #[cfg(debug_assertions)]
                             super::unreachable()
@@ -247,6 +859,7 @@ mod task {
                 UsbdEvent::EPDATA => {
                     let status = super::EPDATASTATUS();
 
+                    #[cfg(feature = "class-cdc")]
                     if status.EPIN2() != 0 {
                         crate::dma_end();
                         if EP2IN_STATE.load() != Ep2InState::InUse {
@@ -257,6 +870,7 @@ mod task {
                         unsafe { super::start_epin2(&mut EP2IN_BUF.0) }
                     }
 
+                    #[cfg(feature = "class-cdc")]
                     if status.EPIN1() != 0 {
                         semidap::info!("EP1IN: notification sent");
                         semidap::info!("EP2IN: fully enabled");
@@ -267,22 +881,36 @@ mod task {
                         }
                     }
 
+                    #[cfg(feature = "class-cdc")]
                     if status.EPIN2() != 0 {
                         semidap::info!("EP2IN: transfer done");
                     }
 
+                    #[cfg(feature = "class-cdc")]
                     if status.EPOUT2() != 0 {
-                        // discard received data
-                        USBD::borrow_unchecked(|usbd| {
-                            let n = usbd.SIZE_EPOUT2.read().SIZE();
-                            semidap::info!("EP2OUT: received {} bytes (discarded)", n);
-                            usbd.SIZE_EPOUT2.write(|w| w.SIZE(0))
-                        });
+                        if RX_CLAIMED.load(Ordering::Relaxed) {
+                            semidap::info!("EP2OUT: received data");
+                            EPOUT2_STATE.store(EpOut2State::DataReady);
+                        } else {
+                            // nobody is reading; discard received data
+                            USBD::borrow_unchecked(|usbd| {
+                                let n = usbd.SIZE_EPOUT2.read().SIZE();
+                                semidap::info!("EP2OUT: received {} bytes (discarded)", n);
+                                usbd.SIZE_EPOUT2.write(|w| w.SIZE(0))
+                            });
+                            DISCARDED.fetch_add(1, Ordering::Relaxed);
+                        }
                     }
 
                     if status.EPOUT3() != 0 {
                         semidap::info!("HID: received data");
-                        EPOUT3_STATE.store(EpOut3State::DataReady);
+                        if EPOUT3_SLOT.load() == Epout3Slot::Armed {
+                            // double buffered mode: free the endpoint right away
+                            unsafe { super::start_epout3_slot() }
+                        }
+                        if EPOUT3_SLOT.load() != Epout3Slot::Transfer {
+                            EPOUT3_STATE.store(EpOut3State::DataReady);
+                        }
                     }
 
                     if status.EPIN3() != 0 {
@@ -291,9 +919,26 @@ mod task {
                     }
                 }
 
+                #[cfg(feature = "class-cdc")]
+                UsbdEvent::ENDEPOUT2 => {
+                    semidap::info!("EP2OUT: received data has been copied");
+                    EPOUT2_STATE.store(EpOut2State::Done);
+                }
+
                 UsbdEvent::ENDEPOUT0 => {
                     crate::dma_end();
-                    *EP0_STATE = Ep0State::Idle;
+
+                    #[cfg(debug_assertions)]
+                    if *EP0_STATE != (Ep0State::DataOut { received: true }) {
+                        super::unreachable()
+                    }
+
+                    // the data is in RAM; acknowledge the transfer
+                    USBD::borrow_unchecked(|usbd| {
+                        usbd.SHORTS.rmw(|_, w| w.EP0DATADONE_STARTEPOUT0(0));
+                    });
+                    super::ep0status();
+                    *EP0_STATE = Ep0State::Status;
                 }
 
                 UsbdEvent::ENDEPIN3 => {
@@ -303,9 +948,27 @@ mod task {
 
                 UsbdEvent::ENDEPOUT3 => {
                     semidap::info!("HID: received data has been copied");
-                    EPOUT3_STATE.store(EpOut3State::Done);
+                    if EPOUT3_SLOT.load() == Epout3Slot::Transfer {
+                        EPOUT3_SLOT.store(Epout3Slot::Filled);
+                    } else {
+                        EPOUT3_STATE.store(EpOut3State::Done);
+                    }
                 }
 
+                #[cfg(any(feature = "class-cdc", feature = "dfu", feature = "iso"))]
+                UsbdEvent::SOF => {
+                    #[cfg(feature = "class-cdc")]
+                    super::epin1_sof();
+                    #[cfg(feature = "dfu")]
+                    super::dfu_sof();
+                    #[cfg(feature = "iso")]
+                    super::isoin_sof();
+                }
+
+                #[cfg(feature = "iso")]
+                UsbdEvent::ENDISOIN => super::isoin_end(),
+
+                #[cfg(feature = "class-cdc")]
                 UsbdEvent::TxWrite => unsafe { super::start_epin2(&mut EP2IN_BUF.0) },
             },
         }
@@ -314,364 +977,728 @@ mod task {
     }
 }
 
-fn ep0setup(usb_state: &mut usb2::State, ep_state: &mut Ep0State) -> Result<(), ()> {
-    let bmrequesttype = BMREQUESTTYPE();
-    let brequest = BREQUEST();
-    let wvalue = WVALUE();
-    let windex = WINDEX();
-    let wlength = WLENGTH();
+/// A SETUP packet that could not be parsed into a supported request
+#[cfg_attr(feature = "text", derive(ufmt::derive::uDebug))]
+#[derive(Clone, Copy, PartialEq, binDebug)]
+pub struct RequestParseError {
+    /// The `bmRequestType` field of the SETUP packet
+    pub bmrequesttype: u8,
+    /// The `bRequest` field of the SETUP packet
+    pub brequest: u8,
+    /// The `wValue` field of the SETUP packet
+    pub wvalue: u16,
+    /// The `wIndex` field of the SETUP packet
+    pub windex: u16,
+    /// The `wLength` field of the SETUP packet
+    pub wlength: u16,
+}
 
-    let req = Request::parse(bmrequesttype, brequest, wvalue, windex, wlength).map_err(|_| {
-        semidap::error!(
-            "EP0SETUP: unknown request (bmrequesttype={}, brequest={}, wvalue={}, windex={}, wlength={})",
-            bmrequesttype,
-            brequest,
-            wvalue,
-            windex,
-            wlength
-        );
-    })?;
+display!(RequestParseError);
 
-    match req {
-        Request::Standard(req) => std_req(usb_state, ep_state, req)?,
+fn ep0setup(usb_state: &mut usb2::State, ep_state: &mut Ep0State) -> Result<(), ()> {
+    let setup = control::Setup {
+        bmrequesttype: BMREQUESTTYPE(),
+        brequest: BREQUEST(),
+        wvalue: WVALUE(),
+        windex: WINDEX(),
+        wlength: WLENGTH(),
+    };
 
-        Request::Acm(req) => match *usb_state {
-            usb2::State::Configured { .. } => acm_req(ep_state, req)?,
+    #[cfg(feature = "usb-trace")]
+    trace_request(
+        setup.bmrequesttype,
+        setup.brequest,
+        setup.wvalue,
+        setup.windex,
+        setup.wlength,
+    );
 
-            _ => {
-                semidap::error!("received ACM request but device is not yet Configured");
-                return Err(());
-            }
+    let descs = control::Descriptors {
+        device: &DEVICE_DESC,
+        configs: &CONFIGS,
+        config_descs: &CONFIG_DESC_SLICES,
+        strings: &STRING_DESCS,
+        string_offsets: &STRING_OFFSETS,
+        // NOTE(unsafe) only written while the USBD interrupt is masked
+        provider: unsafe { DESCRIPTOR_PROVIDER },
+        max_alt_setting: &MAX_ALT_SETTING,
+        self_powered: SELF_POWERED,
+        cdc_iface: if cfg!(feature = "class-cdc") {
+            Some(CDC_IFACE)
+        } else {
+            None
         },
-
-        Request::Hid(req) => match *usb_state {
-            usb2::State::Configured { .. } => hid_req(req)?,
-
-            _ => {
-                semidap::error!("received HID request but device is not yet Configured");
-                return Err(());
-            }
+        hid_iface: if cfg!(feature = "hid") {
+            Some(HID_IFACE)
+        } else {
+            None
         },
-    }
-
-    Ok(())
-}
-
-fn std_req(
-    usb_state: &mut usb2::State,
-    ep_state: &mut Ep0State,
-    req: StandardRequest,
-) -> Result<(), ()> {
-    match req {
-        StandardRequest::GetDescriptor { descriptor, length } => {
-            semidap::info!("GET_DESCRIPTOR [{}] ..", length as u8);
-
-            match descriptor {
-                GetDescriptor::Device => {
-                    semidap::info!("GET_DESCRIPTOR Device");
+        dfu_iface: if cfg!(feature = "dfu") {
+            Some(DFU_IFACE)
+        } else {
+            None
+        },
+    };
 
-                    start_epin0(
-                        DEVICE_DESC.get(..length.into()).unwrap_or(&DEVICE_DESC),
-                        ep_state,
-                    );
-                }
+    #[cfg(feature = "remote-wakeup")]
+    let remote_wakeup = Some(REMOTE_WAKEUP.load(Ordering::Relaxed));
+    #[cfg(not(feature = "remote-wakeup"))]
+    let remote_wakeup = None;
+
+    let device = control::Device {
+        state: usb_state,
+        // NOTE(unsafe) only written from the USB tasks
+        alt_settings: unsafe { &ALT_SETTINGS },
+        remote_wakeup,
+        is_halted,
+    };
 
-                GetDescriptor::DeviceQualifier => {
-                    semidap::warn!("GET_DESCRIPTOR DeviceQualifier is not supported");
-                    return Err(());
-                }
+    let response = control::handle(setup, &descs, &device).map_err(|e| match e {
+        control::Stall::UnknownRequest => {
+            let e = RequestParseError {
+                bmrequesttype: setup.bmrequesttype,
+                brequest: setup.brequest,
+                wvalue: setup.wvalue,
+                windex: setup.windex,
+                wlength: setup.wlength,
+            };
+            semidap::error!("EP0SETUP: unknown request {}", e);
+        }
 
-                GetDescriptor::Configuration { index } => {
-                    semidap::info!("GET_DESCRIPTOR Configuration {}", index);
+        // hosts probe string indices so this is not an error; STALL quietly
+        control::Stall::NoDescriptor => {
+            semidap::info!("EP0SETUP: descriptor doesn't exist (stalled)");
+        }
 
-                    if index == 0 {
-                        start_epin0(
-                            CONFIG_DESC.get(..length.into()).unwrap_or(&CONFIG_DESC),
-                            ep_state,
-                        );
-                    } else {
-                        semidap::error!("out of bounds GET_DESCRIPTOR Configuration request");
-                        return Err(());
-                    }
-                }
+        _ => {
+            semidap::error!("EP0SETUP: {} (stalled)", e);
+        }
+    })?;
 
-                GetDescriptor::String { .. } => {
-                    semidap::error!("requested string descriptor doesn't exist");
-                    return Err(());
-                }
+    match response {
+        control::Response::Data(bytes) => start_epin0(bytes, ep_state),
 
-                _ => {
-                    semidap::error!("unsupported GET_DESCRIPTOR");
-                    todo();
-                }
+        control::Response::Value(value) => {
+            let bytes = value.as_bytes();
+            unsafe {
+                STATUS[..bytes.len()].copy_from_slice(bytes);
+                start_epin0(&STATUS[..bytes.len()], ep_state);
             }
         }
 
-        StandardRequest::SetAddress {
-            address: new_address,
-        } => {
-            // nothing to do here; the hardware will complete the transaction
-            semidap::info!(
-                "SET_ADDRESS {}",
-                new_address.map(|nz| nz.get()).unwrap_or(0)
-            );
+        #[cfg(feature = "class-cdc")]
+        control::Response::GetLineCoding => {
+            semidap::info!("ACM: GET_LINE_CODING");
 
-            match *usb_state {
-                usb2::State::Default => {
-                    if let Some(address) = new_address {
-                        // move to the Address state
-                        *usb_state = usb2::State::Address(address);
+            start_epin0(unsafe { &LINE_CODING }, ep_state);
+        }
 
-                        semidap::info!("moving to the Address state");
-                    } else {
-                        // stay in the Default state
-                    }
-                }
+        #[cfg(feature = "class-cdc")]
+        control::Response::SetLineCoding => {
+            semidap::info!("ACM: SET_LINE_CODING");
 
-                usb2::State::Address(curr_address) => {
-                    if let Some(new_address) = new_address {
-                        if new_address != curr_address {
-                            *usb_state = usb2::State::Address(new_address);
+            accept_line_coding(ep_state);
+        }
 
-                            semidap::info!("changing host assigned address");
-                        }
-                    } else {
-                        *usb_state = usb2::State::Default;
+        // `cdc_iface` is `None` so `handle` stalls all ACM requests
+        #[cfg(not(feature = "class-cdc"))]
+        control::Response::GetLineCoding | control::Response::SetLineCoding => unreachable(),
 
-                        semidap::info!("returning to the Default state");
-                    }
-                }
+        control::Response::Status(change) => {
+            apply(usb_state, change);
+
+            // issue a status stage to acknowledge the request
+            ep0status()
+        }
 
-                usb2::State::Configured { .. } => {
-                    semidap::error!("invalid request in the Configured state");
-                    return Err(());
+        control::Response::SetAddress(state) => {
+            match state {
+                usb2::State::Address(address) => {
+                    semidap::info!("SET_ADDRESS {}", address.get())
                 }
+                _ => semidap::info!("SET_ADDRESS 0"),
             }
 
             // nothing else to do here; the hardware will complete the transaction
+            *usb_state = state;
         }
+    }
 
-        StandardRequest::SetConfiguration { value } => {
-            semidap::info!(
-                "SET_CONFIGURATION {}",
-                value.map(|nz| nz.get()).unwrap_or(0)
-            );
-
-            match *usb_state {
-                usb2::State::Default => {
-                    semidap::error!("invalid request in the Default state");
-                    return Err(());
-                }
+    Ok(())
+}
 
-                usb2::State::Address(address) => {
-                    if let Some(value) = value {
-                        if value == CONFIG_VAL {
-                            semidap::info!("moving to the Configured state");
-                            *usb_state = usb2::State::Configured { address, value };
+// carries out a change requested by the host; the status stage has not started yet
+fn apply(usb_state: &mut usb2::State, change: control::Change) {
+    match change {
+        control::Change::None => {}
+
+        control::Change::Configure(state, config) => {
+            let configured = match *usb_state {
+                usb2::State::Configured { .. } => true,
+                _ => false,
+            };
+            *usb_state = state;
+
+            // all interfaces start in their default alternate setting
+            unsafe { ALT_SETTINGS = [0; NIFACES] }
+            #[cfg(feature = "iso")]
+            isoin_disable();
+
+            enable_endpoints(config);
+
+            if configured {
+                // NOTE the endpoints of both configurations are already running; only the set of
+                // enabled endpoints changes
+                semidap::info!("switching configurations");
+                return;
+            }
 
-                            USBD::borrow_unchecked(|usbd| {
-                                usbd.EPINEN.write(|w| w.IN0(1).IN1(1).IN2(1).IN3(1));
-                                // TODO? Rx support -- host sends back junk when Tx is used though
-                                usbd.EPOUTEN.write(|w| w.OUT0(1).OUT3(1));
+            semidap::info!("moving to the Configured state");
 
-                                EPIN3_STATE.store(EpIn3State::Idle);
+            #[cfg(feature = "class-cdc")]
+            {
+                // start accepting data on EPOUT2
+                USBD::borrow_unchecked(|usbd| usbd.SIZE_EPOUT2.write(|w| w.SIZE(0)));
 
-                                // start accepting data on EPOUT3
-                                usbd.SIZE_EPOUT3.write(|w| w.SIZE(0));
+                // send a SerialState notification
+                send_serial_state();
+            }
 
-                                // send a SerialState notification
-                                start_epin1(&SERIAL_STATE.0);
-                            })
-                        } else {
-                            semidap::error!("requested configuration is not supported");
-                            return Err(());
-                        }
-                    } else {
-                        // stay in the Address state
-                    }
-                }
+            #[cfg(feature = "hid")]
+            {
+                EPIN3_STATE.store(EpIn3State::Idle);
 
-                usb2::State::Configured {
-                    address,
-                    value: curr_value,
-                } => {
-                    if let Some(new_value) = value {
-                        if new_value == curr_value {
-                            // no change
-                        } else {
-                            // other configurations are not supported
-                            semidap::error!("requested configuration is not supported");
-                            return Err(());
-                        }
-                    } else {
-                        // TODO disable endpoints and transfers
-                        semidap::info!("returning to the Address state");
-                        *usb_state = usb2::State::Address(address);
-                    }
-                }
+                // start accepting data on EPOUT3
+                USBD::borrow_unchecked(|usbd| usbd.SIZE_EPOUT3.write(|w| w.SIZE(0)));
             }
-
-            // issue a status stage to acknowledge the request
-            ep0status()
         }
 
-        StandardRequest::ClearFeature(..) => {
-            semidap::error!("CLEAR_FEATURE (..)");
-            return Err(());
+        control::Change::Deconfigure(state) => {
+            // TODO cancel transfers
+            semidap::info!("returning to the Address state");
+            disable_endpoints();
+            *usb_state = state;
+            #[cfg(feature = "iso")]
+            isoin_disable();
+            // EP1IN is now disabled
+            #[cfg(feature = "class-cdc")]
+            if EPIN1_DEFERRED.swap(false, Ordering::Relaxed) {
+                release_sof();
+            }
+            #[cfg(feature = "remote-wakeup")]
+            REMOTE_WAKEUP.store(false, Ordering::Relaxed);
         }
 
-        StandardRequest::GetConfiguration => {
-            semidap::error!("GET_CONFIGURATION");
-            return Err(());
-        }
+        #[cfg(feature = "remote-wakeup")]
+        control::Change::RemoteWakeup(enabled) => {
+            semidap::info!("DEVICE_REMOTE_WAKEUP {}", enabled as u8);
 
-        StandardRequest::GetInterface { .. } => {
-            semidap::error!("GET_INTERFACE (..)");
-            return Err(());
+            REMOTE_WAKEUP.store(enabled, Ordering::Relaxed);
         }
 
-        StandardRequest::GetStatus(..) => {
-            semidap::error!("GET_STATUS (..)");
-            return Err(());
-        }
+        // `remote_wakeup` is `None` so `handle` stalls these requests
+        #[cfg(not(feature = "remote-wakeup"))]
+        control::Change::RemoteWakeup(_) => unreachable(),
 
-        StandardRequest::SetDescriptor { .. } => {
-            semidap::error!("SET_DESCRIPTOR (..)");
-            return Err(());
-        }
+        control::Change::Halt(ep, halt) => {
+            semidap::info!("ENDPOINT_HALT {} {}", ep_address(ep), halt as u8);
 
-        StandardRequest::SetFeature(..) => {
-            semidap::error!("SET_FEATURE (..)");
-            return Err(());
+            set_halt(ep, halt)
         }
 
-        StandardRequest::SetInterface { .. } => {
-            semidap::error!("SET_INTERFACE (..)");
-            return Err(());
-        }
+        control::Change::AltSetting {
+            interface,
+            alternate,
+        } => {
+            semidap::info!("SET_INTERFACE {} {}", interface, alternate);
 
-        StandardRequest::SynchFrame { .. } => {
-            semidap::error!("SYNCH_FRAME (..)");
-            return Err(());
+            set_alt_setting(interface, alternate)
         }
-    }
 
-    Ok(())
-}
+        #[cfg(feature = "dfu")]
+        control::Change::DfuDetach => dfu_detach(),
 
-fn acm_req(ep_state: &mut Ep0State, req: acm::Request) -> Result<(), ()> {
-    if req.interface != CDC_IFACE {
-        semidap::error!("ACM request sent to the wrong interface");
-        return Err(());
+        // `dfu_iface` is `None` so `handle` never returns this change
+        #[cfg(not(feature = "dfu"))]
+        control::Change::DfuDetach => unreachable(),
     }
+}
 
-    match req.kind {
-        acm::Kind::GetLineCoding => {
-            semidap::info!("ACM: GET_LINE_CODING");
-
-            start_epin0(unsafe { &LINE_CODING }, ep_state);
-        }
+/// Returns `true` if endpoint number `ep` is halted (stalled) in the given direction
+///
+/// The IN and OUT endpoints that share an endpoint number are halted independently. Endpoint 0 and
+/// endpoints that are not used by this crate are never reported as halted
+pub fn is_halted(ep: u8, dir: usb2::Direction) -> bool {
+    // NOTE(borrow_unchecked) single-instruction read with no side effects
+    USBD::borrow_unchecked(|usbd| {
+        let status = match (dir, ep) {
+            (usb2::Direction::In, 1) => usbd.HALTED_EPIN1.read().GETSTATUS(),
+            (usb2::Direction::In, 2) => usbd.HALTED_EPIN2.read().GETSTATUS(),
+            (usb2::Direction::In, 3) => usbd.HALTED_EPIN3.read().GETSTATUS(),
+            (usb2::Direction::Out, 1) => usbd.HALTED_EPOUT1.read().GETSTATUS(),
+            (usb2::Direction::Out, 2) => usbd.HALTED_EPOUT2.read().GETSTATUS(),
+            (usb2::Direction::Out, 3) => usbd.HALTED_EPOUT3.read().GETSTATUS(),
+            _ => 0,
+        };
 
-        acm::Kind::SetLineCoding => {
-            semidap::info!("ACM: SET_LINE_CODING");
+        status != 0
+    })
+}
 
-            if *ep_state != Ep0State::Idle {
-                #[cfg(debug_assertions)]
-                unreachable()
-            }
+/// Returns the number of the current USB frame
+///
+/// The host starts a new frame, with a Start-Of-Frame (SOF) packet, every millisecond. The frame
+/// number is 11 bits wide so it wraps around every 2,048 ms
+pub fn frame_number() -> u16 {
+    // NOTE(borrow_unchecked) single-instruction read with no side effects
+    USBD::borrow_unchecked(|usbd| usbd.FRAMECNTR.read().FRAMECNTR())
+}
 
-            *ep_state = Ep0State::Read;
+/// Waits until the host has enabled endpoint number `ep` in the given direction, that is until the
+/// host has selected a configuration that uses the endpoint
+///
+/// Resolves immediately if the endpoint is already enabled. Endpoint 0 is always enabled. Note that
+/// the host can disable the endpoint again at any time, e.g. by unplugging the device
+pub async fn wait_endpoint_enabled(ep: u8, dir: usb2::Direction) {
+    crate::poll_fn(|| {
+        let enabled = match dir {
+            usb2::Direction::In => EPINEN().bits(),
+            usb2::Direction::Out => EPOUTEN().bits(),
+        };
 
-            semidap::info!("EP0OUT: accepting host data");
+        if ep == 0 || (ep <= 7 && enabled & (1 << ep) != 0) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
 
-            // accept data into `LINE_CODING` buffer
-            USBD::borrow_unchecked(|usbd| {
-                unsafe {
-                    usbd.EPOUT0_PTR
-                        .write(|w| w.PTR(LINE_CODING.as_mut_ptr() as u32));
-                    usbd.EPOUT0_MAXCNT
-                        .write(|w| w.MAXCNT(LINE_CODING.len() as u8));
-                }
-                usbd.SHORTS.rmw(|_, w| w.EP0DATADONE_STARTEPOUT0(1));
-                crate::dma_start();
-                usbd.TASKS_EP0RCVOUT.write(|w| w.TASKS_EP0RCVOUT(1))
-            });
+// `bEndpointAddress`
+fn ep_address(ep: usb2::Endpoint) -> u8 {
+    ep.number | (u8::from(ep.direction == usb2::Direction::In) << 7)
+}
+
+// stalls (`halt = true`) or un-stalls an endpoint; `control::handle` only asks for this on the
+// endpoints of the current configuration
+// NOTE must be kept in sync with `is_halted`, which reports the state set here
+fn set_halt(ep: usb2::Endpoint, halt: bool) {
+    let io = u8::from(ep.direction == usb2::Direction::In);
+    USBD::borrow_unchecked(|usbd| {
+        usbd.EPSTALL
+            .write(|w| w.EP(ep.number).IO(io).STALL(u8::from(halt)));
+
+        // clearing the halt feature also resets the data toggle to DATA0 (USB 2.0 section 9.4.5)
+        if !halt {
+            usbd.DTOGGLE.write(|w| w.EP(ep.number).IO(io).VALUE(1));
         }
+    });
+}
 
-        acm::Kind::SetControlLineState { rts, dtr } => {
-            semidap::info!(
-                "ACM: SET_CONTROL_LINE_STATE rts={} dtr={}",
-                rts as u8,
-                dtr as u8
-            );
+static RESET_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Resets the USB peripheral
+///
+/// The device detaches from the bus, the peripheral is disabled, which aborts all transfers, and
+/// all the USB state is returned to its power-on values. If the cable is still attached the
+/// peripheral is then re-enabled and the device re-attaches to the bus, after which the host
+/// enumerates it again. Use this to recover from a wedged peripheral without resetting the whole
+/// device (see `crate::reset`)
+///
+/// The reset is performed asynchronously, by the POWER interrupt handler, shortly after this
+/// function returns
+pub fn reset() {
+    RESET_REQUESTED.store(true, Ordering::Relaxed);
+    crate::pend0(crate::Interrupt0::POWER_CLOCK);
+}
 
-            // issue a status stage to acknowledge the request
-            semidap::info!("ACM request acknowledged");
-            ep0status();
+// whether the application has called `enable`
+#[cfg(feature = "deferred-connect")]
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// whether the USB peripheral is ready but the device has not attached to the bus yet
+#[cfg(feature = "deferred-connect")]
+static CONNECT_DEFERRED: AtomicBool = AtomicBool::new(false);
+
+/// Lets the device attach to the bus
+///
+/// Call this once the application is ready to answer the host's requests. If the USB peripheral
+/// is already ready the device attaches right away; otherwise it attaches as soon as the
+/// peripheral becomes ready. This stays in effect across cable re-plugs and USB resets
+#[cfg(feature = "deferred-connect")]
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+
+    if CONNECT_DEFERRED.swap(false, Ordering::Relaxed) {
+        connect();
+    }
+}
+
+// NOTE must only be called from the USB tasks
+fn connect_unless_deferred() {
+    #[cfg(feature = "deferred-connect")]
+    if !ENABLED.load(Ordering::Relaxed) {
+        semidap::info!("USB is ready; waiting for `usbd::enable` to attach to the bus");
+        CONNECT_DEFERRED.store(true, Ordering::Relaxed);
+        return;
+    }
+
+    connect();
+}
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Maximum time `shutdown` waits for the host to take the data already handed to the IN endpoints
+pub const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Cleanly detaches the device from the bus and turns the USB peripheral off
+///
+/// The data already handed to the IN endpoints is sent to the host first; then the device detaches
+/// from the bus, the peripheral is disabled and all the USB state is returned to its power-on
+/// values. This function returns once the peripheral is off. Use this before jumping to a
+/// bootloader or entering a low power mode
+///
+/// The host may never take the data, e.g. because it has suspended the bus or stopped polling the
+/// endpoints, so the wait is cut short when the bus is (or becomes) suspended and after
+/// `SHUTDOWN_DRAIN_TIMEOUT`. The data that was not sent by then is discarded
+///
+/// The device stays detached until it's re-enabled, either with `reset` or by unplugging and
+/// plugging back the cable; the host then enumerates the device again
+pub async fn shutdown() {
+    // drain the IN endpoints
+    let drained = time::with_timeout(
+        time::ticks(SHUTDOWN_DRAIN_TIMEOUT),
+        crate::poll_fn(|| {
+            if in_drained() {
+                Poll::Ready(true)
+            } else if SUSPENDED.load(Ordering::Relaxed) {
+                Poll::Ready(false)
+            } else {
+                Poll::Pending
+            }
+        }),
+    )
+    .await;
+    if drained != Ok(true) {
+        semidap::warn!("shutdown: the host did not take the pending IN data; discarding it");
+    }
+
+    SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+    crate::pend0(crate::Interrupt0::POWER_CLOCK);
+
+    // wait for the POWER handler to turn the peripheral off
+    crate::poll_fn(|| {
+        if SHUTDOWN_REQUESTED.load(Ordering::Relaxed) || peripheral_is_enabled() {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
         }
+    })
+    .await;
+}
+
+fn peripheral_is_enabled() -> bool {
+    USBD::borrow_unchecked(|usbd| usbd.ENABLE.read().ENABLE() != 0)
+}
+
+fn vbus_detected() -> bool {
+    POWER::borrow_unchecked(|power| power.USBREGSTATUS.read().VBUSDETECT() != 0)
+}
+
+// returns all the endpoints, and the rest of the protocol state that lives outside the USB
+// tasks, to their power-on state
+// NOTE must only be called from the USB tasks
+fn reset_endpoints() {
+    EPIN3_STATE.store(EpIn3State::Off);
+    EPOUT3_STATE.store(EpOut3State::Idle);
+    #[cfg(feature = "deferred-connect")]
+    CONNECT_DEFERRED.store(false, Ordering::Relaxed);
+    // NOTE disabling the peripheral stops EasyDMA so an interrupted transfer will never complete;
+    // give its buffer back to the hardware
+    if EPOUT3_SLOT.load() == Epout3Slot::Transfer {
+        EPOUT3_SLOT.store(Epout3Slot::Armed);
     }
 
-    Ok(())
+    #[cfg(feature = "class-cdc")]
+    {
+        EP2IN_STATE.store(Ep2InState::Off);
+        EPOUT2_STATE.store(EpOut2State::Idle);
+        EPIN1_LAST_SENT.store(0, Ordering::Relaxed);
+        EPIN1_DEFERRED.store(false, Ordering::Relaxed);
+    }
+
+    unsafe { ALT_SETTINGS = [0; NIFACES] }
+    #[cfg(feature = "iso")]
+    {
+        ISOIN_ENABLED.store(false, Ordering::Relaxed);
+        isoin_reset();
+    }
+    // a pending DFU detach is dropped along with the rest of the protocol state
+    #[cfg(feature = "dfu")]
+    DFU_DETACH_FRAMES.store(0, Ordering::Relaxed);
+    #[cfg(any(feature = "class-cdc", feature = "dfu", feature = "iso"))]
+    USBD::borrow_unchecked(|usbd| usbd.INTENCLR.write(|w| w.SOF(1)));
+    SUSPENDED.store(false, Ordering::Relaxed);
+    #[cfg(feature = "remote-wakeup")]
+    REMOTE_WAKEUP.store(false, Ordering::Relaxed);
+}
+
+static mut DESCRIPTOR_PROVIDER: Option<fn(GetDescriptor) -> Option<&'static [u8]>> = None;
+
+/// Registers a function that provides descriptors at runtime
+///
+/// `f` is called on every GET_DESCRIPTOR request, from the USBD interrupt handler; if it returns
+/// `Some` the returned bytes are sent to the host (truncated to the requested length), otherwise
+/// the request is served from the static descriptors generated at build time. This can be used to
+/// e.g. serve a serial number string derived from `deviceid` (see also `STRING_OFFSETS` in the
+/// generated `descs.rs` for the string indices already in use).
+///
+/// Ownership requirements of the returned bytes:
+///
+/// - they must stay valid, and not be modified, until the transfer to the host completes. In
+///   practice this means they must be `static` and fully initialized before they are handed out
+/// - they must live in RAM because EasyDMA can't read Flash; in particular a `static` that's not
+///   `mut` may be placed in Flash unless it has a `#[link_section = ".data.*"]` attribute
+/// - they must be a complete descriptor, including the `bLength` and `bDescriptorType` fields
+///
+/// Calling this function again replaces the previous provider
+pub fn set_descriptor_provider(f: fn(GetDescriptor) -> Option<&'static [u8]>) {
+    // NOTE(unsafe) not nested; the USBD interrupt handler reads the provider
+    unsafe { crate::atomic1(crate::Interrupt1::USBD, || DESCRIPTOR_PROVIDER = Some(f)) }
+}
+
+fn enable_endpoints(config: &control::Config) {
+    USBD::borrow_unchecked(|usbd| {
+        usbd.EPINEN.write(|w| {
+            *w = epinen::W::from_bits(config.epinen);
+            w
+        });
+        usbd.EPOUTEN.write(|w| {
+            *w = epouten::W::from_bits(config.epouten);
+            w
+        });
+    })
+}
+
+// disables all the endpoints but the control one
+fn disable_endpoints() {
+    USBD::borrow_unchecked(|usbd| {
+        usbd.EPINEN.write(|w| w.IN0(1));
+        usbd.EPOUTEN.write(|w| w.OUT0(1));
+    })
+}
+
+// enables the SOF interrupt, if it's not already enabled
+// NOTE must only be called from the USB tasks
+#[cfg(feature = "class-cdc")]
+fn acquire_sof() {
+    USBD::borrow_unchecked(|usbd| {
+        // NOTE don't discard an event that another user of the interrupt has not seen yet
+        if usbd.INTEN.read().SOF() == 0 {
+            usbd.EVENTS_SOF.zero();
+            unsafe { usbd.INTENSET.write(|w| w.SOF(1)) }
+        }
+    })
 }
 
-fn hid_req(req: hid::Request) -> Result<(), ()> {
-    if req.interface != HID_IFACE {
-        semidap::error!("HID request sent to the wrong interface");
-        return Err(());
+// disables the SOF interrupt, unless another of its users still needs it
+// NOTE must only be called from the USB tasks
+#[cfg(any(feature = "class-cdc", feature = "iso"))]
+fn release_sof() {
+    #[cfg(feature = "class-cdc")]
+    if EPIN1_DEFERRED.load(Ordering::Relaxed) {
+        return;
     }
 
-    match req.kind {
-        hid::Kind::SetIdle {
-            duration,
-            report_id,
-        } => {
-            semidap::info!(
-                "HID: SET_IDLE dur={} report={}",
-                duration.map(|nz| nz.get()).unwrap_or(0),
-                report_id.map(|nz| nz.get()).unwrap_or(0),
-            );
+    #[cfg(feature = "dfu")]
+    if dfu_detach_pending() {
+        return;
+    }
 
-            ep0status()
+    #[cfg(feature = "iso")]
+    if ISOIN_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    USBD::borrow_unchecked(|usbd| usbd.INTENCLR.write(|w| w.SOF(1)));
+}
+
+// response to GET_STATUS, GET_CONFIGURATION and GET_INTERFACE requests; must live in RAM
+// NOTE only accessed from the USBD interrupt handler
+static mut STATUS: [u8; 2] = [0; 2];
+
+// the current alternate setting of each interface
+// NOTE only accessed from the USB tasks
+static mut ALT_SETTINGS: [u8; NIFACES] = [0; NIFACES];
+
+// `control::handle` has checked that the interface has this alternate setting
+fn set_alt_setting(interface: u8, alternate: u8) {
+    // NOTE the endpoints of interfaces without alternate settings are enabled on
+    // SET_CONFIGURATION; only the isochronous interface enables or disables its endpoint here
+    #[cfg(feature = "iso")]
+    if interface == ISO_IFACE {
+        if alternate == 0 {
+            isoin_disable()
+        } else {
+            isoin_enable()
         }
+    }
+
+    unsafe { ALT_SETTINGS[usize::from(interface)] = alternate }
+}
 
-        hid::Kind::GetDescriptor { descriptor, length } => match descriptor {
-            hid::GetDescriptor::Report { index } => {
-                semidap::info!("HID: GET_DESCRIPTOR REPORT {} [{}]", index, length);
+// SET_LINE_CODING data stage
+#[cfg(feature = "class-cdc")]
+fn accept_line_coding(ep_state: &mut Ep0State) {
+    if *ep_state != Ep0State::Setup {
+        #[cfg(debug_assertions)]
+        unreachable()
+    }
 
-                // FIXME we should return a valid HID report descriptor here but this seems enough
-                // to use `hidapi` with this device on Linux at least
+    *ep_state = Ep0State::DataOut { received: false };
 
-                return Err(());
-            }
-        },
+    semidap::info!("EP0OUT: accepting host data");
+
+    // accept data into `LINE_CODING` buffer
+    USBD::borrow_unchecked(|usbd| {
+        unsafe {
+            usbd.EPOUT0_PTR
+                .write(|w| w.PTR(ram_ptr(DmaEndpoint::EPOUT0, LINE_CODING.as_mut_ptr())));
+            usbd.EPOUT0_MAXCNT
+                .write(|w| w.MAXCNT(LINE_CODING.len() as u8));
+        }
+        usbd.SHORTS.rmw(|_, w| w.EP0DATADONE_STARTEPOUT0(1));
+        crate::dma_start();
+        usbd.TASKS_EP0RCVOUT.trigger()
+    });
+}
+
+/// Value written to `GPREGRET` before resetting the device; the bootloader looks for this value to
+/// decide whether to enter DFU mode
+#[cfg(feature = "dfu")]
+pub const DFU_MAGIC: u8 = 0xB1;
+
+// schedules the detach that follows the DFU_DETACH request
+#[cfg(feature = "dfu")]
+fn dfu_detach() {
+    semidap::info!("DFU: DETACH");
+
+    // give the host some time to see the status stage before we drop off the bus. The USBD has no
+    // event for the end of the status stage so count frames instead; see `dfu_sof`
+    DFU_DETACH_FRAMES.store(DFU_DETACH_DELAY, Ordering::Relaxed);
+    USBD::borrow_unchecked(|usbd| {
+        usbd.EVENTS_SOF.zero();
+        unsafe { usbd.INTENSET.write(|w| w.SOF(1)) }
+    });
+}
+
+/// Number of frames (1 ms each) between the DFU_DETACH request and the detach
+#[cfg(feature = "dfu")]
+const DFU_DETACH_DELAY: u8 = 10;
+
+// frames left until the device detaches after a DFU_DETACH request; 0 if no detach is pending
+#[cfg(feature = "dfu")]
+static DFU_DETACH_FRAMES: AtomicU8 = AtomicU8::new(0);
+
+#[cfg(feature = "dfu")]
+fn dfu_detach_pending() -> bool {
+    DFU_DETACH_FRAMES.load(Ordering::Relaxed) != 0
+}
+
+// runs on every SOF
+#[cfg(feature = "dfu")]
+fn dfu_sof() {
+    match DFU_DETACH_FRAMES.load(Ordering::Relaxed) {
+        0 => {}
+        1 => dfu_reboot(),
+        n => DFU_DETACH_FRAMES.store(n - 1, Ordering::Relaxed),
     }
+}
 
-    Ok(())
+#[cfg(feature = "dfu")]
+fn dfu_reboot() -> ! {
+    // NOTE(borrow_unchecked) single instruction write; we are about to reset the device anyway
+    POWER::borrow_unchecked(|power| power.GPREGRET.write(|w| w.GPREGRET(DFU_MAGIC)));
+
+    disconnect();
+
+    crate::reset()
+}
+
+// when the last CDC notification was sent, in ms since boot, plus one; 0 means never
+#[cfg(feature = "class-cdc")]
+static EPIN1_LAST_SENT: AtomicU32 = AtomicU32::new(0);
+
+// whether a SerialState notification came too early and is waiting to be sent; see `epin1_sof`
+// NOTE only accessed from the USB tasks
+#[cfg(feature = "class-cdc")]
+static EPIN1_DEFERRED: AtomicBool = AtomicBool::new(false);
+
+// sends a SerialState notification or, if the previous one was sent less than
+// `CDC_NOTIFY_INTERVAL` ms ago, defers it to a later SOF. EP2IN is only fully enabled once the
+// notification has been sent (see the EPDATA handler) so it must not be dropped
+// NOTE must only be called from the USB tasks
+#[cfg(feature = "class-cdc")]
+fn send_serial_state() {
+    let now = time::uptime().as_millis() as u32;
+    let last = EPIN1_LAST_SENT.load(Ordering::Relaxed);
+    if last != 0 && now.wrapping_sub(last - 1) < CDC_NOTIFY_INTERVAL {
+        if !EPIN1_DEFERRED.swap(true, Ordering::Relaxed) {
+            semidap::info!(
+                "EP1IN: notification deferred; the host polls every {} ms",
+                CDC_NOTIFY_INTERVAL
+            );
+            acquire_sof();
+        }
+        return;
+    }
+    EPIN1_LAST_SENT.store(now.wrapping_add(1), Ordering::Relaxed);
+
+    if EPIN1_DEFERRED.swap(false, Ordering::Relaxed) {
+        release_sof();
+    }
+
+    start_epin1(&SERIAL_STATE.0);
 }
 
+// runs on every SOF
+#[cfg(feature = "class-cdc")]
+fn epin1_sof() {
+    if EPIN1_DEFERRED.load(Ordering::Relaxed) {
+        send_serial_state();
+    }
+}
+
+#[cfg(feature = "class-cdc")]
 fn start_epin1(buf: &'static [u8]) {
-    let n = cmp::min(buf.len(), 64) as u8;
+    // notifications longer than a packet are truncated
+    let n = cmp::min(buf.len(), usize::from(DmaEndpoint::EPIN1.max_packet_size())) as u8;
     semidap::info!("EP1IN: sending {} bytes", n);
 
     USBD::borrow_unchecked(|usbd| {
-        usbd.EPIN1_PTR.write(|w| w.PTR(buf.as_ptr() as u32));
+        usbd.EPIN1_PTR
+            .write(|w| w.PTR(dma_ptr(DmaEndpoint::EPIN1, buf.as_ptr())));
         usbd.EPIN1_MAXCNT.write(|w| w.MAXCNT(n));
         crate::dma_start();
-        usbd.TASKS_STARTEPIN1.write(|w| w.TASKS_STARTEPIN(1));
+        usbd.TASKS_STARTEPIN1.trigger();
     });
 }
 
 /// # Safety
 /// This hands `buf` to the DMA. Caller must manually enforce that aliasing rules are respected
+#[cfg(feature = "class-cdc")]
 unsafe fn start_epin2(buf: &mut [u8; 63]) {
-    let n = TX_BUF.read(buf) as u8;
-    if n != 0 {
+    let n = TX_BUF.read(buf);
+    if n == 0 {
+        EP2IN_STATE.store(Ep2InState::Idle);
+    } else if let Some(n) = maxcnt(DmaEndpoint::EPIN2, n) {
         semidap::info!("EP2IN: sending {} bytes", n);
         USBD::borrow_unchecked(|usbd| {
-            usbd.EPIN2_PTR.write(|w| w.PTR(buf.as_ptr() as u32));
+            usbd.EPIN2_PTR
+                .write(|w| w.PTR(dma_ptr(DmaEndpoint::EPIN2, buf.as_ptr())));
             usbd.EPIN2_MAXCNT.write(|w| w.MAXCNT(n));
             crate::dma_start();
-            usbd.TASKS_STARTEPIN2.write(|w| w.TASKS_STARTEPIN(1));
+            usbd.TASKS_STARTEPIN2.trigger();
         });
         EP2IN_STATE.store(Ep2InState::InUse);
     } else {
@@ -682,21 +1709,27 @@ unsafe fn start_epin2(buf: &mut [u8; 63]) {
 fn start_epin0(bytes: &'static [u8], ep_state: &mut Ep0State) {
     #[cfg(debug_assertions)]
     semidap::assert!(
-        *ep_state == Ep0State::Idle,
+        *ep_state == Ep0State::Setup,
         "tried to start a control read transfer before the previous one finished"
     );
 
     let len = bytes.len() as u16;
 
+    #[cfg(feature = "ep0-debug")]
+    {
+        EP0_LEN.store(len.into(), Ordering::Relaxed);
+        EP0_SENT.store(0, Ordering::Relaxed);
+    }
+
     let maxcnt = if len <= MAX_PACKET_SIZE0.into() {
         // done in a single transfer
         short_ep0datadone_ep0status();
-        *ep_state = Ep0State::Write { leftover: 0 };
+        *ep_state = Ep0State::DataIn { leftover: 0 };
         len as u8
     } else {
         unshort_ep0datadone_ep0status();
         let maxcnt = MAX_PACKET_SIZE0;
-        *ep_state = Ep0State::Write {
+        *ep_state = Ep0State::DataIn {
             leftover: len - u16::from(maxcnt),
         };
         maxcnt
@@ -706,12 +1739,55 @@ fn start_epin0(bytes: &'static [u8], ep_state: &mut Ep0State) {
 
     USBD::borrow_unchecked(|usbd| {
         usbd.EPIN0_MAXCNT.write(|w| w.MAXCNT(maxcnt));
-        usbd.EPIN0_PTR.write(|w| w.PTR(bytes.as_ptr() as u32));
+        usbd.EPIN0_PTR
+            .write(|w| w.PTR(ram_ptr(DmaEndpoint::EPIN0, bytes.as_ptr())));
 
-        usbd.TASKS_STARTEPIN0.write(|w| w.TASKS_STARTEPIN(1));
+        usbd.TASKS_STARTEPIN0.trigger();
     })
 }
 
+/// EP0 diagnostics
+#[cfg(feature = "ep0-debug")]
+#[derive(Clone, Copy, binDebug)]
+pub struct Ep0Debug {
+    /// Total size, in bytes, of the last control read (device to host) transfer
+    pub len: u32,
+    /// How many bytes of the last control read transfer the host has received
+    ///
+    /// If smaller than `len` when the next SETUP packet arrives the host aborted the transfer
+    pub sent: u32,
+    /// Number of EP0DATADONE events since boot
+    pub datadone: u32,
+}
+
+#[cfg(feature = "ep0-debug")]
+static EP0_LEN: AtomicU32 = AtomicU32::new(0);
+#[cfg(feature = "ep0-debug")]
+static EP0_SENT: AtomicU32 = AtomicU32::new(0);
+#[cfg(feature = "ep0-debug")]
+static EP0_DATADONE: AtomicU32 = AtomicU32::new(0);
+
+/// Returns the progress of the last EP0 transfer
+#[cfg(feature = "ep0-debug")]
+pub fn ep0_debug() -> Ep0Debug {
+    Ep0Debug {
+        len: EP0_LEN.load(Ordering::Relaxed),
+        sent: EP0_SENT.load(Ordering::Relaxed),
+        datadone: EP0_DATADONE.load(Ordering::Relaxed),
+    }
+}
+
+// NOTE must only be called from the USBD interrupt handler
+#[cfg(feature = "ep0-debug")]
+fn ep0_debug_datadone(write: bool) {
+    EP0_DATADONE.fetch_add(1, Ordering::Relaxed);
+
+    if write {
+        let amount = USBD::borrow_unchecked(|usbd| usbd.EPIN0_AMOUNT.read().AMOUNT());
+        EP0_SENT.fetch_add(amount.into(), Ordering::Relaxed);
+    }
+}
+
 fn continue_epin0(leftover: &mut u16) {
     USBD::borrow_unchecked(|usbd| {
         usbd.EPIN0_PTR
@@ -729,16 +1805,18 @@ fn continue_epin0(leftover: &mut u16) {
             *leftover -= max_packet_size0;
         }
 
-        usbd.TASKS_STARTEPIN0.write(|w| w.TASKS_STARTEPIN(1));
+        usbd.TASKS_STARTEPIN0.trigger();
     })
 }
 
 /// CDC ACM transmit (device to host) endpoint
+#[cfg(feature = "class-cdc")]
 pub struct Tx {
     _not_send_or_sync: NotSendOrSync,
 }
 
 /// Claims the USB CDC ACM interface
+#[cfg(feature = "class-cdc")]
 pub fn serial() -> Tx {
     static ONCE: AtomicBool = AtomicBool::new(false);
 
@@ -754,6 +1832,7 @@ pub fn serial() -> Tx {
     }
 }
 
+#[cfg(feature = "class-cdc")]
 static TX_BUF: ring::Buffer = unsafe {
     ring::Buffer::new({
         #[link_section = ".uninit.TX_BUF"]
@@ -762,6 +1841,7 @@ static TX_BUF: ring::Buffer = unsafe {
     })
 };
 
+#[cfg(feature = "class-cdc")]
 impl Tx {
     /// Sends data to the host
     pub fn write(&mut self, bytes: &[u8]) {
@@ -771,6 +1851,315 @@ impl Tx {
             crate::pend1(Interrupt1::USBD);
         }
     }
+
+    /// Waits until all the data written so far has been handed to the host
+    ///
+    /// NOTE this waits for as long as the host has not configured the device
+    pub async fn flush(&mut self) {
+        crate::poll_fn(|| {
+            if TX_BUF.bytes_to_read() != 0 || EP2IN_STATE.load() == Ep2InState::InUse {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await
+    }
+
+    // waits until there's room in the transmit buffer and then queues as many bytes as fit
+    #[cfg(feature = "embedded-io")]
+    async fn write_some(&mut self, bytes: &[u8]) -> usize {
+        if bytes.is_empty() {
+            return 0;
+        }
+
+        let n = crate::poll_fn(|| match TX_BUF.write(bytes) {
+            0 => Poll::Pending,
+            n => Poll::Ready(n),
+        })
+        .await;
+        crate::pend1(Interrupt1::USBD);
+        n
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io_async::ErrorType for Tx {
+    type Error = core::convert::Infallible;
+}
+
+/// Byte-stream interface over the CDC ACM data IN endpoint
+///
+/// `write` returns once part of the data has been queued in the transmit buffer, which the USB
+/// interrupt handler then sends in packets of up to 63 bytes
+#[cfg(feature = "embedded-io")]
+impl embedded_io_async::Write for Tx {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(self.write_some(buf).await)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Tx::flush(self).await;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io_async::ErrorType for Rx {
+    type Error = core::convert::Infallible;
+}
+
+/// Byte-stream interface over the CDC ACM data OUT endpoint; see `Rx::read`
+#[cfg(feature = "embedded-io")]
+impl embedded_io_async::Read for Rx {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(Rx::read(self, buf).await)
+    }
+}
+
+/// Error returned by `Rx::read_transfer` when the transfer doesn't fit in the buffer
+#[cfg(feature = "class-cdc")]
+#[derive(Clone, Copy, PartialEq, binDebug)]
+pub struct TransferTooLong;
+
+/// CDC ACM receive (host to device) endpoint
+#[cfg(feature = "class-cdc")]
+pub struct Rx {
+    // position of the next unread byte in `EPOUT2_BUF`
+    pos: u8,
+    // number of bytes in `EPOUT2_BUF`
+    len: u8,
+    _not_send_or_sync: NotSendOrSync,
+}
+
+/// Claims the receive side of the USB CDC ACM interface
+///
+/// Until this is called, data sent by the host is discarded
+#[cfg(feature = "class-cdc")]
+pub fn serial_rx() -> Rx {
+    if RX_CLAIMED
+        .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+        .is_ok()
+    {
+        Rx {
+            pos: 0,
+            len: 0,
+            _not_send_or_sync: NotSendOrSync::new(),
+        }
+    } else {
+        semidap::panic!("`usbd::serial_rx` interface has already been claimed")
+    }
+}
+
+#[cfg(feature = "class-cdc")]
+static RX_CLAIMED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "class-cdc")]
+derive!(EpOut2State);
+
+#[cfg(feature = "class-cdc")]
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+enum EpOut2State {
+    #[allow(dead_code)]
+    Idle = 0,
+    DataReady = 1,
+    Done = 2,
+}
+
+#[cfg(feature = "class-cdc")]
+static EPOUT2_STATE: Atomic<EpOut2State> = Atomic::new();
+
+#[cfg(feature = "class-cdc")]
+#[link_section = ".uninit.EPOUT2_BUF"]
+static mut EPOUT2_BUF: Align4<[u8; 64]> = Align4([0; 64]);
+
+#[cfg(feature = "class-cdc")]
+impl Rx {
+    /// Receives data from the host
+    ///
+    /// Returns the number of bytes written into `buf`; this is never zero unless `buf` is empty.
+    /// Bytes that don't fit in `buf` are returned by the next `read` operation
+    pub async fn read(&mut self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+
+        if self.pos == self.len {
+            self.receive().await;
+        }
+
+        let n = cmp::min(buf.len(), usize::from(self.len - self.pos));
+        let start = usize::from(self.pos);
+        // NOTE(unsafe) the EasyDMA transfer into `EPOUT2_BUF` has completed and no other transfer
+        // will be started until all the data has been read
+        buf[..n].copy_from_slice(unsafe { &EPOUT2_BUF.0[start..start + n] });
+        self.pos += n as u8;
+        n
+    }
+
+    /// Receives a whole bulk transfer from the host
+    ///
+    /// A bulk transfer is a sequence of packets that ends with a short packet, one smaller than the
+    /// maximum packet size of the endpoint (64 bytes); when the size of the transfer is a multiple
+    /// of 64 bytes the host ends it with a zero-length packet (ZLP). This operation keeps receiving
+    /// packets until the short packet, like a bulk read does on the host side, so the application
+    /// doesn't need to track transfer boundaries. Returns the size of the transfer, which is zero
+    /// if the transfer was a lone ZLP. Data left over from a previous `read` is treated as the
+    /// start of the transfer
+    ///
+    /// If the transfer doesn't fit in `buf` then `buf` is filled up and an error is returned; the
+    /// rest of the transfer is returned by the next `read` or `read_transfer` operation
+    pub async fn read_transfer(&mut self, buf: &mut [u8]) -> Result<usize, TransferTooLong> {
+        let max = usize::from(DmaEndpoint::EPOUT2.max_packet_size());
+
+        if self.pos == self.len {
+            self.receive_packet().await;
+        }
+
+        let mut filled = 0;
+        loop {
+            let n = cmp::min(buf.len() - filled, usize::from(self.len - self.pos));
+            let start = usize::from(self.pos);
+            // NOTE(unsafe) see `read`
+            buf[filled..filled + n].copy_from_slice(unsafe { &EPOUT2_BUF.0[start..start + n] });
+            self.pos += n as u8;
+            filled += n;
+
+            if self.pos != self.len {
+                return Err(TransferTooLong);
+            }
+
+            if usize::from(self.len) < max {
+                return Ok(filled);
+            }
+
+            // a full packet; the transfer continues
+            self.receive_packet().await;
+        }
+    }
+
+    // waits for a non-empty packet and copies it into `EPOUT2_BUF`
+    async fn receive(&mut self) {
+        loop {
+            self.receive_packet().await;
+
+            // skip zero-length packets
+            if self.len != 0 {
+                return;
+            }
+        }
+    }
+
+    // waits for a packet, which may be empty, and copies it into `EPOUT2_BUF`
+    //
+    // Recovery: turning the USB peripheral off, because VBUS was removed or on a `usbd::reset`,
+    // puts the endpoint back in the `Idle` state (see `reset_endpoints`). If that happens while
+    // the data is being copied the EasyDMA transfer will never complete and waiting for the `Done`
+    // state would hang forever. Instead the interrupted transfer is given up (the data is lost)
+    // and this goes back to waiting for the next packet. This is a release-mode path, not a debug
+    // assertion: the desync is caused by bus events, not by a bug
+    async fn receive_packet(&mut self) {
+        loop {
+            // wait until the endpoint has received data
+            crate::poll_fn(|| {
+                if EPOUT2_STATE.load() == EpOut2State::DataReady {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await;
+
+            // move data from USBD to `EPOUT2_BUF`
+            let len = USBD::borrow_unchecked(|usbd| unsafe {
+                let size = usbd.SIZE_EPOUT2.read().SIZE();
+                usbd.EPOUT2_PTR
+                    .write(|w| w.PTR(dma_ptr(DmaEndpoint::EPOUT2, EPOUT2_BUF.0.as_mut_ptr())));
+                usbd.EPOUT2_MAXCNT
+                    .write(|w| w.MAXCNT(EPOUT2_BUF.0.len() as u8));
+
+                crate::dma_start();
+                usbd.TASKS_STARTEPOUT2.trigger();
+                size
+            });
+
+            // wait until transfer is done
+            let done = crate::poll_fn(|| match EPOUT2_STATE.load() {
+                EpOut2State::Done => Poll::Ready(true),
+                EpOut2State::DataReady => Poll::Pending,
+                // the endpoint was reset mid-transfer; see above
+                EpOut2State::Idle => Poll::Ready(false),
+            })
+            .await;
+            crate::dma_end();
+
+            if !done {
+                semidap::warn!("EP2OUT: endpoint reset during a transfer; packet dropped");
+                continue;
+            }
+
+            self.pos = 0;
+            self.len = len;
+            return;
+        }
+    }
+}
+
+/// Line-buffered console on top of the CDC ACM receive endpoint
+#[cfg(feature = "class-cdc")]
+pub struct SerialConsole {
+    rx: Rx,
+    // a `\r` was the last byte seen; a `\n` that follows it is not a new line
+    cr: bool,
+}
+
+#[cfg(feature = "class-cdc")]
+impl SerialConsole {
+    /// Creates a new console from the CDC ACM receive endpoint
+    pub fn new(rx: Rx) -> Self {
+        Self { rx, cr: false }
+    }
+
+    /// Reads a line into `buf` and returns its length
+    ///
+    /// Lines can end in `\r`, `\n` or `\r\n`; the line terminator is not written into `buf`.
+    /// Backspace (`0x08`) and delete (`0x7f`) remove the last byte of the line.
+    ///
+    /// If the line is longer than `buf` the bytes that don't fit are dropped and the returned line
+    /// is truncated to `buf.len()` bytes
+    pub async fn read_line(&mut self, buf: &mut [u8]) -> usize {
+        const BS: u8 = 0x08;
+        const DEL: u8 = 0x7f;
+
+        let mut len = 0;
+        loop {
+            let mut byte = 0;
+            self.rx.read(slice::from_mut(&mut byte)).await;
+
+            let cr = self.cr;
+            self.cr = byte == b'\r';
+            match byte {
+                b'\n' if cr => {}
+
+                b'\r' | b'\n' => return len,
+
+                BS | DEL => len = len.saturating_sub(1),
+
+                _ => {
+                    if let Some(slot) = buf.get_mut(len) {
+                        *slot = byte;
+                        len += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Releases the CDC ACM receive endpoint
+    pub fn free(self) -> Rx {
+        self.rx
+    }
 }
 
 /// HID OUT (host to device) endpoint
@@ -794,32 +2183,215 @@ static EPOUT3_STATE: Atomic<EpOut3State> = Atomic::new();
 impl HidOut {
     /// Receives a HID packet
     pub async fn read(&mut self, packet: &mut Packet) {
-        // wait until the endpoint has received data
-        crate::poll_fn(|| {
-            if EPOUT3_STATE.load() == EpOut3State::DataReady {
-                Poll::Ready(())
-            } else {
-                Poll::Pending
+        count_backlog();
+        self.receive(packet).await
+    }
+
+    // `read` minus the `Stats::backlogged` accounting
+    async fn receive(&mut self, packet: &mut Packet) {
+        loop {
+            // wait until the endpoint has received data
+            crate::poll_fn(|| {
+                if EPOUT3_STATE.load() == EpOut3State::DataReady {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await;
+
+            if self.transfer(packet).await {
+                return;
             }
-        })
-        .await;
+        }
+    }
+
+    /// Receives a HID packet and returns the number of the USB frame it was received in (see
+    /// `frame_number`)
+    ///
+    /// The frame number is sampled when this operation observes that the EasyDMA transfer into
+    /// `packet` has completed, not when the packet arrived on the bus. The packet waits in the
+    /// peripheral until this operation starts the transfer so the sampled frame is later than the
+    /// actual one by the time the packet spent waiting, plus the wake-up latency of the executor.
+    /// When `read_with_frame` is already waiting for the packet the skew is usually within a frame
+    pub async fn read_with_frame(&mut self, packet: &mut Packet) -> u16 {
+        self.read(packet).await;
+        frame_number()
+    }
+
+    /// Receives a HID packet, giving up if the host sends no data within `timeout`
+    ///
+    /// The timeout only covers waiting for the host. The USBD peripheral has no task to stop an
+    /// EasyDMA transfer and the transfer buffer must not be repointed while the transfer is in
+    /// progress so, once data has arrived, the transfer into `packet` always runs to completion.
+    /// This is not a problem in practice: the transfer copies data from the peripheral's internal
+    /// buffer into RAM and completes within a few microseconds. On timeout the endpoint keeps NAK-ing
+    /// the host and the data it sends next will be returned by the next `read` operation.
+    ///
+    /// NOTE the deadline is checked every time the executor wakes up; the RTC0 heartbeat wakes it
+    /// up every 125 ms so the timeout may be exceeded by up to that amount
+    pub async fn read_timeout(
+        &mut self,
+        packet: &mut Packet,
+        timeout: Duration,
+    ) -> Result<(), time::Timeout> {
+        count_backlog();
+        self.receive_timeout(packet, timeout).await
+    }
+
+    // `read_timeout` minus the `Stats::backlogged` accounting
+    async fn receive_timeout(
+        &mut self,
+        packet: &mut Packet,
+        timeout: Duration,
+    ) -> Result<(), time::Timeout> {
+        // NOTE an interrupted transfer (see `transfer`) restarts the timeout
+        loop {
+            // wait until the endpoint has received data; nothing has been started yet so this can
+            // be abandoned at any point
+            time::with_timeout(
+                time::ticks(timeout),
+                crate::poll_fn(|| {
+                    if EPOUT3_STATE.load() == EpOut3State::DataReady {
+                        Poll::Ready(())
+                    } else {
+                        Poll::Pending
+                    }
+                }),
+            )
+            .await?;
+
+            if self.transfer(packet).await {
+                return Ok(());
+            }
+        }
+    }
 
+    // NOTE must only be called when `EPOUT3_STATE` is `DataReady`
+    // returns `false` if the endpoint was reset before the transfer completed; in that case the
+    // data is lost and `packet` is left untouched. See `Rx::receive` for the conditions
+    async fn transfer(&mut self, packet: &mut Packet) -> bool {
         // move data from USBD to `packet`
-        packet.len = USBD::borrow_unchecked(|usbd| {
-            let size = usbd.SIZE_EPOUT3.read().SIZE();
-            usbd.EPOUT3_PTR
-                .write(|w| w.PTR(packet.data_ptr_mut() as u32));
-            usbd.EPOUT3_MAXCNT.write(|w| w.MAXCNT(Packet::CAPACITY + 1));
-
-            // omitted because no memory operation is performed on `packet`
-            // crate::dma_start();
-            usbd.TASKS_STARTEPOUT3.write(|w| w.TASKS_STARTEPOUT(1));
-            size
-        });
+        let size = start_epout3(packet.data_ptr_mut());
 
         // wait until transfer is done
+        let done = crate::poll_fn(|| match EPOUT3_STATE.load() {
+            EpOut3State::Done => Poll::Ready(true),
+            EpOut3State::DataReady => Poll::Pending,
+            EpOut3State::Idle => Poll::Ready(false),
+        })
+        .await;
+        crate::dma_end();
+
+        if done {
+            packet.set_len_from_dma(size);
+        } else {
+            semidap::warn!("HID: endpoint reset during a transfer; packet dropped");
+        }
+
+        done
+    }
+}
+
+// see `Stats::backlogged`
+fn count_backlog() {
+    if EPOUT3_STATE.load() == EpOut3State::DataReady {
+        BACKLOGGED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// starts moving the data received on EPOUT3 into `ptr`; returns the size of the data
+fn start_epout3(ptr: *mut u8) -> u8 {
+    USBD::borrow_unchecked(|usbd| {
+        let size = usbd.SIZE_EPOUT3.read().SIZE();
+        usbd.EPOUT3_PTR
+            .write(|w| w.PTR(dma_ptr(DmaEndpoint::EPOUT3, ptr)));
+        usbd.EPOUT3_MAXCNT.write(|w| w.MAXCNT(Packet::CAPACITY + 1));
+
+        // omitted because no memory operation is performed on the buffer
+        // crate::dma_start();
+        usbd.TASKS_STARTEPOUT3.trigger();
+        size
+    })
+}
+
+/// HID OUT endpoint with two alternating packet buffers
+///
+/// `HidOut` only frees the endpoint when `read` is called: while the application processes a
+/// packet the next one waits in the peripheral's internal buffer and the one after that is NAK-ed
+/// (and retried by the host), so at most one packet is ever in flight. In this mode a second packet
+/// buffer is handed to the hardware ahead of time and the USB interrupt moves the data into it as
+/// soon as it arrives, freeing the endpoint right away. While the application processes packet `N`
+/// packet `N + 1` sits in the armed buffer and packet `N + 2` in the peripheral, so the host is not
+/// NAK-ed as long as the application keeps up on average.
+///
+/// The gain is largest when the processing time per packet is comparable to the 1 ms interval at
+/// which a full-speed host polls the endpoint; it comes at the cost of holding one extra pool
+/// packet for as long as the endpoint is in this mode
+pub struct DoubleBufferedHidOut {
+    // the buffer that's currently owned by the hardware
+    armed: Option<Packet>,
+}
+
+derive!(Epout3Slot);
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+enum Epout3Slot {
+    // no buffer; `HidOut` mode
+    Empty = 0,
+    // a buffer is ready to receive data
+    Armed = 1,
+    // data is being moved into the buffer
+    Transfer = 2,
+    // the buffer holds a packet that the application has not picked up yet
+    Filled = 3,
+}
+
+static EPOUT3_SLOT: Atomic<Epout3Slot> = Atomic::new();
+// NOTE only meaningful when `EPOUT3_SLOT` is not `Empty`
+static mut EPOUT3_SLOT_PTR: *mut u8 = ptr::null_mut();
+static EPOUT3_SLOT_SIZE: AtomicU8 = AtomicU8::new(0);
+
+// NOTE must be called with the USBD interrupt masked (or from the USB tasks) and only when
+// `EPOUT3_SLOT` is `Armed` and data is ready
+unsafe fn start_epout3_slot() {
+    EPOUT3_SLOT.store(Epout3Slot::Transfer);
+    EPOUT3_SLOT_SIZE.store(start_epout3(EPOUT3_SLOT_PTR), Ordering::Relaxed);
+}
+
+impl DoubleBufferedHidOut {
+    /// Switches the HID OUT endpoint to double buffered mode
+    ///
+    /// NOTE this allocates the second buffer from the packet pool
+    pub async fn new(out: HidOut) -> Self {
+        // NOTE `HidOut` is a zero-sized token; `free` hands out a new one
+        drop(out);
+        let mut this = Self { armed: None };
+        this.arm(Packet::new().await);
+        this
+    }
+
+    /// Switches the endpoint back to single buffered mode
+    ///
+    /// NOTE a packet that has been received into the second buffer but not yet `read` is
+    /// discarded
+    pub fn free(mut self) -> HidOut {
+        self.disarm();
+        HidOut {
+            _not_send_or_sync: NotSendOrSync::new(),
+        }
+    }
+
+    /// Receives a HID packet
+    ///
+    /// `packet` is swapped with the buffer that holds the received data: on return `packet`
+    /// contains the received data and its old buffer is handed to the hardware to receive the
+    /// packet after the next one
+    pub async fn read(&mut self, packet: &mut Packet) {
+        // wait until the armed buffer has been filled
         crate::poll_fn(|| {
-            if EPOUT3_STATE.load() == EpOut3State::Done {
+            if EPOUT3_SLOT.load() == Epout3Slot::Filled {
                 crate::dma_end();
                 Poll::Ready(())
             } else {
@@ -827,6 +2399,203 @@ impl HidOut {
             }
         })
         .await;
+
+        let mut filled = self.armed.take().unwrap_or_else(|| unreachable());
+        filled.set_len_from_dma(EPOUT3_SLOT_SIZE.load(Ordering::Relaxed));
+        mem::swap(packet, &mut filled);
+        self.arm(filled);
+    }
+
+    fn arm(&mut self, mut packet: Packet) {
+        let ptr = packet.data_ptr_mut();
+        self.armed = Some(packet);
+
+        unsafe {
+            crate::atomic1(crate::Interrupt1::USBD, || {
+                EPOUT3_SLOT_PTR = ptr;
+                EPOUT3_SLOT.store(Epout3Slot::Armed);
+
+                // data arrived while no buffer was armed
+                if EPOUT3_STATE.load() == EpOut3State::DataReady {
+                    EPOUT3_STATE.store(EpOut3State::Idle);
+                    start_epout3_slot();
+                }
+            })
+        }
+    }
+
+    fn disarm(&mut self) {
+        // an EasyDMA transfer must not be interrupted; it completes within a few microseconds
+        loop {
+            let done = unsafe {
+                crate::atomic1(crate::Interrupt1::USBD, || {
+                    if EPOUT3_SLOT.load() == Epout3Slot::Transfer {
+                        false
+                    } else {
+                        EPOUT3_SLOT.store(Epout3Slot::Empty);
+                        true
+                    }
+                })
+            };
+
+            if done {
+                break;
+            }
+        }
+
+        crate::dma_end();
+        self.armed.take();
+    }
+}
+
+impl Drop for DoubleBufferedHidOut {
+    fn drop(&mut self) {
+        if self.armed.is_some() {
+            self.disarm();
+        }
+    }
+}
+
+/// Byte-stream view of the HID OUT endpoint
+///
+/// Packet boundaries are not preserved: the data received from the host is copied into an
+/// internal circular buffer of `HidOutRing::CAPACITY` bytes and handed out as a stream of bytes
+///
+/// Overrun policy: no data is ever dropped. A new packet is only accepted from the host when the
+/// buffer has room for a full packet; until then the endpoint NAKs the host, which retries later.
+/// A consumer that falls behind hence throttles the host instead of losing data
+pub struct HidOutRing {
+    out: HidOut,
+    buffer: [u8; HidOutRing::CAPACITY],
+    // index of the oldest byte
+    start: usize,
+    // number of bytes in the buffer
+    len: usize,
+}
+
+impl HidOutRing {
+    /// Size of the circular buffer
+    pub const CAPACITY: usize = 256;
+
+    /// Wraps the HID OUT endpoint
+    pub fn new(out: HidOut) -> Self {
+        Self {
+            out,
+            buffer: [0; HidOutRing::CAPACITY],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    /// Releases the HID OUT endpoint, discarding the buffered data
+    pub fn free(self) -> HidOut {
+        self.out
+    }
+
+    /// Returns the number of buffered bytes
+    pub fn bytes_to_read(&self) -> usize {
+        self.len
+    }
+
+    /// Reads buffered data into `buf`, waiting for the host to send data if the buffer is empty
+    ///
+    /// Returns the number of bytes read, which is never zero unless `buf` is empty
+    pub async fn read(&mut self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+
+        count_backlog();
+        self.drain(buf).await
+    }
+
+    // `read` minus the `Stats::backlogged` accounting
+    // NOTE `buf` must not be empty
+    async fn drain(&mut self, buf: &mut [u8]) -> usize {
+        // NOTE zero-length packets add nothing to the buffer
+        while self.len == 0 {
+            self.receive().await;
+        }
+
+        // take in packets that have already arrived, while there's room for them
+        while Self::CAPACITY - self.len >= usize::from(Packet::CAPACITY)
+            && EPOUT3_STATE.load() == EpOut3State::DataReady
+        {
+            self.receive().await;
+        }
+
+        let n = cmp::min(buf.len(), self.len);
+        for byte in &mut buf[..n] {
+            *byte = self.buffer[self.start];
+            self.start = (self.start + 1) % Self::CAPACITY;
+        }
+        self.len -= n;
+        n
+    }
+
+    /// Fills `buf` with data from the host, giving up if not enough data arrives within `timeout`
+    ///
+    /// On timeout the error includes the number of bytes, `n`, that were copied into `buf` before
+    /// the deadline; that data is in `buf[..n]` and is no longer buffered. An EasyDMA transfer is
+    /// never aborted midway; see `HidOut::read_timeout` for details. The deadline also covers the
+    /// wait for a free `Packet` when the pool is exhausted. The deadline has the same 125 ms
+    /// granularity as `HidOut::read_timeout`
+    pub async fn read_exact_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<(), (time::Timeout, usize)> {
+        let deadline = time::uptime() + timeout;
+        count_backlog();
+
+        let mut filled = 0;
+        while filled < buf.len() {
+            if self.len == 0 {
+                self.receive_until(deadline)
+                    .await
+                    .map_err(|timeout| (timeout, filled))?;
+            } else {
+                // NOTE does not wait for the host because the buffer is not empty
+                filled += self.drain(&mut buf[filled..]).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    // NOTE must only be called when there's room for a full packet
+    async fn receive(&mut self) {
+        let mut packet = Packet::new().await;
+        self.out.receive(&mut packet).await;
+        self.push(&packet);
+    }
+
+    // like `receive` but gives up at `deadline` (see `time::uptime`), which also bounds the wait for
+    // a free packet when the pool is exhausted
+    // NOTE must only be called when there's room for a full packet
+    async fn receive_until(&mut self, deadline: Duration) -> Result<(), time::Timeout> {
+        let now = time::uptime();
+        if now >= deadline {
+            return Err(time::Timeout);
+        }
+        let mut packet = time::with_timeout(time::ticks(deadline - now), Packet::new()).await?;
+
+        let now = time::uptime();
+        if now >= deadline {
+            return Err(time::Timeout);
+        }
+        self.out
+            .receive_timeout(&mut packet, deadline - now)
+            .await?;
+        self.push(&packet);
+        Ok(())
+    }
+
+    fn push(&mut self, packet: &Packet) {
+        for byte in packet.iter() {
+            self.buffer[(self.start + self.len) % Self::CAPACITY] = *byte;
+            self.len += 1;
+        }
     }
 }
 
@@ -853,26 +2622,28 @@ impl HidIn {
     ///
     /// Note that this returns after `packet` can be used but before the data has been put "on the
     /// wire"
+    ///
+    /// If `packet` is longer than the `wMaxPacketSize` of the endpoint an error is logged and
+    /// nothing is sent
     pub async fn write(&mut self, packet: &Packet) {
-        // wait until the endpoint has been enabled
-        crate::poll_fn(|| {
-            if EPIN3_STATE.load() == EpIn3State::Off {
-                Poll::Pending
-            } else {
-                Poll::Ready(())
-            }
-        })
-        .await;
+        let n = if let Some(n) = maxcnt(DmaEndpoint::EPIN3, usize::from(packet.len())) {
+            n
+        } else {
+            return;
+        };
+
+        wait_endpoint_enabled(3, usb2::Direction::In).await;
 
         self.flush().await;
 
         USBD::borrow_unchecked(|usbd| {
-            usbd.EPIN3_PTR.write(|w| w.PTR(packet.as_ptr() as u32));
-            usbd.EPIN3_MAXCNT.write(|w| w.MAXCNT(packet.len()));
+            usbd.EPIN3_PTR
+                .write(|w| w.PTR(dma_ptr(DmaEndpoint::EPIN3, packet.as_ptr())));
+            usbd.EPIN3_MAXCNT.write(|w| w.MAXCNT(n));
 
             EPIN3_STATE.store(EpIn3State::TransferStart);
             crate::dma_start();
-            usbd.TASKS_STARTEPIN3.write(|w| w.TASKS_STARTEPIN(1));
+            usbd.TASKS_STARTEPIN3.trigger();
         });
 
         // wait until data has been transferred
@@ -888,6 +2659,35 @@ impl HidIn {
         .await;
     }
 
+    /// Sends all the bytes yielded by `iter`, `Packet::CAPACITY` bytes at a time
+    ///
+    /// The bytes are pulled from `iter` as packets are sent so the data doesn't need to be
+    /// buffered up front. The transfer always ends with a short packet: if the total number of
+    /// bytes is a multiple of `Packet::CAPACITY` (this includes an empty `iter`) a zero-length
+    /// packet is sent at the end
+    pub async fn write_iter(&mut self, mut iter: impl Iterator<Item = u8>) {
+        let mut packet = Packet::new().await;
+
+        loop {
+            let mut len = 0;
+            while len < Packet::CAPACITY {
+                if let Some(byte) = iter.next() {
+                    unsafe { packet.data_ptr_mut().add(len.into()).write(byte) }
+                    len += 1;
+                } else {
+                    break;
+                }
+            }
+            packet.set_len(len);
+
+            self.write(&packet).await;
+
+            if len < Packet::CAPACITY {
+                break;
+            }
+        }
+    }
+
     /// Waits until the any pending write completes
     pub async fn flush(&mut self) {
         crate::poll_fn(|| {
@@ -901,6 +2701,84 @@ impl HidIn {
     }
 }
 
+/// HID IN endpoint that coalesces small writes into full packets
+///
+/// Each packet costs a full USB transaction so sending a few bytes at a time wastes most of the
+/// bandwidth. This writer instead accumulates data in a staging packet and only sends it when it's
+/// full (`Packet::CAPACITY` bytes) or when `flush` is called
+///
+/// The trade-off is latency: data that doesn't fill a packet stays in the staging packet, and
+/// never reaches the host, until the next `flush`. Call `flush` after writing a message the host
+/// is waiting for
+pub struct BufferedHidIn {
+    inner: HidIn,
+    // allocated on the first write
+    staging: Option<Packet>,
+}
+
+impl BufferedHidIn {
+    /// Wraps the HID IN endpoint
+    pub fn new(inner: HidIn) -> Self {
+        Self {
+            inner,
+            staging: None,
+        }
+    }
+
+    /// Releases the HID IN endpoint, discarding any data that has not been flushed
+    pub fn free(self) -> HidIn {
+        self.inner
+    }
+
+    /// Returns the number of bytes that have been written but not yet sent
+    pub fn bytes_to_flush(&self) -> u8 {
+        self.staging
+            .as_ref()
+            .map(|packet| packet.len())
+            .unwrap_or(0)
+    }
+
+    /// Queues `bytes` for transmission, sending every packet that fills up along the way
+    pub async fn write(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            if self.staging.is_none() {
+                self.staging = Some(Packet::new().await);
+            }
+
+            if let Some(packet) = self.staging.as_mut() {
+                let len = packet.len();
+                let n = cmp::min(usize::from(Packet::CAPACITY - len), bytes.len());
+                unsafe {
+                    ptr::copy_nonoverlapping(
+                        bytes.as_ptr(),
+                        packet.data_ptr_mut().add(len.into()),
+                        n,
+                    )
+                }
+                packet.set_len(len + n as u8);
+                bytes = &bytes[n..];
+
+                if packet.len() == Packet::CAPACITY {
+                    self.inner.write(packet).await;
+                    packet.set_len(0);
+                }
+            }
+        }
+    }
+
+    /// Sends the data that has been written but not yet sent, if any
+    pub async fn flush(&mut self) {
+        if let Some(packet) = self.staging.as_mut() {
+            if packet.len() != 0 {
+                self.inner.write(packet).await;
+                packet.set_len(0);
+            }
+        }
+
+        self.inner.flush().await;
+    }
+}
+
 /// HID packet
 pub struct Packet {
     buffer: Box<P>,
@@ -921,11 +2799,47 @@ impl Packet {
         }
     }
 
+    /// Returns a new HID packet that contains `len` zero bytes
+    ///
+    /// NOTE `len` will be truncated to `Self::CAPACITY` bytes
+    pub async fn zeroed(len: u8) -> Self {
+        let mut packet = Self::new().await;
+        packet.set_len(len);
+        unsafe { ptr::write_bytes(packet.data_ptr_mut(), 0, packet.len.into()) }
+        packet
+    }
+
+    /// Returns a new HID packet filled with the given `src` data
+    ///
+    /// NOTE `src` data will be truncated to `Self::CAPACITY` bytes
+    pub async fn from_slice(src: &[u8]) -> Self {
+        let mut packet = Self::new().await;
+        packet.copy_from_slice(src);
+        packet
+    }
+
+    /// Returns a new HID packet filled with the given `src` data
+    ///
+    /// Returns an error, without allocating a packet, if `src` is larger than `Self::CAPACITY`
+    pub async fn try_from_slice(src: &[u8]) -> Result<Self, ()> {
+        if src.len() > usize::from(Self::CAPACITY) {
+            return Err(());
+        }
+
+        Ok(Self::from_slice(src).await)
+    }
+
     /// Returns the length of the packet
     pub fn len(&self) -> u8 {
         self.len
     }
 
+    /// Returns a view into the packet contents that formats as hexadecimal
+    #[cfg(feature = "text")]
+    pub fn hex(&self) -> crate::Hex<'_> {
+        crate::Hex::new(self)
+    }
+
     /// Fills the packet with given `src` data
     ///
     /// NOTE `src` data will be truncated to `Self::CAPACITY` bytes
@@ -942,6 +2856,30 @@ impl Packet {
         self.len = cmp::min(len, Self::CAPACITY);
     }
 
+    /// Sets the `len` of the packet to the number of bytes an EasyDMA transfer wrote into it
+    ///
+    /// NOTE unlike `set_len` this doesn't truncate; in debug builds it panics if `n` exceeds
+    /// `Self::CAPACITY`
+    pub fn set_len_from_dma(&mut self, n: u8) {
+        #[cfg(debug_assertions)]
+        if n > Self::CAPACITY {
+            semidap::panic!(
+                "EasyDMA wrote {} bytes into a packet that holds {}",
+                n,
+                Self::CAPACITY
+            );
+        }
+
+        self.len = n;
+    }
+
+    /// # Safety
+    /// The first `len` bytes of data in `buffer` must be initialized
+    #[cfg(feature = "radio")]
+    pub(crate) unsafe fn from_parts(buffer: Box<P>, len: u8) -> Self {
+        Self { buffer, len }
+    }
+
     fn data_ptr(&self) -> *const u8 {
         unsafe { self.buffer.as_ptr().add(Self::PADDING) }
     }
@@ -965,28 +2903,421 @@ impl ops::DerefMut for Packet {
     }
 }
 
+/// Zero-copy conversion: the data of both kinds of packet starts at the same offset of the pool
+/// buffer so only the length field is rewritten
+#[cfg(feature = "radio")]
+impl From<Packet> for crate::radio::Packet {
+    fn from(packet: Packet) -> crate::radio::Packet {
+        // NOTE a HID packet always fits in a radio packet
+        unsafe { crate::radio::Packet::from_parts(packet.buffer, packet.len) }
+    }
+}
+
+/// Waits until no data transfer is in flight in either direction
+///
+/// "Quiesced" means that:
+///
+/// - no `HidIn::write` transfer has been started and not yet completed
+/// - there's no data received on the HID OUT endpoint that has not been read by `HidOut::read`
+/// - the CDC ACM transmit buffer has been fully sent (when the `class-cdc` feature is enabled)
+///
+/// It does NOT mean that the host won't start a new transfer right after this returns, nor that
+/// there are no `read` / `write` futures waiting for the endpoints to become ready. This function
+/// does not start any transfer.
+pub async fn quiesce() {
+    crate::poll_fn(|| {
+        if in_drained() && EPOUT3_STATE.load() != EpOut3State::DataReady {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+// whether all the data handed to the IN endpoints has been sent; see `quiesce`
+fn in_drained() -> bool {
+    let epin3 = EPIN3_STATE.load();
+
+    #[cfg(feature = "class-cdc")]
+    let tx_done = EP2IN_STATE.load() != Ep2InState::InUse && TX_BUF.bytes_to_read() == 0;
+    #[cfg(not(feature = "class-cdc"))]
+    let tx_done = true;
+
+    (epin3 == EpIn3State::Off || epin3 == EpIn3State::Idle) && tx_done
+}
+
+/// Echoes every HID packet received from the host back to the host, forever
+///
+/// Meant as a self-test of the USB data path. A fresh packet is allocated from the pool for each
+/// transfer so a pool leak makes this stall, instead of going unnoticed. Zero-length packets are
+/// echoed back as zero-length packets. There's no buffering: the next packet is not read until
+/// the previous one has been handed to the hardware, so a slow host is back-pressured with NAKs
+#[cfg(feature = "hid")]
+pub async fn loopback(mut hidout: HidOut, mut hidin: HidIn) {
+    let mut count = 0u32;
+    loop {
+        let mut packet = Packet::new().await;
+        hidout.read(&mut packet).await;
+        hidin.write(&packet).await;
+        // the packet must not be freed while EasyDMA may still be reading it
+        hidin.flush().await;
+        drop(packet);
+
+        count = count.wrapping_add(1);
+        if count % 1024 == 0 {
+            semidap::info!("loopback: echoed {} packets", count);
+        }
+    }
+}
+
+#[cfg(feature = "hid")]
+static HID_IN_CLAIMED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "hid")]
+static HID_OUT_CLAIMED: AtomicBool = AtomicBool::new(false);
+
 /// Claims the USB HID interface
+///
+/// Shorthand for `(hid_out(), hid_in())`
 #[cfg(feature = "hid")]
 pub fn hid() -> (HidOut, HidIn) {
-    static ONCE: AtomicBool = AtomicBool::new(false);
+    (hid_out(), hid_in())
+}
 
-    if ONCE
+/// Tries to claim the USB HID interface
+///
+/// Returns `None` if either endpoint has already been claimed; in that case neither endpoint is
+/// claimed by this call
+#[cfg(feature = "hid")]
+pub fn try_hid() -> Option<(HidOut, HidIn)> {
+    let hidout = try_hid_out()?;
+    if let Some(hidin) = try_hid_in() {
+        Some((hidout, hidin))
+    } else {
+        // give the OUT endpoint back
+        drop(hidout);
+        HID_OUT_CLAIMED.store(false, Ordering::Relaxed);
+        None
+    }
+}
+
+/// Claims the IN endpoint of the USB HID interface
+///
+/// Can be claimed independently of the OUT endpoint (see `hid_out`) so that each half can be
+/// handed to a different task
+#[cfg(feature = "hid")]
+pub fn hid_in() -> HidIn {
+    if let Some(hidin) = try_hid_in() {
+        hidin
+    } else {
+        semidap::panic!("`usbd::hid_in` endpoint has already been claimed")
+    }
+}
+
+/// Tries to claim the IN endpoint of the USB HID interface
+///
+/// Returns `None` if the endpoint has already been claimed
+#[cfg(feature = "hid")]
+pub fn try_hid_in() -> Option<HidIn> {
+    if HID_IN_CLAIMED
         .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
         .is_ok()
     {
-        (
-            HidOut {
-                _not_send_or_sync: NotSendOrSync::new(),
-            },
-            HidIn {
-                _not_send_or_sync: NotSendOrSync::new(),
-            },
-        )
+        Some(HidIn {
+            _not_send_or_sync: NotSendOrSync::new(),
+        })
+    } else {
+        None
+    }
+}
+
+/// Claims the OUT endpoint of the USB HID interface
+///
+/// Can be claimed independently of the IN endpoint (see `hid_in`) so that each half can be
+/// handed to a different task
+#[cfg(feature = "hid")]
+pub fn hid_out() -> HidOut {
+    if let Some(hidout) = try_hid_out() {
+        hidout
+    } else {
+        semidap::panic!("`usbd::hid_out` endpoint has already been claimed")
+    }
+}
+
+/// Tries to claim the OUT endpoint of the USB HID interface
+///
+/// Returns `None` if the endpoint has already been claimed
+#[cfg(feature = "hid")]
+pub fn try_hid_out() -> Option<HidOut> {
+    if HID_OUT_CLAIMED
+        .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+        .is_ok()
+    {
+        Some(HidOut {
+            _not_send_or_sync: NotSendOrSync::new(),
+        })
+    } else {
+        None
+    }
+}
+
+/// What the isochronous IN endpoint sends when the application has no fresh buffer ready for the
+/// next frame
+#[cfg(feature = "iso")]
+#[derive(Clone, Copy, PartialEq)]
+pub enum Underrun {
+    /// Send a zero-length packet
+    Zlp,
+    /// Send the previous buffer again; a zero-length packet is sent if nothing has been sent yet
+    Repeat,
+}
+
+#[cfg(feature = "iso")]
+derive!(IsoSlot);
+
+#[cfg(feature = "iso")]
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+enum IsoSlot {
+    // the application can fill it
+    Free = 0,
+    // filled; will be loaded on the next SOF
+    Ready = 1,
+    // being copied into the USBD by EasyDMA
+    Loading = 2,
+    // loaded; kept around in case it has to be repeated (`Underrun::Repeat`)
+    Sent = 3,
+}
+
+#[cfg(feature = "iso")]
+static ISOIN_CLAIMED: AtomicBool = AtomicBool::new(false);
+// whether the isochronous interface is in alternate setting 1
+#[cfg(feature = "iso")]
+static ISOIN_ENABLED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "iso")]
+static ISOIN_REPEAT: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "iso")]
+static ISOIN_UNDERRUNS: AtomicU32 = AtomicU32::new(0);
+#[cfg(feature = "iso")]
+static ISOIN_SLOTS: [Atomic<IsoSlot>; 2] = [Atomic::new(), Atomic::new()];
+#[cfg(feature = "iso")]
+static ISOIN_LENS: [AtomicU16; 2] = [AtomicU16::new(0), AtomicU16::new(0)];
+// the next slot the application fills
+#[cfg(feature = "iso")]
+static ISOIN_FILL: AtomicU8 = AtomicU8::new(0);
+// the next slot the USBD task loads
+#[cfg(feature = "iso")]
+static ISOIN_NEXT: AtomicU8 = AtomicU8::new(0);
+#[cfg(feature = "iso")]
+#[link_section = ".uninit.ISOIN_BUFS"]
+static mut ISOIN_BUFS: [Align4<[u8; ISO_MAX_PACKET_SIZE as usize]>; 2] = [
+    Align4([0; ISO_MAX_PACKET_SIZE as usize]),
+    Align4([0; ISO_MAX_PACKET_SIZE as usize]),
+];
+
+/// Isochronous IN endpoint, double buffered and synchronized to the USB frames
+///
+/// The endpoint lives in its own (vendor-specific) interface. Alternate setting 0 of the interface
+/// has no endpoints; the endpoint only starts sending once the host selects alternate setting 1
+///
+/// The endpoint has two buffers of `ISO_MAX_PACKET_SIZE` bytes (see the module documentation).
+/// On every Start Of Frame (SOF) the USBD task loads the oldest filled buffer into the USB
+/// peripheral, which sends it when the host polls the endpoint during that frame; meanwhile the
+/// application fills the other buffer. If no buffer has been filled when the SOF arrives the
+/// endpoint underruns and sends what `set_underrun` selected
+///
+/// # Latency
+///
+/// A buffer is never sent in the frame in which it's filled: it waits for the next SOF so data
+/// reaches the host one frame (1 ms) after it was written, plus up to one more frame if the
+/// other buffer was already waiting to be sent
+#[cfg(feature = "iso")]
+pub struct IsoIn {
+    _not_send_or_sync: NotSendOrSync,
+}
+
+/// Claims the isochronous IN endpoint
+///
+/// # Panics
+///
+/// This function panics if the endpoint has already been claimed
+#[cfg(feature = "iso")]
+pub fn iso_in() -> IsoIn {
+    if ISOIN_CLAIMED
+        .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+        .is_ok()
+    {
+        IsoIn {
+            _not_send_or_sync: NotSendOrSync::new(),
+        }
+    } else {
+        semidap::panic!("`usbd::iso_in` endpoint has already been claimed")
+    }
+}
+
+#[cfg(feature = "iso")]
+impl IsoIn {
+    /// Selects what to send when no buffer is ready at the start of a frame; defaults to
+    /// `Underrun::Zlp`
+    pub fn set_underrun(&mut self, underrun: Underrun) {
+        ISOIN_REPEAT.store(underrun == Underrun::Repeat, Ordering::Relaxed);
+    }
+
+    /// Returns the number of frames in which no fresh buffer was ready
+    pub fn underruns(&self) -> u32 {
+        ISOIN_UNDERRUNS.load(Ordering::Relaxed)
+    }
+
+    /// Fills the next free buffer with `f` and queues it for transmission
+    ///
+    /// `f` receives the whole buffer and returns the number of bytes it wrote. This waits until a
+    /// buffer is free, which happens at most once per frame while the endpoint is active
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `f` returns a length larger than `ISO_MAX_PACKET_SIZE`
+    pub async fn fill(&mut self, f: impl FnOnce(&mut [u8]) -> usize) {
+        let i = usize::from(ISOIN_FILL.load(Ordering::Relaxed));
+
+        crate::poll_fn(|| {
+            if ISOIN_SLOTS[i].load() == IsoSlot::Free {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        // NOTE(unsafe) the USBD task doesn't touch `Free` buffers
+        let len = f(unsafe { &mut ISOIN_BUFS[i].0[..] });
+        if len > usize::from(ISO_MAX_PACKET_SIZE) {
+            semidap::panic!(
+                "ISO IN: packet of {} bytes exceeds the maximum packet size ({})",
+                len,
+                ISO_MAX_PACKET_SIZE
+            );
+        }
+
+        ISOIN_LENS[i].store(len as u16, Ordering::Relaxed);
+        ISOIN_FILL.store(1 - i as u8, Ordering::Relaxed);
+        crate::dma_start();
+        ISOIN_SLOTS[i].store(IsoSlot::Ready);
+    }
+
+    /// Queues a copy of `bytes` for transmission
+    ///
+    /// See `fill` for details
+    pub async fn write(&mut self, bytes: &[u8]) {
+        self.fill(|buf| {
+            let n = cmp::min(bytes.len(), buf.len());
+            buf[..n].copy_from_slice(&bytes[..n]);
+            bytes.len()
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "iso")]
+fn isoin_enable() {
+    semidap::info!("ISO IN: enabled");
+
+    ISOIN_ENABLED.store(true, Ordering::Relaxed);
+    USBD::borrow_unchecked(|usbd| {
+        // a zero-length packet is sent in frames where no data was loaded
+        usbd.ISOINCONFIG.write(|w| w.RESPONSE(1));
+        usbd.EPINEN.rmw(|_, w| w.ISOIN(1));
+        usbd.EVENTS_SOF.zero();
+        unsafe { usbd.INTENSET.write(|w| w.SOF(1)) }
+    });
+}
+
+// NOTE must only be called from the USB tasks
+#[cfg(feature = "iso")]
+fn isoin_disable() {
+    if !ISOIN_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    semidap::info!("ISO IN: disabled");
+
+    ISOIN_ENABLED.store(false, Ordering::Relaxed);
+    release_sof();
+    USBD::borrow_unchecked(|usbd| usbd.EPINEN.rmw(|_, w| w.ISOIN(0)));
+    isoin_reset();
+}
+
+// drops the queued buffers
+#[cfg(feature = "iso")]
+fn isoin_reset() {
+    for slot in &ISOIN_SLOTS {
+        slot.store(IsoSlot::Free);
+    }
+    ISOIN_FILL.store(0, Ordering::Relaxed);
+    ISOIN_NEXT.store(0, Ordering::Relaxed);
+}
+
+// loads the next buffer; runs on every SOF
+#[cfg(feature = "iso")]
+fn isoin_sof() {
+    if !ISOIN_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let next = usize::from(ISOIN_NEXT.load(Ordering::Relaxed));
+    let prev = 1 - next;
+
+    if ISOIN_SLOTS[next].load() == IsoSlot::Ready {
+        // the previous buffer won't be repeated anymore
+        if ISOIN_SLOTS[prev].load() == IsoSlot::Sent {
+            ISOIN_SLOTS[prev].store(IsoSlot::Free);
+        }
+
+        start_isoin(next);
+        ISOIN_NEXT.store(prev as u8, Ordering::Relaxed);
+    } else {
+        ISOIN_UNDERRUNS.fetch_add(1, Ordering::Relaxed);
+
+        if ISOIN_REPEAT.load(Ordering::Relaxed) && ISOIN_SLOTS[prev].load() == IsoSlot::Sent {
+            start_isoin(prev);
+        }
+        // otherwise the USBD sends a zero-length packet; see `isoin_enable`
+    }
+}
+
+#[cfg(feature = "iso")]
+fn isoin_end() {
+    crate::dma_end();
+
+    // the data now lives in the USBD's internal buffer
+    let next = if ISOIN_REPEAT.load(Ordering::Relaxed) {
+        IsoSlot::Sent
     } else {
-        semidap::panic!("`usbd::hid` interface has already been claimed")
+        IsoSlot::Free
+    };
+    for slot in &ISOIN_SLOTS {
+        if slot.load() == IsoSlot::Loading {
+            slot.store(next);
+        }
     }
 }
 
+#[cfg(feature = "iso")]
+fn start_isoin(i: usize) {
+    ISOIN_SLOTS[i].store(IsoSlot::Loading);
+
+    // NOTE(unsafe) the application doesn't touch `Loading` buffers
+    let ptr = unsafe { ISOIN_BUFS[i].0.as_ptr() };
+    USBD::borrow_unchecked(|usbd| {
+        usbd.ISOIN_PTR
+            .write(|w| w.PTR(dma_ptr(DmaEndpoint::ISOIN, ptr)));
+        usbd.ISOIN_MAXCNT
+            .write(|w| w.MAXCNT(ISOIN_LENS[i].load(Ordering::Relaxed)));
+
+        crate::dma_start();
+        usbd.TASKS_STARTISOIN.trigger();
+    });
+}
+
 /// USB packet
 #[cfg(TODO)]
 pub struct Packet {
@@ -1060,19 +3391,35 @@ impl ops::DerefMut for Packet {
     }
 }
 
-#[cfg(TODO)]
-#[cfg(feature = "radio")]
-impl From<Packet> for crate::radio::Packet {
-    fn from(packet: Packet) -> crate::radio::Packet {
-        crate::radio::Packet::from_parts(packet.buffer, packet.len)
-    }
-}
-
+/// Phase of the control transfer on endpoint 0
+///
+/// ``` text
+///           EP0SETUP                EP0DATADONE (leftover == 0)
+/// Setup ---------------> DataIn -------------------------------> Status
+///   |                     |  ^
+///   |                     +--+ EP0DATADONE (leftover != 0)
+///   |
+///   |       EP0SETUP                 EP0DATADONE                    ENDEPOUT0
+///   +--------------> DataOut(false) ------------> DataOut(true) ----------> Status
+///   |
+///   |       EP0SETUP (no data stage)
+///   +----------------------------------------------------------------------> Status
+/// ```
+///
+/// A stalled request or a bus reset goes back to `Setup`. The peripheral raises no event when the status stage
+/// completes so the next EP0SETUP event starts a new transfer from either `Setup` or `Status`
 #[derive(Clone, Copy, PartialEq)]
 enum Ep0State {
-    Idle,
-    Read,
-    Write { leftover: u16 },
+    /// Waiting for the SETUP packet of a control transfer
+    Setup,
+    /// Data stage of a control read (device to host) transfer; `leftover` bytes have yet to be
+    /// handed to the peripheral
+    DataIn { leftover: u16 },
+    /// Data stage of a control write (host to device) transfer; `received` is set once the host
+    /// data has arrived and is being copied into RAM
+    DataOut { received: bool },
+    /// The status stage has been started
+    Status,
 }
 
 #[derive(Clone, Copy)]
@@ -1082,13 +3429,19 @@ enum PowerState {
     Ready,
 }
 
+// NOTE `Reset` and `Shutdown` are software events
+#[cfg_attr(feature = "text", derive(ufmt::derive::uDebug))]
 #[derive(Clone, Copy, PartialEq, binDebug)]
 enum PowerEvent {
+    Reset,
+    Shutdown,
     USBDETECTED,
     USBREMOVED,
     USBPWRRDY,
 }
 
+display!(PowerEvent);
+
 impl PowerEvent {
     fn next() -> Option<Self> {
         POWER::borrow_unchecked(|power| {
@@ -1107,24 +3460,44 @@ impl PowerEvent {
                 return Some(PowerEvent::USBPWRRDY);
             }
 
+            if RESET_REQUESTED.swap(false, Ordering::Relaxed) {
+                return Some(PowerEvent::Reset);
+            }
+
+            if SHUTDOWN_REQUESTED.swap(false, Ordering::Relaxed) {
+                return Some(PowerEvent::Shutdown);
+            }
+
             None
         })
     }
 }
 
+// NOTE must stay in sync with the events enabled in `INTENSET` (see `init`); `TxWrite` is a
+// software event
+#[cfg_attr(feature = "text", derive(ufmt::derive::uDebug))]
 #[derive(Clone, Copy, binDebug, PartialEq)]
 enum UsbdEvent {
     ENDEPOUT0,
+    #[cfg(feature = "class-cdc")]
+    ENDEPOUT2,
     ENDEPOUT3,
     ENDEPIN3,
+    #[cfg(feature = "iso")]
+    ENDISOIN,
     EP0DATADONE,
     EP0SETUP,
     EPDATA,
+    #[cfg(any(feature = "class-cdc", feature = "dfu", feature = "iso"))]
+    SOF,
+    #[cfg(feature = "class-cdc")]
     TxWrite,
     USBEVENT,
     USBRESET,
 }
 
+display!(UsbdEvent);
+
 impl UsbdEvent {
     fn next() -> Option<Self> {
         USBD::borrow_unchecked(|usbd| {
@@ -1158,6 +3531,12 @@ impl UsbdEvent {
                 return Some(UsbdEvent::ENDEPOUT0);
             }
 
+            #[cfg(feature = "class-cdc")]
+            if usbd.EVENTS_ENDEPOUT2.read().bits() != 0 {
+                usbd.EVENTS_ENDEPOUT2.zero();
+                return Some(UsbdEvent::ENDEPOUT2);
+            }
+
             if usbd.EVENTS_ENDEPOUT3.read().bits() != 0 {
                 usbd.EVENTS_ENDEPOUT3.zero();
                 return Some(UsbdEvent::ENDEPOUT3);
@@ -1168,6 +3547,20 @@ impl UsbdEvent {
                 return Some(UsbdEvent::ENDEPIN3);
             }
 
+            // NOTE the SOF event is raised every frame, even while its interrupt is disabled
+            #[cfg(any(feature = "class-cdc", feature = "dfu", feature = "iso"))]
+            if usbd.INTEN.read().SOF() != 0 && usbd.EVENTS_SOF.read().bits() != 0 {
+                usbd.EVENTS_SOF.zero();
+                return Some(UsbdEvent::SOF);
+            }
+
+            #[cfg(feature = "iso")]
+            if usbd.EVENTS_ENDISOIN.read().bits() != 0 {
+                usbd.EVENTS_ENDISOIN.zero();
+                return Some(UsbdEvent::ENDISOIN);
+            }
+
+            #[cfg(feature = "class-cdc")]
             if EP2IN_STATE.load() == Ep2InState::Idle && TX_BUF.bytes_to_read() != 0 {
                 return Some(UsbdEvent::TxWrite);
             }
@@ -1208,7 +3601,11 @@ fn EVENTCAUSE() -> eventcause::R {
             *w = r.into();
             w
         });
+        // the PAC only implements `binDebug` for `R`
+        #[cfg(not(feature = "text"))]
         semidap::debug!("{}", r);
+        #[cfg(feature = "text")]
+        semidap::debug!("EVENTCAUSE {}", r.bits());
         r
     })
 }
@@ -1225,6 +3622,104 @@ fn EPDATASTATUS() -> epdatastatus::R {
     })
 }
 
+// endpoints that move data through EasyDMA; used to report bad buffers
+#[allow(dead_code)]
+#[cfg_attr(feature = "text", derive(ufmt::derive::uDebug))]
+#[derive(Clone, Copy, binDebug)]
+enum DmaEndpoint {
+    EPIN0,
+    EPIN1,
+    EPIN2,
+    EPIN3,
+    EPOUT0,
+    EPOUT2,
+    EPOUT3,
+    ISOIN,
+}
+
+display!(DmaEndpoint);
+
+impl DmaEndpoint {
+    // `wMaxPacketSize` declared in the descriptor of this endpoint
+    fn max_packet_size(self) -> u16 {
+        match self {
+            DmaEndpoint::EPIN0 => EPIN_MAX_PACKET_SIZE[0],
+            DmaEndpoint::EPIN1 => EPIN_MAX_PACKET_SIZE[1],
+            DmaEndpoint::EPIN2 => EPIN_MAX_PACKET_SIZE[2],
+            DmaEndpoint::EPIN3 => EPIN_MAX_PACKET_SIZE[3],
+            DmaEndpoint::EPOUT0 => EPOUT_MAX_PACKET_SIZE[0],
+            DmaEndpoint::EPOUT2 => EPOUT_MAX_PACKET_SIZE[2],
+            DmaEndpoint::EPOUT3 => EPOUT_MAX_PACKET_SIZE[3],
+            DmaEndpoint::ISOIN => ISO_MAX_PACKET_SIZE,
+        }
+    }
+}
+
+/// Converts `len` into a value that can be written to the `MAXCNT` register of `ep`
+///
+/// A single EasyDMA transfer moves one packet so `len` must not exceed the `wMaxPacketSize` of
+/// the endpoint. If it does an error is logged and `None` is returned; the caller must then not
+/// start the transfer
+///
+/// NOTE not for `DmaEndpoint::ISOIN`, whose packets can be larger than 255 bytes; `IsoIn::fill`
+/// checks their length
+fn maxcnt(ep: DmaEndpoint, len: usize) -> Option<u8> {
+    let max = ep.max_packet_size();
+    if len > usize::from(max) {
+        semidap::error!(
+            "{}: transfer of {} bytes exceeds wMaxPacketSize ({})",
+            ep,
+            len as u32,
+            max
+        );
+        None
+    } else {
+        Some(len as u8)
+    }
+}
+
+/// Converts `ptr` into a value that can be written to the `PTR` register of endpoints 1 to 7
+///
+/// The EasyDMA buffers of endpoints 1 to 7 (both IN and OUT) must be 4-byte aligned; endpoint 0
+/// has no such requirement. This function checks that requirement in debug builds
+fn dma_ptr(ep: DmaEndpoint, ptr: *const u8) -> u32 {
+    let addr = ram_ptr(ep, ptr);
+
+    #[cfg(debug_assertions)]
+    if addr % 4 != 0 {
+        semidap::panic!(
+            "{}: EasyDMA buffer (address: {}) is not 4-byte aligned",
+            ep,
+            addr
+        );
+    }
+
+    addr
+}
+
+/// Converts `ptr` into a value that can be written to the `PTR` register of any endpoint
+///
+/// EasyDMA can only access RAM; a buffer in Flash, e.g. a `static` without a `.data` link section,
+/// makes the transfer silently send garbage or fail. The USBD has no event that reports this
+/// condition: `EVENTCAUSE` only flags CRC errors on isochronous OUT transfers (`ISOOUTCRC`) and
+/// the `EPSTATUS` / `EPDATASTATUS` registers only report completed transfers; the bus error is
+/// not seen by the CPU either. So this function checks the address before the transfer starts,
+/// in all build profiles, and reports the endpoint and the address if the check fails
+fn ram_ptr(ep: DmaEndpoint, ptr: *const u8) -> u32 {
+    const RAM_START: u32 = 0x2000_0000;
+    #[cfg(feature = "nrf52833")]
+    const RAM_END: u32 = 0x2002_0000;
+    #[cfg(not(feature = "nrf52833"))]
+    const RAM_END: u32 = 0x2004_0000;
+
+    let addr = ptr as u32;
+    if addr < RAM_START || addr >= RAM_END {
+        semidap::panic!("{}: EasyDMA buffer (address: {}) is not in RAM", ep, addr);
+    }
+
+    addr
+}
+
 // NOTE(borrow_unchecked) all these are either single instruction reads w/o side effects or single
 // instruction writes to registers that won't be RMW-ed
 fn connect() {
@@ -1271,12 +3766,12 @@ fn EPOUT1_MAXCNT(cnt: u8) {
 #[allow(dead_code)]
 #[allow(non_snake_case)]
 fn STARTEPOUT1() {
-    USBD::borrow_unchecked(|usbd| usbd.TASKS_STARTEPOUT1.write(|w| w.TASKS_STARTEPOUT(1)));
+    USBD::borrow_unchecked(|usbd| usbd.TASKS_STARTEPOUT1.trigger());
 }
 
 #[allow(non_snake_case)]
 fn EP0STALL() {
-    USBD::borrow_unchecked(|usbd| usbd.TASKS_EP0STALL.write(|w| w.TASKS_EP0STALL(1)));
+    USBD::borrow_unchecked(|usbd| usbd.TASKS_EP0STALL.trigger());
     semidap::info!("EP0: stalled");
 }
 
@@ -1313,16 +3808,25 @@ fn WLENGTH() -> u16 {
 
 fn ep0status() {
     USBD::borrow_unchecked(|usbd| {
-        usbd.TASKS_EP0STATUS.write(|w| w.TASKS_EP0STATUS(1));
+        usbd.TASKS_EP0STATUS.trigger();
     });
 }
 
 fn suspend() {
+    // no more SOFs will arrive to complete the countdown; the host has seen the status stage
+    #[cfg(feature = "dfu")]
+    if dfu_detach_pending() {
+        dfu_reboot()
+    }
+
     semidap::info!("entering low power mode");
-    USBD::borrow_unchecked(|usbd| usbd.LOWPOWER.write(|w| w.LOWPOWER(1)))
+    USBD::borrow_unchecked(|usbd| usbd.LOWPOWER.write(|w| w.LOWPOWER(1)));
+    SUSPENDED_AT.store(time::now(), Ordering::Relaxed);
+    SUSPENDED.store(true, Ordering::Relaxed);
 }
 
 fn resume() {
     semidap::info!("leaving low power mode");
+    SUSPENDED.store(false, Ordering::Relaxed);
     USBD::borrow_unchecked(|usbd| usbd.LOWPOWER.zero())
 }
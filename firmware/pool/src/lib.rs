@@ -30,6 +30,11 @@ impl<T> PoolImpl<T> {
         }
     }
 
+    #[doc(hidden)]
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Relaxed).is_null()
+    }
+
     #[doc(hidden)]
     pub fn pop(&self) -> Option<NonNull<Node<T>>> {
         loop {
@@ -128,6 +133,37 @@ macro_rules! pool {
                 Alloc
             }
 
+            /// Waits until the pool has at least one free memory block
+            ///
+            /// Resolves immediately if the pool is not empty. Returning a memory block to the pool
+            /// (dropping a `Box`) raises an event (`SEV`) that wakes up the executor so this
+            /// doesn't busy-poll the pool while it's empty.
+            ///
+            /// NOTE no fairness or ordering is promised: when several tasks wait on the same pool
+            /// they all wake up and the free block is not reserved for any of them, so `try_alloc`
+            /// may still return `None` after this resolves
+            #[allow(dead_code)]
+            pub fn wait_available() -> impl core::future::Future<Output = ()> {
+                struct WaitAvailable;
+
+                impl core::future::Future for WaitAvailable {
+                    type Output = ();
+
+                    fn poll(
+                        self: core::pin::Pin<&mut Self>,
+                        _: &mut core::task::Context,
+                    ) -> core::task::Poll<()> {
+                        if <$ident as $crate::Pool>::get().is_empty() {
+                            core::task::Poll::Pending
+                        } else {
+                            core::task::Poll::Ready(())
+                        }
+                    }
+                }
+
+                WaitAvailable
+            }
+
             /// Gives the pool a memory block to manage
             #[allow(dead_code)]
             pub fn manage(node: &'static mut core::mem::MaybeUninit<$crate::Node<[u8; $N]>>) {
@@ -257,4 +293,40 @@ mod tests {
         core::mem::forget(y);
         assert!(B::try_alloc().is_none());
     }
+
+    #[test]
+    fn wait_available() {
+        use core::{
+            future::Future,
+            pin::Pin,
+            ptr,
+            task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+        };
+
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(
+            |_| RawWaker::new(ptr::null(), &VTABLE),
+            |_| {},
+            |_| {},
+            |_| {},
+        );
+
+        static mut N: MaybeUninit<Node<[u8; 1]>> = MaybeUninit::uninit();
+
+        pool!(pub C: [u8; 1]);
+        C::manage(unsafe { &mut N });
+
+        let waker = unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) };
+        let mut cx = Context::from_waker(&waker);
+
+        // a free block is available
+        let mut fut = C::wait_available();
+        assert_eq!(Pin::new(&mut fut).poll(&mut cx), Poll::Ready(()));
+
+        let x = C::try_alloc().unwrap();
+        let mut fut = C::wait_available();
+        assert_eq!(Pin::new(&mut fut).poll(&mut cx), Poll::Pending);
+
+        drop(x); // returns to the pool
+        assert_eq!(Pin::new(&mut fut).poll(&mut cx), Poll::Ready(()));
+    }
 }
@@ -1,4 +1,12 @@
 //! CMSIS-DAP based semihosting
+//!
+//! # Features
+//!
+//! - `text`. Log messages are formatted as plain ASCII text, using `ufmt`, instead of being
+//! encoded in the compact `binfmt` format. This is meant for bring-up when the `semidap` host
+//! tool is not available; it costs bandwidth and code size. In this mode all formatting arguments
+//! must implement `ufmt::uDisplay` (or `ufmt::uDebug` when `{:?}` is used); arguments that only
+//! implement `binfmt::binDebug` are not supported.
 
 #![deny(missing_docs)]
 #![deny(warnings)]
@@ -11,6 +19,32 @@ use core::{
 
 #[doc(hidden)]
 pub use binfmt::{binWrite, binwrite, Level};
+#[cfg(feature = "text")]
+#[doc(hidden)]
+pub use ufmt::{self, uwriteln};
+
+/// Implementation detail
+#[cfg(not(feature = "text"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __write {
+    ($stdout:expr, $($tt:tt)+) => {
+        $crate::binwrite!($stdout, $($tt)+)
+    };
+}
+
+/// Implementation detail
+#[cfg(feature = "text")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __write {
+    ($stdout:expr, $($tt:tt)+) => {{
+        // `uwriteln!` expands to paths that start with `ufmt::`
+        use $crate::ufmt;
+        // NOTE writing to a `Channel` never fails
+        let _ = $crate::uwriteln!($stdout, $($tt)+);
+    }};
+}
 
 /// Logs the formatted string at the `Debug` log level
 ///
@@ -24,7 +58,7 @@ macro_rules! debug {
                 match $crate::stdout() {
                     ref mut __stdout__ => {
                         $crate::log(__stdout__, $crate::Level::Debug);
-                        $crate::binwrite!(__stdout__, $($tt)+)
+                        $crate::__write!(__stdout__, $($tt)+)
                     }
                 }
             }
@@ -43,7 +77,7 @@ macro_rules! error {
         match $crate::stdout() {
             ref mut __stdout__ => {
                 $crate::log(__stdout__, $crate::Level::Error);
-                $crate::binwrite!(__stdout__, $($tt)+)
+                $crate::__write!(__stdout__, $($tt)+)
             }
         }
     }
@@ -58,7 +92,7 @@ macro_rules! info {
         match $crate::stdout() {
             ref mut __stdout__ => {
                 $crate::log(__stdout__, $crate::Level::Info);
-                $crate::binwrite!(__stdout__, $($tt)+)
+                $crate::__write!(__stdout__, $($tt)+)
             }
         }
     }
@@ -76,7 +110,7 @@ macro_rules! trace {
                 match $crate::stdout() {
                     ref mut __stdout__ => {
                         $crate::log(__stdout__, $crate::Level::Trace);
-                        $crate::binwrite!(__stdout__, $($tt)+)
+                        $crate::__write!(__stdout__, $($tt)+)
                     }
                 }
             }
@@ -95,7 +129,7 @@ macro_rules! warn {
         match $crate::stdout() {
             ref mut __stdout__ => {
                 $crate::log(__stdout__, $crate::Level::Warn);
-                $crate::binwrite!(__stdout__, $($tt)+)
+                $crate::__write!(__stdout__, $($tt)+)
             }
         }
     }
@@ -107,14 +141,34 @@ fn in_thread_mode() -> bool {
     unsafe { SCB_ICSR.read_volatile() as u8 == 0 }
 }
 
+#[cfg(not(feature = "text"))]
 #[doc(hidden)]
 pub fn log(stdout: &mut impl binWrite, level: Level) {
+    let ts = timestamp();
+    stdout.write_byte(level as u8);
+    stdout.leb128_write(ts);
+}
+
+#[cfg(feature = "text")]
+#[doc(hidden)]
+pub fn log(stdout: &mut Channel, level: Level) {
+    let ts = timestamp();
+    let level = match level {
+        Level::Error => "ERROR",
+        Level::Warn => "WARN",
+        Level::Info => "INFO",
+        Level::Debug => "DEBUG",
+        Level::Trace => "TRACE",
+    };
+    // NOTE writing to a `Channel` never fails
+    let _ = ufmt::uwrite!(stdout, "{} {} ", ts, level);
+}
+
+fn timestamp() -> u32 {
     extern "Rust" {
         fn __semidap_timestamp() -> u32;
     }
-    let ts = unsafe { __semidap_timestamp() };
-    stdout.write_byte(level as u8);
-    stdout.leb128_write(ts);
+    unsafe { __semidap_timestamp() }
 }
 
 /// Aborts the `semidap` process running on the host
@@ -247,6 +301,16 @@ impl binfmt::binWrite for Channel {
     }
 }
 
+#[cfg(feature = "text")]
+impl ufmt::uWrite for Channel {
+    type Error = core::convert::Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        self.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
 // opt-level = 3
 #[cfg(not(debug_assertions))]
 unsafe fn memcpy(src: *const u8, dst: *mut u8, len: usize) {
@@ -0,0 +1,490 @@
+//! Feeds random SETUP packets to the control request handler used by `hal::usbd`
+//!
+//! Usage: `control-fuzz [ITERATIONS [SEED]]`
+//!
+//! The requests are sent to a model device, in sequence, so the ones that change the state of the
+//! device (SET_ADDRESS, SET_CONFIGURATION, etc.) affect how the following ones are handled. Every
+//! request must be either stalled or answered with a well-formed response: control reads never
+//! return more than `wLength` bytes and only return existing descriptors, and state changes follow
+//! the USB 2.0 device state machine. Panics (e.g. arithmetic overflow in debug builds) are failures
+
+use std::{
+    env,
+    num::NonZeroU8,
+    panic,
+    sync::atomic::{AtomicU16, Ordering},
+};
+
+use anyhow::{bail, ensure, Context as _};
+use control::{Change, Config, Descriptors, Device, Response, Setup};
+use rand::Rng as _;
+use rand_core::SeedableRng as _;
+use rand_xorshift::XorShiftRng;
+use usb2::{GetDescriptor, State};
+
+// (bmRequestType, bRequest) of the requests the device supports
+const KNOWN: &[(u8, u8)] = &[
+    // standard requests
+    (0x80, 0),  // GET_STATUS (device)
+    (0x81, 0),  // GET_STATUS (interface)
+    (0x82, 0),  // GET_STATUS (endpoint)
+    (0x00, 1),  // CLEAR_FEATURE (device)
+    (0x02, 1),  // CLEAR_FEATURE (endpoint)
+    (0x00, 3),  // SET_FEATURE (device)
+    (0x02, 3),  // SET_FEATURE (endpoint)
+    (0x00, 5),  // SET_ADDRESS
+    (0x80, 6),  // GET_DESCRIPTOR
+    (0x00, 7),  // SET_DESCRIPTOR
+    (0x80, 8),  // GET_CONFIGURATION
+    (0x00, 9),  // SET_CONFIGURATION
+    (0x81, 10), // GET_INTERFACE
+    (0x01, 11), // SET_INTERFACE
+    (0x82, 12), // SYNCH_FRAME
+    // CDC ACM requests
+    (0x21, 0x20), // SET_LINE_CODING
+    (0xA1, 0x21), // GET_LINE_CODING
+    (0x21, 0x22), // SET_CONTROL_LINE_STATE
+    // HID requests
+    (0xA1, 0x01), // GET_REPORT
+    (0x21, 0x09), // SET_REPORT
+    (0x21, 0x0A), // SET_IDLE
+    (0x21, 0x0B), // SET_PROTOCOL
+    // DFU requests
+    (0x21, 0x00), // DFU_DETACH
+];
+
+// a CDC ACM (interfaces 0 and 1) + HID (2) + isochronous (3) + DFU (4) device with two
+// configurations; the descriptors only need to be told apart, not to be valid
+const NIFACES: usize = 5;
+const CDC_IFACE: u8 = 0;
+const HID_IFACE: u8 = 2;
+const DFU_IFACE: u8 = 4;
+const MAX_ALT_SETTING: [u8; NIFACES] = [0, 0, 0, 1, 0];
+const DEVICE_DESC: [u8; 18] = [18, 1, 0, 2, 0, 0, 0, 64, 0x20, 0x20, 9, 3, 0, 0, 1, 2, 3, 1];
+const CONFIG_DESC1: [u8; 9] = [9, 2, 9, 0, NIFACES as u8, 1, 0, 0x80, 250];
+const CONFIG_DESC2: [u8; 9] = [9, 2, 9, 0, NIFACES as u8, 2, 0, 0x80, 50];
+const STRING_DESCS: [u8; 14] = [4, 3, 0x09, 0x04, 10, 3, b'f', 0, b'u', 0, b'z', 0, b'z', 0];
+const STRING_OFFSETS: [u16; 3] = [0, 4, 14];
+// served by the descriptor provider
+const SERIAL: [u8; 6] = [6, 3, b'4', 0, b'2', 0];
+const SERIAL_INDEX: u8 = 3;
+
+// halted endpoints: bit `n` is IN endpoint `n`; bit `8 + n` is OUT endpoint `n`
+static HALTED: AtomicU16 = AtomicU16::new(0);
+
+// the part of the device state that `control::handle` reads
+struct Model {
+    state: State,
+    alt_settings: [u8; NIFACES],
+    remote_wakeup: bool,
+}
+
+impl Model {
+    fn new() -> Self {
+        HALTED.store(0, Ordering::Relaxed);
+
+        Model {
+            state: State::Default,
+            alt_settings: [0; NIFACES],
+            remote_wakeup: false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    stalled: u64,
+    read: u64,
+    written: u64,
+    acknowledged: u64,
+    configured: u64,
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let args = env::args().skip(1).collect::<Vec<_>>(); // skip program name
+    let iterations = args
+        .get(0)
+        .map(|s| s.parse::<u64>())
+        .transpose()
+        .context("ITERATIONS must be an integer")?
+        .unwrap_or(1_000_000);
+    let seed = args
+        .get(1)
+        .map(|s| s.parse::<u64>())
+        .transpose()
+        .context("SEED must be an integer")?
+        .unwrap_or_else(rand::random);
+
+    println!("seed: {}", seed);
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+
+    let configs = [
+        Config {
+            value: nz(1),
+            epinen: 0b1111,
+            epouten: 0b1101,
+        },
+        Config {
+            value: nz(2),
+            epinen: 0b0111,
+            epouten: 0b0101,
+        },
+    ];
+    let config_descs: [&[u8]; 2] = [&CONFIG_DESC1, &CONFIG_DESC2];
+    let descs = Descriptors {
+        device: &DEVICE_DESC,
+        configs: &configs,
+        config_descs: &config_descs,
+        strings: &STRING_DESCS,
+        string_offsets: &STRING_OFFSETS,
+        provider: Some(provider),
+        max_alt_setting: &MAX_ALT_SETTING,
+        self_powered: false,
+        cdc_iface: Some(CDC_IFACE),
+        hid_iface: Some(HID_IFACE),
+        dfu_iface: Some(DFU_IFACE),
+    };
+
+    let mut model = Model::new();
+    let mut stats = Stats::default();
+    for _ in 0..iterations {
+        // the host resets the bus every now and then
+        if rng.gen_ratio(1, 256) {
+            model = Model::new();
+        }
+
+        let setup = setup(&mut rng);
+
+        match panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            check(setup, &descs, &mut model, &mut stats)
+        })) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => bail!("{:?} in the {} state: {}", setup, state(&model.state), e),
+            Err(_) => bail!("{:?}: the handler panicked", setup),
+        }
+    }
+
+    println!(
+        "{} requests stalled, {} control reads, {} control writes, {} acknowledged \
+         ({} SET_CONFIGURATION)",
+        stats.stalled, stats.read, stats.written, stats.acknowledged, stats.configured
+    );
+
+    Ok(())
+}
+
+fn provider(desc: GetDescriptor) -> Option<&'static [u8]> {
+    match desc {
+        GetDescriptor::String { index, .. } if index == SERIAL_INDEX => Some(&SERIAL),
+        _ => None,
+    }
+}
+
+fn is_halted(ep: u8, dir: usb2::Direction) -> bool {
+    HALTED.load(Ordering::Relaxed) & halt_mask(ep, dir) != 0
+}
+
+fn halt_mask(ep: u8, dir: usb2::Direction) -> u16 {
+    match dir {
+        usb2::Direction::In => 1 << ep,
+        usb2::Direction::Out => 1 << (8 + ep),
+    }
+}
+
+// a random SETUP packet; most of them are based on a known request so they get past the parser
+fn setup(rng: &mut XorShiftRng) -> Setup {
+    let (bmrequesttype, brequest) = if rng.gen_ratio(3, 4) {
+        KNOWN[rng.gen_range(0, KNOWN.len())]
+    } else {
+        (rng.gen(), rng.gen())
+    };
+
+    Setup {
+        bmrequesttype,
+        brequest,
+        wvalue: value(rng),
+        windex: value(rng),
+        wlength: value(rng),
+    }
+}
+
+// favors edge cases and the small values (interface numbers, configuration values, endpoint
+// addresses, descriptor indices) that get past the validation of the request
+fn value(rng: &mut XorShiftRng) -> u16 {
+    match rng.gen_range(0, 7) {
+        0 => 0,
+        1 => u16::max_value(),
+        2 => rng.gen_range(0, 256),
+        3 => rng.gen_range(0, 8),
+        // `bEndpointAddress` of an IN endpoint
+        4 => rng.gen_range(0u16, 8) | 1 << 7,
+        // `wValue` of GET_DESCRIPTOR Device, Configuration or String
+        5 => rng.gen_range(1u16, 4) << 8 | rng.gen_range(0u16, 8),
+        _ => rng.gen(),
+    }
+}
+
+// checks that the response to `setup` is well-formed and applies it to the model
+fn check(
+    setup: Setup,
+    descs: &Descriptors<'_>,
+    model: &mut Model,
+    stats: &mut Stats,
+) -> Result<(), anyhow::Error> {
+    let device = Device {
+        state: &model.state,
+        alt_settings: &model.alt_settings,
+        remote_wakeup: Some(model.remote_wakeup),
+        is_halted,
+    };
+
+    let response = match control::handle(setup, descs, &device) {
+        Ok(response) => response,
+        Err(_) => {
+            stats.stalled += 1;
+            return Ok(());
+        }
+    };
+
+    let device_to_host = setup.bmrequesttype & (1 << 7) != 0;
+    match response {
+        Response::Data(bytes) => {
+            stats.read += 1;
+            ensure!(
+                device_to_host,
+                "data sent in response to a host-to-device request"
+            );
+            ensure!(
+                bytes.len() <= usize::from(setup.wlength),
+                "sent {} bytes but wLength is {}",
+                bytes.len(),
+                setup.wlength
+            );
+
+            let known = descriptors(descs).any(|desc| desc.starts_with(bytes));
+            ensure!(
+                known,
+                "sent {:?}, which is not (part of) a descriptor",
+                bytes
+            );
+        }
+
+        Response::Value(value) => {
+            stats.read += 1;
+            ensure!(
+                device_to_host,
+                "data sent in response to a host-to-device request"
+            );
+            let bytes = value.as_bytes();
+            ensure!(
+                bytes.len() <= usize::from(setup.wlength),
+                "sent {} bytes but wLength is {}",
+                bytes.len(),
+                setup.wlength
+            );
+        }
+
+        Response::GetLineCoding => {
+            stats.read += 1;
+            ensure!(
+                device_to_host,
+                "data sent in response to a host-to-device request"
+            );
+            configured(&model.state)?;
+        }
+
+        Response::SetLineCoding => {
+            stats.written += 1;
+            ensure!(!device_to_host, "data received in a device-to-host request");
+            configured(&model.state)?;
+        }
+
+        Response::SetAddress(state) => {
+            stats.acknowledged += 1;
+            ensure!(!device_to_host, "status stage of a device-to-host request");
+            match model.state {
+                State::Default | State::Address(_) => {}
+                State::Configured { .. } => bail!("SET_ADDRESS accepted in the Configured state"),
+            }
+            if let State::Configured { .. } = state {
+                bail!("SET_ADDRESS moved the device to the Configured state");
+            }
+
+            model.state = state;
+        }
+
+        Response::Status(change) => {
+            stats.acknowledged += 1;
+            ensure!(!device_to_host, "status stage of a device-to-host request");
+            if let Change::Configure(..) = change {
+                stats.configured += 1;
+            }
+
+            apply(descs, model, change)?;
+        }
+    }
+
+    Ok(())
+}
+
+// the descriptors the device may return
+fn descriptors<'a>(descs: &'a Descriptors<'a>) -> impl Iterator<Item = &'a [u8]> {
+    let strings = descs
+        .string_offsets
+        .windows(2)
+        .map(move |w| &descs.strings[usize::from(w[0])..usize::from(w[1])]);
+
+    Some(descs.device)
+        .into_iter()
+        .chain(descs.config_descs.iter().copied())
+        .chain(strings)
+        .chain(Some(&SERIAL[..]))
+}
+
+// checks that `change` is valid in the current state and applies it, like `hal::usbd` does
+fn apply<'a>(
+    descs: &Descriptors<'a>,
+    model: &mut Model,
+    change: Change<'a>,
+) -> Result<(), anyhow::Error> {
+    match change {
+        Change::None => {}
+
+        Change::Configure(state, config) => {
+            let address = match model.state {
+                State::Default => bail!("SET_CONFIGURATION accepted in the Default state"),
+                State::Address(address) | State::Configured { address, .. } => address,
+            };
+
+            match state {
+                State::Configured {
+                    address: new_address,
+                    value,
+                } => {
+                    ensure!(
+                        new_address == address,
+                        "SET_CONFIGURATION changed the address"
+                    );
+                    ensure!(
+                        value == config.value,
+                        "moved to configuration {} but enabled the endpoints of configuration {}",
+                        value,
+                        config.value
+                    );
+                    ensure!(
+                        descs.config(value).is_some(),
+                        "configuration {} doesn't exist",
+                        value
+                    );
+                }
+                _ => bail!("SET_CONFIGURATION didn't move to the Configured state"),
+            }
+
+            model.state = state;
+            model.alt_settings = [0; NIFACES];
+            HALTED.store(0, Ordering::Relaxed);
+        }
+
+        Change::Deconfigure(state) => {
+            let address = match model.state {
+                State::Configured { address, .. } => address,
+                _ => bail!("SET_CONFIGURATION(0) deconfigured a device that's not Configured"),
+            };
+
+            match state {
+                State::Address(new_address) => {
+                    ensure!(
+                        new_address == address,
+                        "SET_CONFIGURATION(0) changed the address"
+                    )
+                }
+                _ => bail!("SET_CONFIGURATION(0) didn't return to the Address state"),
+            }
+
+            model.state = state;
+            model.remote_wakeup = false;
+            HALTED.store(0, Ordering::Relaxed);
+        }
+
+        Change::RemoteWakeup(enabled) => {
+            if enabled {
+                configured(&model.state)?;
+            }
+
+            model.remote_wakeup = enabled;
+        }
+
+        Change::Halt(ep, halt) => {
+            let value = configured(&model.state)?;
+            ensure!(
+                ep.number != 0 && ep.number < 8,
+                "ENDPOINT_HALT on endpoint {}",
+                ep.number
+            );
+
+            let config = descs.config(value).expect("unreachable");
+            let enabled = match ep.direction {
+                usb2::Direction::In => config.epinen,
+                usb2::Direction::Out => config.epouten,
+            };
+            ensure!(
+                enabled & (1 << ep.number) != 0,
+                "ENDPOINT_HALT on endpoint {}, which is not enabled",
+                ep.number
+            );
+
+            let mask = halt_mask(ep.number, ep.direction);
+            if halt {
+                HALTED.fetch_or(mask, Ordering::Relaxed);
+            } else {
+                HALTED.fetch_and(!mask, Ordering::Relaxed);
+            }
+        }
+
+        Change::AltSetting {
+            interface,
+            alternate,
+        } => {
+            configured(&model.state)?;
+            let i = usize::from(interface);
+            ensure!(i < NIFACES, "interface {} doesn't exist", interface);
+            ensure!(
+                alternate <= MAX_ALT_SETTING[i],
+                "interface {} has no alternate setting {}",
+                interface,
+                alternate
+            );
+
+            model.alt_settings[i] = alternate;
+        }
+
+        Change::DfuDetach => {
+            configured(&model.state)?;
+
+            // the device re-enumerates in DFU mode
+            *model = Model::new();
+        }
+    }
+
+    Ok(())
+}
+
+// returns the current configuration
+fn configured(state: &State) -> Result<NonZeroU8, anyhow::Error> {
+    match *state {
+        State::Configured { value, .. } => Ok(value),
+        _ => bail!("request accepted by a device that's not Configured"),
+    }
+}
+
+fn state(state: &State) -> &'static str {
+    match state {
+        State::Default => "Default",
+        State::Address(_) => "Address",
+        State::Configured { .. } => "Configured",
+    }
+}
+
+fn nz(x: u8) -> NonZeroU8 {
+    NonZeroU8::new(x).expect("unreachable")
+}
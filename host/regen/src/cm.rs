@@ -20,11 +20,15 @@ pub fn device() -> Device<'static> {
 fn peripherals() -> Vec<Peripheral<'static>> {
     vec![
         Peripheral {
+            block_size: None,
             description: Some("Debug Control Block".into()),
+            devices: vec![],
             instances: Instances::Single {
                 base_address: 0xE000_EDF0,
             },
+            interrupt: None,
             name: "DCB".into(),
+            regions: vec![],
             registers: vec![
                 {
                     let fields = vec![
@@ -33,30 +37,45 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                             name: "C_DEBUGEN".into(),
                             offset: 0,
                             width: 1,
+                            values: vec![],
+                            unsafe_write: false,
+                            min_rev: None,
                         },
                         Bitfield {
                             description: None,
                             name: "C_HALT".into(),
                             offset: 1,
                             width: 1,
+                            values: vec![],
+                            unsafe_write: false,
+                            min_rev: None,
                         },
                         Bitfield {
                             description: None,
                             name: "C_STEP".into(),
                             offset: 2,
                             width: 1,
+                            values: vec![],
+                            unsafe_write: false,
+                            min_rev: None,
                         },
                         Bitfield {
                             description: None,
                             name: "C_MASKINTS".into(),
                             offset: 3,
                             width: 1,
+                            values: vec![],
+                            unsafe_write: false,
+                            min_rev: None,
                         },
                         Bitfield {
                             description: None,
                             name: "C_SNAPSTALL".into(),
                             offset: 5,
                             width: 1,
+                            values: vec![],
+                            unsafe_write: false,
+                            min_rev: None,
                         },
                     ];
 
@@ -67,36 +86,54 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "S_REGRDY".into(),
                         offset: 16,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "S_HALT".into(),
                         offset: 17,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "S_SLEEP".into(),
                         offset: 18,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "S_LOCKUP".into(),
                         offset: 19,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "S_RETIRE_ST".into(),
                         offset: 24,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "S_RESET_ST".into(),
                         offset: 25,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     let mut w_fields = fields;
@@ -105,6 +142,9 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "DBGKEY".into(),
                         offset: 16,
                         width: 16,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     // section C1.6.2 of (ARM)
@@ -113,8 +153,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                             unsafe_write: false,
                         },
                         description: Some("Debug Halting Control and Status Register".into()),
+                        min_rev: None,
                         name: "DHCSR".into(),
                         offset: 0x00,
+                        region: None,
                         r_fields,
                         w_fields,
                         width: Width::U32,
@@ -127,12 +169,18 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "REGSEL".into(),
                         offset: 0,
                         width: 7,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "REGWnR".into(),
                         offset: 16,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     Register {
@@ -140,8 +188,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                             unsafe_write: false,
                         },
                         description: Some("Debug Core Register Selector Register".into()),
+                        min_rev: None,
                         name: "DCRSR".into(),
                         offset: 0x04,
+                        region: None,
                         r_fields: vec![],
                         w_fields,
                         width: Width::U32,
@@ -151,8 +201,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                     Register {
                         access: Access::ReadWrite { unsafe_write: true },
                         description: Some("Debug Core Register Data Register".into()),
+                        min_rev: None,
                         name: "DCRDR".into(),
                         offset: 0x08,
+                        region: None,
                         r_fields: vec![],
                         w_fields: vec![],
                         width: Width::U32,
@@ -165,72 +217,108 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "VC_CORERESET".into(),
                         offset: 0,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "VC_MMERR".into(),
                         offset: 4,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "VC_NOCPERR".into(),
                         offset: 5,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "VC_CHKERR".into(),
                         offset: 6,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "VC_STATERR".into(),
                         offset: 7,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "VC_BUSERR".into(),
                         offset: 8,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "VC_INTERR".into(),
                         offset: 9,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "VC_HARDERR".into(),
                         offset: 10,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "MON_EN".into(),
                         offset: 16,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "MON_PEND".into(),
                         offset: 17,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "MON_STEP".into(),
                         offset: 18,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "MON_REQ".into(),
                         offset: 19,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: Some(
@@ -240,6 +328,9 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "TRCENA".into(),
                         offset: 24,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     // section C.1.6.5 of (ARM)
@@ -248,8 +339,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                             unsafe_write: false,
                         },
                         description: Some("Debug Exception and Monitor Control Register".into()),
+                        min_rev: None,
                         name: "DEMCR".into(),
                         offset: 0x0c,
+                        region: None,
                         r_fields: fields.clone(),
                         w_fields: fields,
                         width: Width::U32,
@@ -258,11 +351,15 @@ fn peripherals() -> Vec<Peripheral<'static>> {
             ],
         },
         Peripheral {
+            block_size: None,
             description: Some("Data Watchpoint and Trace".into()),
+            devices: vec![],
             instances: Instances::Single {
                 base_address: 0xE000_1000,
             },
+            interrupt: None,
             name: "DWT".into(),
+            regions: vec![],
             registers: vec![
                 {
                     let mut w_fields = vec![];
@@ -274,78 +371,117 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "CYCCNTENA".into(),
                         offset: 0,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "POSTPRESET".into(),
                         offset: 1,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "POSTINIT".into(),
                         offset: 5,
                         width: 4,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "CYCTAP".into(),
                         offset: 9,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "SYNCTAP".into(),
                         offset: 10,
                         width: 2,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "PCSAMPLENA".into(),
                         offset: 12,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "EXCTRCENA".into(),
                         offset: 16,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "CPIEVTENA".into(),
                         offset: 17,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "EXCEVTENA".into(),
                         offset: 18,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "SLEEPEVTENA".into(),
                         offset: 19,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "LSUEVTENA".into(),
                         offset: 20,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "FOLDEVTENA".into(),
                         offset: 21,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "CYCEVTENA".into(),
                         offset: 22,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     let mut r_fields = w_fields.clone();
@@ -354,30 +490,45 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "NOPRFCNT".into(),
                         offset: 24,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "NOCYCCNT".into(),
                         offset: 25,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "NOEXTTRIG".into(),
                         offset: 26,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "NOTRCPKT".into(),
                         offset: 27,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "NUMCOMP".into(),
                         offset: 28,
                         width: 4,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     // section C1.8.7 of (ARM)
@@ -386,8 +537,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                             unsafe_write: false,
                         },
                         description: Some("Control register".into()),
+                        min_rev: None,
                         name: "CTRL".into(),
                         offset: 0x0,
+                        region: None,
                         r_fields,
                         w_fields,
                         width: Width::U32,
@@ -399,8 +552,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         unsafe_write: false,
                     },
                     description: Some("Cycle Count register".into()),
+                    min_rev: None,
                     name: "CYCCNT".into(),
                     offset: 0x4,
+                    region: None,
                     r_fields: vec![],
                     w_fields: vec![],
                     width: Width::U32,
@@ -408,18 +563,24 @@ fn peripherals() -> Vec<Peripheral<'static>> {
             ],
         },
         Peripheral {
+            block_size: None,
             description: Some("Nested Vector Interrupt Controller".into()),
+            devices: vec![],
             instances: Instances::Single {
                 base_address: 0xE000_E100,
             },
+            interrupt: None,
             name: "NVIC".into(),
+            regions: vec![],
             registers: vec![
                 // NOTE(unsafe_write) enabling interrupts can break critical section
                 Register {
                     access: Access::ReadWrite { unsafe_write: true },
                     description: Some("Interrupt Set-Enable Register 0".into()),
+                    min_rev: None,
                     name: "ISER0".into(),
                     offset: 0x0,
+                    region: None,
                     r_fields: vec![],
                     w_fields: vec![],
                     width: Width::U32,
@@ -427,8 +588,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                 Register {
                     access: Access::ReadWrite { unsafe_write: true },
                     description: Some("Interrupt Set-Enable Register 1".into()),
+                    min_rev: None,
                     name: "ISER1".into(),
                     offset: 0x4,
+                    region: None,
                     r_fields: vec![],
                     w_fields: vec![],
                     width: Width::U32,
@@ -438,8 +601,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         unsafe_write: false,
                     },
                     description: Some("Interrupt Clear-Enable Register 0".into()),
+                    min_rev: None,
                     name: "ICER0".into(),
                     offset: 0x80,
+                    region: None,
                     r_fields: vec![],
                     w_fields: vec![],
                     width: Width::U32,
@@ -449,8 +614,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         unsafe_write: false,
                     },
                     description: Some("Interrupt Clear-Enable Register 1".into()),
+                    min_rev: None,
                     name: "ICER1".into(),
                     offset: 0x84,
+                    region: None,
                     r_fields: vec![],
                     w_fields: vec![],
                     width: Width::U32,
@@ -460,8 +627,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         unsafe_write: false,
                     },
                     description: Some("Interrupt Set-Pending Register 0".into()),
+                    min_rev: None,
                     name: "ISPR0".into(),
                     offset: 0x100,
+                    region: None,
                     r_fields: vec![],
                     w_fields: vec![],
                     width: Width::U32,
@@ -471,8 +640,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         unsafe_write: false,
                     },
                     description: Some("Interrupt Set-Pending Register 1".into()),
+                    min_rev: None,
                     name: "ISPR1".into(),
                     offset: 0x104,
+                    region: None,
                     r_fields: vec![],
                     w_fields: vec![],
                     width: Width::U32,
@@ -480,11 +651,15 @@ fn peripherals() -> Vec<Peripheral<'static>> {
             ],
         },
         Peripheral {
+            block_size: None,
             description: Some("System Control Block".into()),
+            devices: vec![],
             instances: Instances::Single {
                 base_address: 0xE000_ED00,
             },
+            interrupt: None,
             name: "SCB".into(),
+            regions: vec![],
             registers: vec![
                 {
                     let mut r_fields = vec![];
@@ -493,38 +668,55 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "REVISION".into(),
                         offset: 0,
                         width: 4,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "PARTNO".into(),
                         offset: 4,
                         width: 12,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "ARCHITECTURE".into(),
                         offset: 16,
                         width: 4,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "VARIANT".into(),
                         offset: 20,
                         width: 4,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "IMPLEMENTER".into(),
                         offset: 24,
                         width: 8,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     // section B3.2.3 of (ARM)
                     Register {
                         access: Access::ReadOnly,
                         description: Some("CPUID Base register".into()),
+                        min_rev: None,
                         name: "CPUID".into(),
                         offset: 0x0,
+                        region: None,
                         r_fields,
                         w_fields: vec![],
                         width: Width::U32,
@@ -537,18 +729,27 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "PENDSTSET".into(),
                         offset: 26,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "PENDSVSET".into(),
                         offset: 28,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "NMIPENDSET".into(),
                         offset: 31,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     let mut w_fields = fields.clone();
@@ -558,12 +759,18 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "PENDSTCLR".into(),
                         offset: 25,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "PENDSVCLR".into(),
                         offset: 27,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     let mut r_fields = fields;
@@ -573,30 +780,45 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                     name: "VECTACTIVE".into(),
                     offset: 0,
                     width: 9,
+                    values: vec![],
+                    unsafe_write: false,
+                    min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "RETTOBASE".into(),
                         offset: 11,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "VECTPENDING".into(),
                         offset: 12,
                         width: 9,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "ISRPENDING".into(),
                         offset: 22,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "ISRPREEMPT".into(),
                         offset: 23,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     // section B3.2.4 of (ARM)
@@ -605,8 +827,10 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                             unsafe_write: false,
                         },
                         description: Some("Interrupt Control and State Register".into()),
+                        min_rev: None,
                         name: "ICSR".into(),
                         offset: 0x4,
+                        region: None,
                         r_fields,
                         w_fields,
                         width: Width::U32,
@@ -618,14 +842,19 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "TBLOFF".into(),
                         offset: 7,
                         width: 25,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     }];
 
                     // section B3.2.5 of (ARM)
                     Register {
                         access: Access::ReadWrite { unsafe_write: true },
                         description: Some("Vector Table Offset Register".into()),
+                        min_rev: None,
                         name: "VTOR".into(),
                         offset: 0x8,
+                        region: None,
                         r_fields: fields.clone(),
                         w_fields: fields,
                         width: Width::U32,
@@ -638,12 +867,18 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "SYSRESETREQ".into(),
                         offset: 2,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     fields.push(Bitfield {
                         description: None,
                         name: "PRIGROUP".into(),
                         offset: 8,
                         width: 3,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     let mut r_fields = fields.clone();
@@ -652,12 +887,18 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "ENDIANNESS".into(),
                         offset: 15,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     r_fields.push(Bitfield {
                         description: None,
                         name: "VECTKEYSTAT".into(),
                         offset: 16,
                         width: 16,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     let mut w_fields = fields;
@@ -666,18 +907,27 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         name: "VECTRESET".into(),
                         offset: 0,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "VECTCLRACTIVE".into(),
                         offset: 1,
                         width: 1,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
                     w_fields.push(Bitfield {
                         description: None,
                         name: "VECTKEY".into(),
                         offset: 16,
                         width: 16,
+                        values: vec![],
+                        unsafe_write: false,
+                        min_rev: None,
                     });
 
                     Register {
@@ -685,10 +935,12 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                             unsafe_write: false,
                         },
                         description: Some(
+                        min_rev: None,
                             "Application Interrupt and Reset Control Register".into(),
                         ),
                         name: "AIRCR".into(),
                         offset: 0xc,
+                        region: None,
                         r_fields,
                         w_fields,
                         width: Width::U32,
@@ -8,7 +8,7 @@ use rand_xorshift::XorShiftRng;
 
 use crate::{
     codegen,
-    ir::{Device, Instances, Peripheral, Register},
+    ir::{Bitfield, Device, Instances, Peripheral, Register},
 };
 
 mod util;
@@ -32,23 +32,85 @@ fn common(name: &str, extra_docs: &Option<Cow<'_, str>>) -> TokenStream2 {
         doc.push_str("\n\n");
         doc.push_str(extra_docs);
     }
+    // NOTE no `deny(warnings)`: new lints in a future toolchain would break the generated crate
     quote!(
         #![allow(intra_doc_link_resolution_failure)]
         #![deny(missing_docs)]
         #![deny(rust_2018_compatibility)]
         #![deny(rust_2018_idioms)]
-        #![deny(warnings)]
         #![doc = #doc]
         #![no_std]
 
         use core::marker::PhantomData;
 
+        // source of the atomics that back the singletons; targets without native atomics can use
+        // the `portable-atomic` crate instead of `core`
+        #[allow(unused_imports)]
+        mod atomic {
+            #[cfg(not(feature = "portable-atomic"))]
+            pub use core::sync::atomic::{AtomicBool, Ordering};
+            #[cfg(feature = "portable-atomic")]
+            pub use portable_atomic::{AtomicBool, Ordering};
+        }
+
         /// An instance of a peripheral
         pub trait Peripheral {
             /// The base address of the peripheral instance
             fn base_address() -> usize;
         }
 
+        /// An event register, e.g. `EVENTS_END`
+        ///
+        /// Its address can be written to the event end point (EEP) of a PPI channel
+        pub trait Event {
+            /// The address of the register
+            const ADDRESS: u32;
+        }
+
+        /// A task register, e.g. `TASKS_START`
+        ///
+        /// Its address can be written to the task end point (TEP) of a PPI channel
+        pub trait Task {
+            /// The address of the register
+            const ADDRESS: u32;
+        }
+
+        /// A task register whose `trigger` method is safe to call
+        ///
+        /// Tasks that start EasyDMA transfers are not `SafeTask`s
+        pub trait SafeTask: Task {}
+
+        /// The location of a register block in the address space
+        pub trait Base: Copy {
+            /// The address of the first register of the block
+            fn base_address(&self) -> usize;
+        }
+
+        /// A register block located at an address picked at runtime (see `Registers::at`)
+        #[derive(Clone, Copy)]
+        pub struct At {
+            address: usize,
+        }
+
+        impl Base for At {
+            #[inline(always)]
+            fn base_address(&self) -> usize {
+                self.address
+            }
+        }
+
+        // Data Synchronization Barrier: the writes that precede it reach the peripherals before
+        // the next instruction executes. A no-op on other architectures, e.g. on the host, where
+        // the register API is tested
+        #[allow(dead_code)]
+        #[inline(always)]
+        fn dsb() {
+            #[cfg(target_arch = "arm")]
+            unsafe {
+                core::arch::asm!("dsb", options(nostack, preserves_flags))
+            }
+        }
+
         #[allow(dead_code)]
         struct NotSendOrSync {
             inner: PhantomData<*mut ()>,
@@ -75,62 +137,255 @@ fn peripheral(peripheral: &Peripheral<'_>, rng: &mut XorShiftRng) -> TokenStream
     let mut items = vec![];
     let mut field_decls = vec![];
     let mut field_exprs = vec![];
+    let mut table = vec![];
 
     for reg in &peripheral.registers {
         items.push(codegen::register(reg, rng));
 
+        let name_s = &*reg.name;
+        let offset = util::hex(reg.offset);
+        // NOTE registers of newer silicon revisions are left out of the table
+        if reg.min_rev.is_none() {
+            if let Some(region) = &reg.region {
+                let region = format_ident!("{}_REGION", *region.name);
+                table.push(quote!((#name_s, BASE_ADDRESS + #region + #offset)));
+            } else {
+                table.push(quote!((#name_s, BASE_ADDRESS + #offset)));
+            }
+        }
+
         let doc = reg
             .description
             .as_ref()
             .map(|s| Cow::from(&**s))
             .unwrap_or_else(|| format!("{} register", reg.name).into());
         let name = format_ident!("{}", *reg.name);
+        let mod_name = util::ident(&reg.name.to_snake_case());
+        let cfg = util::rev_cfg(reg.min_rev);
         field_decls.push(quote!(
+            #cfg
             #[doc = #doc]
-            pub #name: #name
+            pub #name: #mod_name::Register<B>
         ));
         field_exprs.push(quote!(
-            #name: #name::new()
+            #cfg
+            #name: #mod_name::Register::at(base)
         ));
     }
 
+    let events = peripheral
+        .registers
+        .iter()
+        .filter(|reg| {
+            reg.name.starts_with("EVENTS_")
+                && reg.min_rev.is_none()
+                && reg.access.can_write()
+                && !reg.access.write_is_unsafe()
+        })
+        .map(|reg| format_ident!("{}", *reg.name))
+        .collect::<Vec<_>>();
+    let clear_all_events = if events.is_empty() {
+        quote!()
+    } else {
+        quote!(
+            /// Clears all the events of the peripheral
+            ///
+            /// This clears pending interrupt sources but doesn't disable them (see `INTENCLR`). The
+            /// writes are followed by a DSB so the events are cleared when this returns; otherwise
+            /// an interrupt handler that ends with this call may be entered again
+            pub fn clear_all_events(&self) {
+                #(self.#events.zero();)*
+                crate::dsb();
+            }
+        )
+    };
+
+    let regions = peripheral.regions.iter().map(|region| {
+        let name = format_ident!("{}_REGION", *region.name);
+        let offset = util::hex(region.offset);
+        let doc = format!(
+            "Distance of the {} address range from the base address, in bytes",
+            region.name
+        );
+        quote!(
+            #[doc = #doc]
+            pub const #name: usize = #offset;
+        )
+    });
+
+    // extent of the registers that live at the base address: offset of the first one and offset
+    // one past the end of the last one
+    let base_regs = peripheral
+        .registers
+        .iter()
+        .filter(|reg| reg.region.is_none());
+    let first = base_regs.clone().map(|reg| reg.offset).min();
+    let last = base_regs
+        .map(|reg| reg.offset + u64::from(reg.width.bits() / 8))
+        .max();
+
+    let size = last.map(|last| {
+        // rounded up to a whole number of words so the span can be read with 32-bit accesses
+        let size = util::hex((last + 3) & !3);
+        quote!(
+            /// Span of the registers at `BASE_ADDRESS`, in bytes: from the base address to past the
+            /// last register, rounded up to a multiple of 4
+            ///
+            /// NOTE this is the extent of the registers, not the size of the address block
+            /// declared in the SVD file, which is usually larger (e.g. 4 KB)
+            pub const SIZE: usize = #size;
+        )
+    });
+
+    // a register that lands outside the address block declared in the SVD has a bad offset
+    let mut block_asserts = vec![];
+    if let Some(size) = peripheral.block_size {
+        if let (Some(first), Some(last)) = (first, last) {
+            let size = util::hex(size);
+            let first = util::hex(first);
+            let last = util::hex(last);
+            block_asserts.push(quote!(
+                /// Size of the address block that starts at `BASE_ADDRESS`, in bytes
+                #[cfg(feature = "test")]
+                const BLOCK_SIZE: usize = #size;
+
+                #[cfg(feature = "test")]
+                const _: [(); 1] = [(); (BASE_ADDRESS + #first < BASE_ADDRESS + BLOCK_SIZE) as usize];
+                #[cfg(feature = "test")]
+                const _: [(); 1] = [(); (BASE_ADDRESS + #last <= BASE_ADDRESS + BLOCK_SIZE) as usize];
+            ));
+        }
+    }
+
+    let irq = peripheral.interrupt.as_ref().map(|interrupt| {
+        let number = util::unsuffixed(interrupt.number);
+        let doc = format!(
+            "The interrupt number of the peripheral (`{}` in the vector table)",
+            interrupt.name
+        );
+        quote!(
+            #[doc = #doc]
+            pub const IRQ: u8 = #number;
+        )
+    });
+
     let doc = format!("Singleton handle to the {} registers", peripheral.name);
     items.push(quote!(
-        use core::sync::atomic::{AtomicBool, Ordering};
+        use crate::atomic::{AtomicBool, Ordering};
 
         const BASE_ADDRESS: usize = #base_addr;
 
+        #(#regions)*
+
+        #size
+
+        #(#block_asserts)*
+
+        /// The name, as it appears in the SVD file, and the address of every register
+        #[cfg(feature = "register-table")]
+        pub const REGISTERS: &[(&str, usize)] = &[#(#table,)*];
+
+        /// The fixed location of the peripheral
+        #[derive(Clone, Copy)]
+        pub struct Fixed;
+
+        impl crate::Base for Fixed {
+            #[inline(always)]
+            fn base_address(&self) -> usize {
+                BASE_ADDRESS
+            }
+        }
+
         #[allow(non_snake_case)]
         #[doc = #doc]
-        pub struct Registers {
+        ///
+        /// The `B` parameter is the location of the registers; it's `Fixed` (the address
+        /// documented in the reference manual) unless the handle was created with `at`
+        pub struct Registers<B = Fixed> {
             #(#field_decls,)*
         }
 
         unsafe impl Send for Registers {}
 
-        impl Registers {
+        impl<B> Registers<B>
+        where
+            B: crate::Base,
+        {
             /// # Safety
             /// Singleton
-            unsafe fn new() -> Self {
+            unsafe fn with_base(base: B) -> Self {
                 Self {
                     #(#field_exprs,)*
                 }
             }
 
+            #clear_all_events
+        }
+
+        impl Registers {
+            #irq
+
+            /// # Safety
+            /// Singleton
+            unsafe fn new() -> Self {
+                Self::with_base(Fixed)
+            }
+
+            /// Returns a handle to a copy of the register block located at address `base`
+            ///
+            /// Meant to run the register code against a different memory region, e.g. a simulated
+            /// register block in host tests. The singleton (`take`, `borrow_unchecked`) keeps using
+            /// the fixed address, at no runtime cost
+            ///
+            /// # Safety
+            /// The memory region that starts at `base` must be valid for volatile reads and writes
+            /// for as long as the handle is in use and must be as large as the register block
+            pub unsafe fn at(base: usize) -> Registers<crate::At> {
+                Registers::with_base(crate::At { address: base })
+            }
+
             fn taken() -> &'static AtomicBool {
                 static TAKEN: AtomicBool = AtomicBool::new(false);
                 &TAKEN
             }
 
+            fn sealed() -> &'static AtomicBool {
+                static SEALED: AtomicBool = AtomicBool::new(false);
+                &SEALED
+            }
+
             /// Grants temporary access to the peripheral, without checking if it has already been
             /// taken
+            ///
+            /// Meant for the HAL, which seals the peripherals it manages; application code should
+            /// use `take` or `try_borrow`, which respect ownership
             #[inline(always)]
             pub fn borrow_unchecked<T>(f: impl FnOnce(&Self) -> T) -> T {
                 f(unsafe{ &Self::new() })
             }
 
+            /// Grants temporary, exclusive access to the peripheral if nobody owns it
+            ///
+            /// Returns `None`, without calling `f`, if the peripheral has been taken (see `take`)
+            /// or sealed (see `seal`), or if it's being borrowed by another context
+            pub fn try_borrow<T>(f: impl FnOnce(&Self) -> T) -> Option<T> {
+                let taken = Self::taken();
+
+                if taken
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    let ret = f(unsafe { &Self::new() });
+                    taken.store(false, Ordering::Release);
+                    Some(ret)
+                } else {
+                    None
+                }
+            }
+
             /// Seals the peripheral making it impossible to `take` it
             pub fn seal() {
+                Self::sealed().store(true, Ordering::Relaxed);
                 Self::taken().store(true, Ordering::Relaxed)
             }
 
@@ -149,6 +404,16 @@ fn peripheral(peripheral: &Peripheral<'_>, rng: &mut XorShiftRng) -> TokenStream
                     None
                 }
             }
+
+            /// Gives back ownership of the peripheral so that it can be `take`-n again
+            ///
+            /// NOTE if the peripheral was sealed (see `seal`) while taken this is a no-op: the
+            /// peripheral stays sealed
+            pub fn release(self) {
+                if !Self::sealed().load(Ordering::Relaxed) {
+                    Self::taken().store(false, Ordering::Release)
+                }
+            }
         }
     ));
 
@@ -156,13 +421,22 @@ fn peripheral(peripheral: &Peripheral<'_>, rng: &mut XorShiftRng) -> TokenStream
     let name = format_ident!("{}", *peripheral.name);
     let name_s = &peripheral.name;
     let mod_name = util::ident(&peripheral.name.to_snake_case());
+    // only some of the devices have this peripheral
+    let devices = if peripheral.devices.is_empty() {
+        quote!()
+    } else {
+        let devices = &peripheral.devices;
+        quote!(#[cfg(any(#(feature = #devices),*))])
+    };
     quote!(
         #[allow(non_camel_case_types)]
         #[cfg(feature = #name_s)]
+        #devices
         #[doc = #doc]
         pub type #name = #mod_name::Registers;
 
         #[cfg(feature = #name_s)]
+        #devices
         #[doc = #doc]
         pub mod #mod_name {
             #(#items)*
@@ -188,7 +462,8 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
                 let field_name = format_ident!("{}", *field.name);
                 let offset = util::unsuffixed(field.offset);
                 let mask = util::hex(field.mask());
-                let doc = util::field_docs(&field);
+                let doc = util::field_docs(register, &field);
+                let cfg = util::rev_cfg(field.min_rev);
 
                 let fname = &field.name;
                 let adapter = if field.width < 4 {
@@ -197,7 +472,28 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
                     format_ident!("Hex{}", (field.width - 1) / 4 + 1)
                 };
                 chain.push(quote!(field(#fname, &regen_ufmt::#adapter(self.#field_name()))?));
+
+                let variant = if field.values.is_empty() {
+                    quote!()
+                } else {
+                    let variant_name = format_ident!("{}_variant", *field.name);
+                    let doc = format!(
+                        "Reads the {} bitfield as one of its enumerated values; returns `None` if \
+                         the bits are a reserved encoding",
+                        field.name
+                    );
+                    quote!(
+                        #cfg
+                        #[allow(non_snake_case)]
+                        #[doc = #doc]
+                        pub fn #variant_name(self) -> Option<#field_name> {
+                            core::convert::TryFrom::try_from(self.#field_name()).ok()
+                        }
+                    )
+                };
+
                 quote!(
+                    #cfg
                     #[allow(non_snake_case)]
                     #[doc = #doc]
                     pub fn #field_name(self) -> #fty {
@@ -205,6 +501,8 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
                         const MASK: #fty = #mask;
                         ((self.inner >> OFFSET) as #fty) & MASK
                     }
+
+                    #variant
                 )
             })
             .collect::<Vec<_>>();
@@ -241,10 +539,14 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
             mod_items.push(quote!(
                 /// View into the readable bitfields
                 #[derive(Clone, Copy)]
+                #[repr(transparent)]
                 pub struct R {
                     inner: #rty,
                 }
 
+                // `R` must be layout compatible with the raw register value
+                const _: [(); core::mem::size_of::<#rty>()] = [(); core::mem::size_of::<R>()];
+
                 impl From<#rty> for R {
                     fn from(bits: #rty) -> Self {
                         R { inner: bits }
@@ -280,10 +582,46 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
                 }
             ));
 
+            let decls = register.r_fields.iter().map(|field| {
+                let fty = util::bitwidth2ty(field.width);
+                let field_name = format_ident!("{}", *field.name);
+                let doc = util::field_docs(register, &field);
+                let cfg = util::rev_cfg(field.min_rev);
+                quote!(
+                    #cfg
+                    #[doc = #doc]
+                    pub #field_name: #fty
+                )
+            });
+            let exprs = register.r_fields.iter().map(|field| {
+                let field_name = format_ident!("{}", *field.name);
+                let cfg = util::rev_cfg(field.min_rev);
+                quote!(#cfg #field_name: self.#field_name())
+            });
+            mod_items.push(quote!(
+                /// All the readable bitfields
+                #[allow(non_snake_case)]
+                #[cfg(feature = "decode")]
+                #[derive(Clone, Copy)]
+                pub struct Fields {
+                    #(#decls,)*
+                }
+
+                #[cfg(feature = "decode")]
+                impl R {
+                    /// Reads all the bitfields at once
+                    pub fn decode(self) -> Fields {
+                        Fields {
+                            #(#exprs,)*
+                        }
+                    }
+                }
+            ));
+
             rmethods.push(quote!(
                 /// Reads the contents of the register in a single, volatile instruction
                 pub fn read(&self) -> R {
-                    R::from(unsafe { Self::address().read_volatile() })
+                    R::from(unsafe { self.ptr().read_volatile() })
                 }
             ));
         } else {
@@ -291,7 +629,7 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
                 /// Reads the contents of the register in a single, volatile instruction
                 pub fn read(&self) -> #rty {
                     unsafe {
-                        Self::address().read_volatile()
+                        self.ptr().read_volatile()
                     }
                 }
             ));
@@ -313,12 +651,21 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
                 let field_name = format_ident!("{}", &*field.name);
                 let offset = util::unsuffixed(field.offset);
                 let mask = util::hex(field.mask());
-                let doc = util::field_docs(&field);
+                let doc = util::field_docs(register, &field);
+                let cfg = util::rev_cfg(field.min_rev);
+                // NOTE if the register write is already `unsafe` there's no need to make the
+                // setter `unsafe`
+                let unsafety = if field.unsafe_write && !register.access.write_is_unsafe() {
+                    quote!(unsafe)
+                } else {
+                    quote!()
+                };
 
                 quote!(
+                    #cfg
                     #[doc = #doc]
                     #[allow(non_snake_case)]
-                    pub fn #field_name(&mut self, val: #fty) -> &mut Self {
+                    pub #unsafety fn #field_name(&mut self, val: #fty) -> &mut Self {
                         const OFFSET: u8 = #offset;
                         const MASK: #fty = #mask;
                         self.inner &= !((MASK as #rty) << OFFSET);
@@ -329,14 +676,32 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
             })
             .collect::<Vec<_>>();
 
+        // seeding the writer bypasses the setters so it must be as `unsafe` as the most `unsafe`
+        // setter
+        let seed_unsafety = if !register.access.write_is_unsafe()
+            && register.w_fields.iter().any(|field| field.unsafe_write)
+        {
+            quote!(unsafe)
+        } else {
+            quote!()
+        };
+
         if !methods.is_empty() {
             mod_items.push(quote!(
                 /// View into the writable bitfields
+                ///
+                /// Each setter only modifies the bits of its bitfield so several bitfields can be
+                /// set by chaining setters, e.g. `w.A(1).B(2)`; the register is accessed once, after
+                /// all the setters have run
                 #[derive(Clone, Copy)]
+                #[repr(transparent)]
                 pub struct W {
                     inner: #rty,
                 }
 
+                // `W` must be layout compatible with the raw register value
+                const _: [(); core::mem::size_of::<#rty>()] = [(); core::mem::size_of::<W>()];
+
                 impl From<W> for #rty {
                     fn from(w: W) -> Self {
                         w.inner
@@ -349,6 +714,18 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
                         W { inner: 0 }
                     }
 
+                    /// Writable view seeded with `bits`, e.g. a previously read or reset value
+                    ///
+                    /// Setters called on the returned value only modify their own bitfields
+                    pub #seed_unsafety fn from_bits(bits: #rty) -> W {
+                        W { inner: bits }
+                    }
+
+                    /// Returns the raw value that would be written to the register
+                    pub fn into_bits(self) -> #rty {
+                        self.inner
+                    }
+
                     #(#methods)*
                 }
             ));
@@ -359,20 +736,45 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
                 pub #unsafety fn write(&self, f: impl FnOnce(&mut W) -> &mut W) {
                     let mut w = W::zero();
                     f(&mut w);
-                    #safe { Self::address().write_volatile(w.into()); }
+                    #safe { self.ptr().write_volatile(w.into()); }
                 }
 
                 /// Writes zeros to the register
                 #[inline(always)]
                 pub #unsafety fn zero(&self) {
-                    #safe { Self::address().write_volatile(0); }
+                    #safe { self.ptr().write_volatile(0); }
                 }
             ));
+
+            if register.is_task() {
+                let field = &register.w_fields[0];
+                let bit = util::hex(1 << field.offset);
+                // NOTE a task register has a single bitfield so the setter is as `unsafe` as
+                // writing it
+                let (unsafety, safe) = if field.unsafe_write || register.access.write_is_unsafe() {
+                    (quote!(unsafe), quote!())
+                } else {
+                    (quote!(), quote!(unsafe))
+                };
+                rmethods.push(quote!(
+                    /// Triggers the task
+                    ///
+                    /// The write is followed by a DSB so the task has been triggered when this
+                    /// returns, e.g. before a WFE / WFI puts the core to sleep. The writes that
+                    /// configure the task need no barrier: the core performs volatile stores in
+                    /// program order
+                    #[inline(always)]
+                    pub #unsafety fn trigger(&self) {
+                        #safe { self.ptr().write_volatile(#bit); }
+                        crate::dsb();
+                    }
+                ));
+            }
         } else {
             rmethods.push(quote!(
                 /// Writes `bits` to the register in a single, volatile instruction
                 pub #unsafety fn write(&self, bits: #rty) {
-                    #safe { Self::address().write_volatile(bits); }
+                    #safe { self.ptr().write_volatile(bits); }
                 }
             ));
         }
@@ -427,7 +829,9 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
                 rmethods.push(quote!(
                     /// Updates the contents of the register using the closure `f`
                     ///
-                    /// This performs a `read` operation followed by a `write` operation
+                    /// This performs a `read` operation followed by a `write` operation. `W` is
+                    /// seeded with the value that was read so the bitfields that `f` doesn't set
+                    /// keep their current value; read-only bits are written back as zeros
                     #[inline(always)]
                     pub #unsafety fn rmw(
                         &self,
@@ -436,21 +840,197 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
                         let r = self.read();
                         let mut w = r.into();
                         f(r, &mut w);
-                        #safe { Self::address().write_volatile(w.into()); }
+                        #safe { self.ptr().write_volatile(w.into()); }
+                    }
+
+                    /// Updates some bitfields of the register, leaving the rest unchanged
+                    ///
+                    /// Same as `rmw` but for closures that don't need the value that was read
+                    #[inline(always)]
+                    pub #unsafety fn update(&self, f: impl FnOnce(&mut W) -> &mut W) {
+                        let mut w = self.read().into();
+                        f(&mut w);
+                        #safe { self.ptr().write_volatile(w.into()); }
                     }
                 ));
+
+                let rwmask = util::rwmask(register);
+                if rwmask != 0 {
+                    let rwmask = util::hex(rwmask);
+                    rmethods.push(quote!(
+                        /// Writes the bits set by `f` to the register and reads them back
+                        ///
+                        /// Returns an error if any of the bitfields that can be both written and
+                        /// read doesn't hold the written value, e.g. because the peripheral
+                        /// rejected the write in its current state
+                        #[cfg(feature = "verify")]
+                        pub #unsafety fn write_verified(
+                            &self,
+                            f: impl FnOnce(&mut W) -> &mut W,
+                        ) -> Result<(), ()> {
+                            const MASK: #rty = #rwmask;
+
+                            let mut w = W::zero();
+                            f(&mut w);
+                            let written: #rty = w.into();
+                            #safe { self.ptr().write_volatile(written); }
+                            let read: #rty = self.read().into();
+
+                            if (read ^ written) & MASK == 0 {
+                                Ok(())
+                            } else {
+                                Err(())
+                            }
+                        }
+                    ));
+                }
+
+                for field in register.w_fields.iter().filter(|field| field.width == 1) {
+                    let field_name = format_ident!("{}", &*field.name);
+                    let set = format_ident!("set_{}", &*field.name);
+                    let clear = format_ident!("clear_{}", &*field.name);
+                    let unsafety = if field.unsafe_write || register.access.write_is_unsafe() {
+                        quote!(unsafe)
+                    } else {
+                        quote!()
+                    };
+                    let cfg = util::rev_cfg(field.min_rev);
+                    let set_doc = format!("Sets the {} bit; see `update`", field.name);
+                    let clear_doc = format!("Clears the {} bit; see `update`", field.name);
+
+                    rmethods.push(quote!(
+                        #cfg
+                        #[allow(non_snake_case)]
+                        #[cfg(feature = "critical-section")]
+                        #[doc = #set_doc]
+                        ///
+                        /// The read-modify-write operation runs in a critical section so it can't
+                        /// race with an interrupt handler that updates the same register, at the
+                        /// cost of disabling interrupts for a few instructions
+                        pub #unsafety fn #set(&self) {
+                            critical_section::with(|_| self.update(|w| w.#field_name(1)))
+                        }
+
+                        #cfg
+                        #[allow(non_snake_case)]
+                        #[cfg(feature = "critical-section")]
+                        #[doc = #clear_doc]
+                        ///
+                        /// The read-modify-write operation runs in a critical section; see the
+                        /// setter
+                        pub #unsafety fn #clear(&self) {
+                            critical_section::with(|_| self.update(|w| w.#field_name(0)))
+                        }
+                    ));
+                }
             }
 
             _ => unimplemented!(),
         }
     }
 
-    let address = if register.offset == 0 {
-        quote!(super::BASE_ADDRESS)
-    } else {
+    // fields that appear in both `R` and `W` get a single enum
+    let mut enum_fields: Vec<&Bitfield<'_>> = vec![];
+    for field in register.r_fields.iter().chain(&register.w_fields) {
+        if !field.values.is_empty() && !enum_fields.iter().any(|f| f.name == field.name) {
+            enum_fields.push(field);
+        }
+    }
+    for field in enum_fields {
+        mod_items.push(codegen::field_enum(field));
+    }
+
+    // a field that doesn't fit in the register would read or write adjacent bits
+    let bits = util::unsuffixed(register.width.bits());
+    let mut bounded_fields: Vec<&Bitfield<'_>> = vec![];
+    for field in register.r_fields.iter().chain(&register.w_fields) {
+        if !bounded_fields.iter().any(|f| f.same_bits(field)) {
+            bounded_fields.push(field);
+        }
+    }
+    for field in bounded_fields {
+        let offset = util::unsuffixed(field.offset);
+        let width = util::unsuffixed(field.width);
+        mod_items.push(quote!(
+            const _: [(); 1] = [(); (#offset + #width <= #bits) as usize];
+        ));
+    }
+
+    // masks to test several bitfields at once on the raw register value, e.g. `u32::from(r)`
+    let mut const_fields: Vec<&Bitfield<'_>> = vec![];
+    for field in register.r_fields.iter().chain(&register.w_fields) {
+        if !const_fields.iter().any(|f| f.name == field.name) {
+            const_fields.push(field);
+        }
+    }
+    for field in const_fields {
+        let mask_name = format_ident!("{}_MASK", *field.name);
+        let offset_name = format_ident!("{}_OFFSET", *field.name);
+        let mask = util::hex(field.mask() << field.offset);
+        let offset = util::unsuffixed(field.offset);
+        let mask_doc = format!("The bits of the {} bitfield, in place", field.name);
+        let offset_doc = format!(
+            "The position of the lowest bit of the {} bitfield",
+            field.name
+        );
+        let cfg = util::rev_cfg(field.min_rev);
+        mod_items.push(quote!(
+            #cfg
+            #[allow(non_upper_case_globals)]
+            #[doc = #mask_doc]
+            pub const #mask_name: #rty = #mask;
+
+            #cfg
+            #[allow(non_upper_case_globals)]
+            #[doc = #offset_doc]
+            pub const #offset_name: u8 = #offset;
+        ));
+    }
+
+    // NOTE the common case, a single address range, adds no terms to the address computation
+    let mut rel = vec![];
+    if let Some(region) = &register.region {
+        let region = format_ident!("{}_REGION", *region.name);
+        rel.push(quote!(super::#region));
+    }
+    if register.offset != 0 {
         let offset = util::hex(register.offset);
-        quote!((super::BASE_ADDRESS + #offset))
+        rel.push(quote!(#offset));
+    }
+    let (address, ptr) = if rel.is_empty() {
+        (
+            quote!(super::BASE_ADDRESS),
+            quote!(self.base.base_address()),
+        )
+    } else {
+        (
+            quote!((super::BASE_ADDRESS #(+ #rel)*)),
+            quote!((self.base.base_address() #(+ #rel)*)),
+        )
     };
+    if register.is_event() {
+        mod_items.push(quote!(
+            impl crate::Event for Register {
+                const ADDRESS: u32 = #address as u32;
+            }
+        ));
+    }
+
+    if register.is_task() {
+        mod_items.push(quote!(
+            impl crate::Task for Register {
+                const ADDRESS: u32 = #address as u32;
+            }
+        ));
+
+        let field = &register.w_fields[0];
+        if !field.unsafe_write && !register.access.write_is_unsafe() {
+            mod_items.push(quote!(
+                impl crate::SafeTask for Register {}
+            ));
+        }
+    }
+
     let doc = register
         .description
         .as_ref()
@@ -461,32 +1041,80 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
     } else {
         quote!(*const #rty)
     };
+    // unlike `address`, the pointers follow the base address of the handle (see `at`)
+    let as_mut_ptr = if register.access.can_write() {
+        Some(quote!(
+            /// Returns a raw pointer to the register, for writing
+            ///
+            /// See `as_ptr` for the contract that users of the pointer must uphold
+            #[inline(always)]
+            pub fn as_mut_ptr(&self) -> *mut #rty {
+                self.ptr()
+            }
+        ))
+    } else {
+        None
+    };
+    let cfg = util::rev_cfg(register.min_rev);
     quote!(
+        #cfg
         #[allow(non_camel_case_types)]
         #[doc = #doc]
         pub type #name = #mod_name::Register;
 
+        #cfg
         #[doc = #doc]
         pub mod #mod_name {
             use crate::NotSendOrSync;
 
             /// Singleton handle to the register
-            pub struct Register {
+            pub struct Register<B = super::Fixed> {
+                base: B,
                 _not_send_or_sync: NotSendOrSync,
             }
 
             impl Register {
+                /// Returns the address of this register
+                pub fn address() -> #pty {
+                    #address as *mut _
+                }
+
+                /// Returns the address of this register as an integer
+                ///
+                /// Useful to connect `EVENTS_*` and `TASKS_*` registers through the PPI
+                pub const fn address_u32() -> u32 {
+                    #address as u32
+                }
+            }
+
+            impl<B> Register<B>
+            where
+                B: crate::Base,
+            {
                 /// # Safety
                 /// Singleton
-                pub(crate) unsafe fn new() -> Self {
-                    Self { _not_send_or_sync: NotSendOrSync::new() }
+                pub(crate) unsafe fn at(base: B) -> Self {
+                    Self { base, _not_send_or_sync: NotSendOrSync::new() }
                 }
 
-                /// Returns the address of this register
-                pub fn address() -> #pty {
-                    #address as *mut _
+                #[inline(always)]
+                fn ptr(&self) -> #pty {
+                    #ptr as *mut _
                 }
 
+                /// Returns a raw pointer to the register
+                ///
+                /// Meant for code that hands the register to hardware, e.g. a DMA engine. Accesses
+                /// through the pointer must be volatile (`read_volatile` / `write_volatile`) and
+                /// register-sized, and writes bypass the `unsafe` audit of the `write` methods;
+                /// never create a reference (`&` / `&mut`) to the register from it
+                #[inline(always)]
+                pub fn as_ptr(&self) -> *const #rty {
+                    self.ptr()
+                }
+
+                #as_mut_ptr
+
                 #(#rmethods)*
             }
 
@@ -494,3 +1122,768 @@ fn register(register: &Register<'_>, rng: &mut XorShiftRng) -> TokenStream2 {
         }
     )
 }
+
+fn field_enum(field: &Bitfield<'_>) -> TokenStream2 {
+    let fty = util::bitwidth2ty(field.width);
+    let name = format_ident!("{}", *field.name);
+    let doc = format!("Values of the {} bitfield", field.name);
+    let cfg = util::rev_cfg(field.min_rev);
+
+    let mut variants = vec![];
+    let mut arms = vec![];
+    for ev in &field.values {
+        let variant = util::variant(&ev.name);
+        let value = util::hex(ev.value);
+        let doc = ev
+            .description
+            .as_ref()
+            .map(|s| Cow::from(&**s))
+            .unwrap_or_else(|| Cow::from(&*ev.name));
+
+        variants.push(quote!(
+            #[doc = #doc]
+            #variant = #value
+        ));
+        arms.push(quote!(#value => Ok(#name::#variant)));
+    }
+
+    quote!(
+        #cfg
+        #[allow(non_camel_case_types)]
+        // logs the name of the variant rather than its value
+        #[cfg_attr(feature = "binfmt", derive(binfmt::derive::binDebug))]
+        #[derive(Clone, Copy, PartialEq)]
+        #[doc = #doc]
+        #[repr(#fty)]
+        pub enum #name {
+            #(#variants,)*
+        }
+
+        #cfg
+        impl From<#name> for #fty {
+            fn from(val: #name) -> Self {
+                val as #fty
+            }
+        }
+
+        #cfg
+        impl core::convert::TryFrom<#fty> for #name {
+            /// The bits that don't correspond to any enumerated value (reserved encodings)
+            type Error = #fty;
+
+            fn try_from(bits: #fty) -> Result<Self, #fty> {
+                match bits {
+                    #(#arms,)*
+                    _ => Err(bits),
+                }
+            }
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::SeedableRng as _;
+    use rand_xorshift::XorShiftRng;
+
+    use super::util;
+    use crate::{
+        ir::{
+            Access, Bitfield, Device, EnumeratedValue, Instances, Interrupt, Peripheral, Region,
+            Register, Width,
+        },
+        tests::{compile, run},
+    };
+
+    const RO: Access = Access::ReadOnly;
+    const RW: Access = Access::ReadWrite {
+        unsafe_write: false,
+    };
+    const WO: Access = Access::WriteOnly {
+        unsafe_write: false,
+    };
+
+    fn field(name: &'static str, offset: u8, width: u8) -> Bitfield<'static> {
+        Bitfield {
+            description: None,
+            name: name.into(),
+            offset,
+            width,
+            values: vec![],
+            unsafe_write: false,
+            min_rev: None,
+        }
+    }
+
+    fn value(name: &'static str, value: u64) -> EnumeratedValue<'static> {
+        EnumeratedValue {
+            description: None,
+            name: name.into(),
+            value,
+        }
+    }
+
+    // a 32-bit register at offset 0, present in all silicon revisions
+    fn register(
+        name: &'static str,
+        access: Access,
+        r_fields: Vec<Bitfield<'static>>,
+        w_fields: Vec<Bitfield<'static>>,
+    ) -> Register<'static> {
+        Register {
+            access,
+            description: None,
+            min_rev: None,
+            name: name.into(),
+            offset: 0,
+            region: None,
+            r_fields,
+            w_fields,
+            width: Width::U32,
+        }
+    }
+
+    // a `PERIPH` peripheral, with no interrupt, that contains the given `registers`
+    fn peripheral(registers: Vec<Register<'static>>) -> Peripheral<'static> {
+        Peripheral {
+            block_size: None,
+            description: None,
+            devices: vec![],
+            instances: Instances::Single {
+                base_address: 0x4000_0000,
+            },
+            interrupt: None,
+            name: "PERIPH".into(),
+            regions: vec![],
+            registers,
+        }
+    }
+
+    // the crate generated from `peripheral`
+    fn krate(peripheral: Peripheral<'static>) -> String {
+        crate::krate(Device {
+            extra_docs: None,
+            name: "test".into(),
+            peripherals: vec![peripheral],
+        })
+        .unwrap()
+    }
+
+    // the code generated for `register`; for properties that can't be observed from the compiled
+    // crate, e.g. documentation or code behind features that need extra dependencies
+    fn tokens(register: &Register<'_>) -> String {
+        super::register(register, &mut XorShiftRng::seed_from_u64(0)).to_string()
+    }
+
+    #[test]
+    fn field_enum_with_gaps() {
+        // encodings `1` and `3` are reserved
+        let mode = Bitfield {
+            values: vec![value("Disabled", 0), value("Enabled", 2)],
+            ..field("MODE", 0, 2)
+        };
+        let register = register("STATUS", RO, vec![mode], vec![]);
+
+        // the variant names are logged
+        assert!(tokens(&register).contains("derive (binfmt :: derive :: binDebug)"));
+
+        run(
+            &krate(peripheral(vec![register])),
+            &["PERIPH"],
+            "use core::convert::TryFrom;
+            use pac::periph::status::MODE;
+
+            fn main() {
+                assert!(MODE::try_from(0).ok() == Some(MODE::Disabled));
+                assert!(MODE::try_from(2).ok() == Some(MODE::Enabled));
+                assert_eq!(u8::from(MODE::Enabled), 2);
+                // reserved encodings are returned as errors
+                assert!(MODE::try_from(1).err() == Some(1));
+                assert!(MODE::try_from(3).err() == Some(3));
+            }",
+            "field-enum-with-gaps",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn variant_getter() {
+        // encodings `1` and `2` are reserved
+        let state = Bitfield {
+            values: vec![value("Off", 0), value("On", 3)],
+            ..field("STATE", 4, 2)
+        };
+        let krate = krate(peripheral(vec![register(
+            "STATUS",
+            RO,
+            vec![state, field("BUSY", 0, 1)],
+            vec![],
+        )]));
+
+        run(
+            &krate,
+            &["PERIPH"],
+            "use pac::periph::status::{R, STATE};
+
+            fn main() {
+                assert!(R::from(0x00).STATE_variant() == Some(STATE::Off));
+                assert!(R::from(0x31).STATE_variant() == Some(STATE::On));
+                assert_eq!(R::from(0x31).BUSY(), 1);
+                // the reserved encodings decode to `None`
+                assert!(R::from(0x10).STATE_variant().is_none());
+                assert!(R::from(0x20).STATE_variant().is_none());
+            }",
+            "variant-getter",
+        )
+        .unwrap();
+
+        // fields without enumerated values only get the raw getter
+        let err = compile(
+            &krate,
+            &["PERIPH"],
+            "pub fn f(r: pac::periph::status::R) { r.BUSY_variant(); }",
+            "variant-getter-raw",
+        )
+        .unwrap_err();
+        assert!(err.contains("no method named `BUSY_variant`"), "{}", err);
+    }
+
+    #[test]
+    fn decode() {
+        let fields = vec![field("A", 0, 1), field("B", 1, 3), field("C", 8, 16)];
+
+        run(
+            &krate(peripheral(vec![register("STATUS", RO, fields, vec![])])),
+            &["PERIPH", "decode"],
+            "fn main() {
+                let fields = pac::periph::status::R::from(0x00ab_cd0b).decode();
+                let c: u16 = fields.C;
+                assert_eq!(fields.A, 1);
+                assert_eq!(fields.B, 0b101);
+                assert_eq!(c, 0xabcd);
+            }",
+            "decode",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn mixed_write_safety() {
+        let fields = vec![field("START", 0, 1), field("MODE", 1, 2)];
+        let mut register = register("CONFIG", RW, fields.clone(), fields);
+        register.make_field_write_unsafe("START");
+        let krate = krate(peripheral(vec![register]));
+
+        // the register write is safe
+        compile(
+            &krate,
+            &["PERIPH"],
+            "pub fn f(r: &pac::periph::CONFIG) {
+                r.write(|w| w.MODE(1));
+                unsafe { r.write(|w| w.START(1)) }
+            }",
+            "mixed-write-safety",
+        )
+        .unwrap();
+
+        // but setting `START` is not
+        let err = compile(
+            &krate,
+            &["PERIPH"],
+            "pub fn f(r: &pac::periph::CONFIG) { r.write(|w| w.START(1)); }",
+            "mixed-write-safety-start",
+        )
+        .unwrap_err();
+        assert!(err.contains("call to unsafe function"), "{}", err);
+    }
+
+    #[test]
+    fn field_bounds() {
+        let fields = vec![field("LOW", 0, 4), field("HIGH", 28, 4)];
+        let register = register("STATUS", RO, fields, vec![]);
+
+        // NOTE `verify` rejects fields that don't fit so these can't be observed from a crate
+        // generated from the IR
+        let tokens = tokens(&register);
+        assert!(tokens.contains("[() ; (0 + 4 <= 32) as usize]"));
+        assert!(tokens.contains("[() ; (28 + 4 <= 32) as usize]"));
+    }
+
+    #[test]
+    fn write_verified() {
+        // `STATUS` is read-only; `START` is write-only
+        let register = register(
+            "CONFIG",
+            RW,
+            vec![field("MODE", 0, 2), field("STATUS", 8, 1)],
+            vec![field("MODE", 0, 2), field("START", 16, 1)],
+        );
+        assert_eq!(util::rwmask(&register), 0b11);
+
+        run(
+            &krate(peripheral(vec![register])),
+            &["PERIPH", "verify"],
+            "fn main() {
+                let mut mem = [0u32];
+                let base = mem.as_mut_ptr();
+                let periph = unsafe { pac::periph::Registers::at(base as usize) };
+                assert_eq!(periph.CONFIG.write_verified(|w| w.MODE(2).START(1)), Ok(()));
+                assert_eq!(unsafe { base.read_volatile() }, 0x1_0002);
+            }",
+            "write-verified",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn field_access_docs() {
+        // `STATUS` is read-only; `START` is write-only
+        let register = register(
+            "CONFIG",
+            RW,
+            vec![field("MODE", 0, 2), field("STATUS", 8, 1)],
+            vec![field("MODE", 0, 2), field("START", 16, 1)],
+        );
+
+        let tokens = tokens(&register);
+        assert!(tokens.contains("\"(Bits 0..=2) (RW)\""));
+        assert!(tokens.contains("\"(Bit 8) (RO)\""));
+        assert!(tokens.contains("\"(Bit 16) (WO)\""));
+    }
+
+    #[test]
+    fn set_clear_bit() {
+        let mut register = register(
+            "CONFIG",
+            RW,
+            vec![field("EN", 0, 1), field("MODE", 1, 2)],
+            vec![field("EN", 0, 1), field("MODE", 1, 2), field("START", 8, 1)],
+        );
+        register.make_field_write_unsafe("START");
+
+        // NOTE these helpers depend on the `critical-section` crate
+        let tokens = tokens(&register);
+        assert!(tokens.contains("pub fn set_EN (& self)"));
+        assert!(tokens.contains("pub fn clear_EN (& self)"));
+        assert!(
+            tokens.contains("critical_section :: with (| _ | self . update (| w | w . EN (1)))")
+        );
+        assert!(
+            tokens.contains("critical_section :: with (| _ | self . update (| w | w . EN (0)))")
+        );
+        // setting `START` is `unsafe`
+        assert!(tokens.contains("pub unsafe fn set_START (& self)"));
+        // only single-bit fields get these helpers
+        assert!(!tokens.contains("set_MODE"));
+    }
+
+    #[test]
+    fn w_bits() {
+        let mut register = register(
+            "CONFIG",
+            WO,
+            vec![],
+            vec![field("EN", 0, 1), field("MODE", 1, 2)],
+        );
+
+        run(
+            &krate(peripheral(vec![register.clone()])),
+            &["PERIPH"],
+            "use pac::periph::config::W;
+
+            fn main() {
+                // modifying one field of a seeded writer only clears and sets that field's bits
+                let mut w = W::from_bits(0xffff_fff9);
+                w.MODE(0b01);
+                assert_eq!(w.into_bits(), 0xffff_fffb);
+            }",
+            "w-bits",
+        )
+        .unwrap();
+
+        // a seed could set the bits of an `unsafe` field
+        register.w_fields.push(field("START", 8, 1));
+        register.make_field_write_unsafe("START");
+        let err = compile(
+            &krate(peripheral(vec![register])),
+            &["PERIPH"],
+            "pub fn f() -> pac::periph::config::W { pac::periph::config::W::from_bits(0) }",
+            "w-bits-unsafe",
+        )
+        .unwrap_err();
+        assert!(err.contains("call to unsafe function"), "{}", err);
+    }
+
+    #[test]
+    fn rev_gated_field() {
+        let fields = vec![field("MODE", 0, 2), field("BOOST", 8, 1)];
+        let mut register = register("CONFIG", RW, fields.clone(), fields);
+        register.set_field_min_rev("BOOST", 3);
+        let krate_ = krate(peripheral(vec![register.clone()]));
+
+        // getter, setter and constants are gated
+        let boost = "pub fn f(r: &pac::periph::CONFIG) -> u8 {
+            r.write(|w| w.BOOST(1));
+            r.read().BOOST() << pac::periph::config::BOOST_OFFSET
+        }";
+        compile(&krate_, &["PERIPH", "rev-3"], boost, "rev-gated-field").unwrap();
+        let err = compile(&krate_, &["PERIPH"], boost, "rev-gated-field-baseline").unwrap_err();
+        assert!(err.contains("no method named `BOOST`"), "{}", err);
+        assert!(err.contains("BOOST_OFFSET"), "{}", err);
+
+        // the baseline layout is not gated
+        compile(
+            &krate_,
+            &["PERIPH"],
+            "pub fn f(r: &pac::periph::CONFIG) -> u8 { r.read().MODE() }",
+            "rev-gated-field-mode",
+        )
+        .unwrap();
+
+        // a gated register gates its module and its handle
+        register.min_rev = Some(2);
+        let krate_ = krate(peripheral(vec![register]));
+        let config = "pub fn f(p: &pac::PERIPH) -> u8 { p.CONFIG.read().MODE() }";
+        compile(&krate_, &["PERIPH", "rev-2"], config, "rev-gated-register").unwrap();
+        let err = compile(&krate_, &["PERIPH"], config, "rev-gated-register-baseline").unwrap_err();
+        assert!(err.contains("no field `CONFIG`"), "{}", err);
+    }
+
+    #[test]
+    fn task_trigger() {
+        let mut register = register("TASKS_START", WO, vec![], vec![field("TASKS_START", 0, 1)]);
+
+        run(
+            &krate(peripheral(vec![register.clone()])),
+            &["PERIPH"],
+            "fn main() {
+                let mut mem = [0u32];
+                let base = mem.as_mut_ptr();
+                let periph = unsafe { pac::periph::Registers::at(base as usize) };
+                periph.TASKS_START.trigger();
+                assert_eq!(unsafe { base.read_volatile() }, 1);
+            }",
+            "task-trigger",
+        )
+        .unwrap();
+
+        // starting a DMA transfer is `unsafe`
+        register.access.make_write_unsafe();
+        let err = compile(
+            &krate(peripheral(vec![register.clone()])),
+            &["PERIPH"],
+            "pub fn f(r: &pac::periph::TASKS_START) { r.trigger() }",
+            "task-trigger-unsafe",
+        )
+        .unwrap_err();
+        assert!(err.contains("call to unsafe function"), "{}", err);
+
+        // not a task
+        register.name = "CONFIG".into();
+        register.w_fields = vec![field("CONFIG", 0, 1)];
+        let err = compile(
+            &krate(peripheral(vec![register])),
+            &["PERIPH"],
+            "pub fn f(r: &pac::periph::CONFIG) { unsafe { r.trigger() } }",
+            "task-trigger-config",
+        )
+        .unwrap_err();
+        assert!(err.contains("no method named `trigger`"), "{}", err);
+    }
+
+    #[test]
+    fn ppi_endpoints() {
+        let mut task = register("TASKS_START", WO, vec![], vec![field("TASKS_START", 0, 1)]);
+        task.offset = 0x4;
+        let event = Register {
+            offset: 0x100,
+            ..register(
+                "EVENTS_END",
+                RW,
+                vec![field("EVENTS_END", 0, 1)],
+                vec![field("EVENTS_END", 0, 1)],
+            )
+        };
+        let krate_ = krate(peripheral(vec![task.clone(), event]));
+
+        run(
+            &krate_,
+            &["PERIPH"],
+            "fn task<T: pac::SafeTask>() -> u32 { T::ADDRESS }
+            fn event<E: pac::Event>() -> u32 { E::ADDRESS }
+
+            fn main() {
+                assert_eq!(task::<pac::periph::TASKS_START>(), 0x4000_0004);
+                assert_eq!(event::<pac::periph::EVENTS_END>(), 0x4000_0100);
+            }",
+            "ppi-endpoints",
+        )
+        .unwrap();
+
+        // an event is not a task
+        let err = compile(
+            &krate_,
+            &["PERIPH"],
+            "pub fn f<T: pac::Task>() {} pub fn g() { f::<pac::periph::EVENTS_END>() }",
+            "ppi-endpoints-event",
+        )
+        .unwrap_err();
+        assert!(err.contains("E0277"), "{}", err);
+
+        // a task that starts a DMA transfer can't be connected through the safe API
+        task.access.make_write_unsafe();
+        let krate_ = krate(peripheral(vec![task]));
+        let f = "pub fn f<T: pac::SafeTask>() {} pub fn g() { f::<pac::periph::TASKS_START>() }";
+        let err = compile(&krate_, &["PERIPH"], f, "ppi-endpoints-unsafe").unwrap_err();
+        assert!(err.contains("E0277"), "{}", err);
+        compile(
+            &krate_,
+            &["PERIPH"],
+            "pub fn f<T: pac::Task>() {} pub fn g() { f::<pac::periph::TASKS_START>() }",
+            "ppi-endpoints-task",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn raw_pointers() {
+        let mut register = Register {
+            offset: 0x10,
+            width: Width::U16,
+            ..register("STATUS", RO, vec![], vec![])
+        };
+
+        let krate_ = krate(peripheral(vec![register.clone()]));
+        compile(
+            &krate_,
+            &["PERIPH"],
+            "pub fn f(r: &pac::periph::STATUS) -> *const u16 { r.as_ptr() }",
+            "raw-pointers-ro",
+        )
+        .unwrap();
+        let err = compile(
+            &krate_,
+            &["PERIPH"],
+            "pub fn f(r: &pac::periph::STATUS) -> *mut u16 { r.as_mut_ptr() }",
+            "raw-pointers-ro-mut",
+        )
+        .unwrap_err();
+        assert!(err.contains("no method named `as_mut_ptr`"), "{}", err);
+
+        // the pointers follow the base address of the handle
+        register.access = RW;
+        run(
+            &krate(peripheral(vec![register])),
+            &["PERIPH"],
+            "fn main() {
+                let mut mem = [0u16; 9];
+                let base = mem.as_mut_ptr();
+                let periph = unsafe { pac::periph::Registers::at(base as usize) };
+                let ptr: *mut u16 = periph.STATUS.as_mut_ptr();
+                assert_eq!(ptr, unsafe { base.add(8) });
+                assert_eq!(periph.STATUS.as_ptr(), ptr as *const u16);
+            }",
+            "raw-pointers-rw",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn region_address() {
+        let mut register = Register {
+            offset: 0x10,
+            ..register("STATUS", RO, vec![], vec![])
+        };
+
+        let program = "fn main() {
+            let mut mem = [0u32; 0x405];
+            let base = mem.as_mut_ptr() as usize;
+            let periph = unsafe { pac::periph::Registers::at(base) };
+            assert_eq!(pac::periph::STATUS::address_u32(), 0x4000_0000 + OFFSET);
+            assert_eq!(periph.STATUS.as_ptr() as usize, base + OFFSET as usize);
+        }";
+        run(
+            &krate(peripheral(vec![register.clone()])),
+            &["PERIPH"],
+            &format!("const OFFSET: u32 = 0x10; {}", program),
+            "region-address",
+        )
+        .unwrap();
+
+        let secure = Region {
+            name: "SECURE".into(),
+            offset: 0x1000,
+        };
+        register.region = Some(secure.clone());
+        let mut peripheral = peripheral(vec![register]);
+        peripheral.regions.push(secure);
+        run(
+            &krate(peripheral),
+            &["PERIPH"],
+            &format!("const OFFSET: u32 = 0x1010; {}", program),
+            "region-address-secure",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn block_size_asserts() {
+        let register = |name: &'static str, offset| Register {
+            offset,
+            ..register(name, RO, vec![], vec![])
+        };
+        let mut peripheral = Peripheral {
+            block_size: Some(0x1000),
+            ..peripheral(vec![register("LAST", 0x540), register("FIRST", 0x100)])
+        };
+
+        compile(
+            &krate(peripheral.clone()),
+            &["PERIPH", "test"],
+            "",
+            "block-size",
+        )
+        .unwrap();
+
+        // `LAST` ends past the block
+        peripheral.block_size = Some(0x540);
+        let krate_ = krate(peripheral);
+        compile(&krate_, &["PERIPH"], "", "block-size-untested").unwrap();
+        let err = compile(&krate_, &["PERIPH", "test"], "", "block-size-overflow").unwrap_err();
+        assert!(err.contains("mismatched types"), "{}", err);
+    }
+
+    #[test]
+    fn peripheral_size() {
+        let register = |name: &'static str, offset, width| Register {
+            offset,
+            width,
+            ..register(name, RO, vec![], vec![])
+        };
+        let mut peripheral = Peripheral {
+            block_size: Some(0x1000),
+            ..peripheral(vec![
+                register("CONFIG", 0x500, Width::U32),
+                register("ID", 0x508, Width::U8),
+            ])
+        };
+
+        // the register extent, not the block size, rounded up to whole words
+        run(
+            &krate(peripheral.clone()),
+            &["PERIPH"],
+            "fn main() { assert_eq!(pac::periph::SIZE, 0x50c); }",
+            "peripheral-size",
+        )
+        .unwrap();
+
+        // no registers, no size
+        peripheral.registers.clear();
+        let err = compile(
+            &krate(peripheral),
+            &["PERIPH"],
+            "pub const SIZE: usize = pac::periph::SIZE;",
+            "peripheral-size-empty",
+        )
+        .unwrap_err();
+        assert!(err.contains("cannot find value `SIZE`"), "{}", err);
+    }
+
+    #[test]
+    fn irq() {
+        let mut peripheral = Peripheral {
+            interrupt: Some(Interrupt {
+                name: "USBD".into(),
+                number: 39,
+            }),
+            ..peripheral(vec![])
+        };
+
+        run(
+            &krate(peripheral.clone()),
+            &["PERIPH"],
+            "fn main() { assert_eq!(pac::PERIPH::IRQ, 39); }",
+            "irq",
+        )
+        .unwrap();
+
+        peripheral.interrupt = None;
+        let err = compile(
+            &krate(peripheral),
+            &["PERIPH"],
+            "pub const IRQ: u8 = pac::PERIPH::IRQ;",
+            "irq-none",
+        )
+        .unwrap_err();
+        assert!(err.contains("no associated item named `IRQ`"), "{}", err);
+    }
+
+    #[test]
+    fn device_gated_peripheral() {
+        let mut peripheral = peripheral(vec![]);
+        peripheral.devices = vec!["nrf52840".into()];
+        let krate = krate(peripheral);
+
+        let snippet = "pub fn f() -> Option<pac::PERIPH> { pac::PERIPH::take() }";
+        compile(&krate, &["PERIPH", "nrf52840"], snippet, "device-gated").unwrap();
+        let err = compile(
+            &krate,
+            &["PERIPH", "nrf52833"],
+            snippet,
+            "device-gated-other",
+        )
+        .unwrap_err();
+        assert!(err.contains("cannot find type `PERIPH`"), "{}", err);
+    }
+
+    #[test]
+    fn field_consts() {
+        let fields = vec![field("LOW", 0, 4), field("HIGH", 28, 4)];
+
+        run(
+            &krate(peripheral(vec![register("STATUS", RO, fields, vec![])])),
+            &["PERIPH"],
+            "use pac::periph::status::*;
+
+            fn main() {
+                assert_eq!(LOW_MASK, 0xf);
+                assert_eq!(LOW_OFFSET, 0);
+                assert_eq!(HIGH_MASK, 0xf000_0000);
+                assert_eq!(HIGH_OFFSET, 28);
+                // to test several bitfields at once
+                assert_eq!(R::from(0x1000_0001).bits() & (LOW_MASK | HIGH_MASK), 0x1000_0001);
+            }",
+            "field-consts",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn rmw_preserves_fields() {
+        let (low, high) = (field("LOW", 0, 4), field("HIGH", 4, 4));
+        // `BUSY` can be read but not written
+        let register = register(
+            "CTRL",
+            RW,
+            vec![low.clone(), high.clone(), field("BUSY", 8, 1)],
+            vec![low, high],
+        );
+        assert_eq!(util::r2wmask(&register), 0x100);
+
+        // the register reads `BUSY=1 HIGH=0b1010 LOW=0b1111`; `HIGH` is preserved, `LOW` is
+        // modified and the read-only `BUSY` bit is not written back
+        run(
+            &krate(peripheral(vec![register])),
+            &["PERIPH"],
+            "fn main() {
+                let mut mem = [0x1afu32];
+                let base = mem.as_mut_ptr();
+                let periph = unsafe { pac::periph::Registers::at(base as usize) };
+                periph.CTRL.rmw(|_, w| w.LOW(0b0011));
+                assert_eq!(unsafe { base.read_volatile() }, 0x0a3);
+            }",
+            "rmw",
+        )
+        .unwrap();
+    }
+}
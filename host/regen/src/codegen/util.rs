@@ -1,3 +1,4 @@
+use heck::CamelCase;
 use proc_macro2::{Span as Span2, TokenStream as TokenStream2};
 use quote::{format_ident, quote};
 use syn::{Ident, LitInt};
@@ -18,6 +19,16 @@ pub fn ident(s: &str) -> Ident {
     )
 }
 
+/// Turns the name of an enumerated value into a valid variant name
+pub fn variant(s: &str) -> Ident {
+    let s = s.to_camel_case();
+    if s.starts_with(|c: char| c.is_ascii_digit()) {
+        format_ident!("_{}", s)
+    } else {
+        format_ident!("{}", s)
+    }
+}
+
 pub fn bitwidth2ty(width: u8) -> TokenStream2 {
     if width <= 8 {
         quote!(u8)
@@ -39,7 +50,18 @@ pub fn hex(val: u64) -> LitInt {
 pub fn r2wmask(reg: &Register<'_>) -> u64 {
     let mut mask = 0;
     for field in &reg.r_fields {
-        if !reg.w_fields.contains(field) {
+        if !reg.w_fields.iter().any(|w| w.same_bits(field)) {
+            mask |= field.mask() << field.offset;
+        }
+    }
+    mask
+}
+
+// bits of the bitfields that can be both written and read back
+pub fn rwmask(reg: &Register<'_>) -> u64 {
+    let mut mask = 0;
+    for field in &reg.w_fields {
+        if reg.r_fields.iter().any(|r| r.same_bits(field)) {
             mask |= field.mask() << field.offset;
         }
     }
@@ -59,7 +81,18 @@ pub fn width2ty(width: Width) -> TokenStream2 {
     }
 }
 
-pub fn field_docs(field: &Bitfield<'_>) -> String {
+/// `#[cfg]` attribute that gates an item that's only present in silicon revision `min_rev` and
+/// newer; nothing if the item is present in all revisions
+pub fn rev_cfg(min_rev: Option<u8>) -> TokenStream2 {
+    if let Some(rev) = min_rev {
+        let feature = format!("rev-{}", rev);
+        quote!(#[cfg(feature = #feature)])
+    } else {
+        quote!()
+    }
+}
+
+pub fn field_docs(reg: &Register<'_>, field: &Bitfield<'_>) -> String {
     let mut doc = if field.width == 1 {
         format!("(Bit {})", field.offset)
     } else {
@@ -69,5 +102,20 @@ pub fn field_docs(field: &Bitfield<'_>) -> String {
         doc.push(' ');
         doc.push_str(desc);
     }
+    doc.push_str(" (");
+    doc.push_str(field_access(reg, field));
+    doc.push(')');
     doc
 }
+
+/// Access kind of the bitfield, as seen through the generated API
+pub fn field_access(reg: &Register<'_>, field: &Bitfield<'_>) -> &'static str {
+    let readable = reg.r_fields.iter().any(|r| r.same_bits(field));
+    let writable = reg.w_fields.iter().any(|w| w.same_bits(field));
+    match (readable, writable) {
+        (true, true) => "RW",
+        (true, false) => "RO",
+        (false, true) => "WO",
+        (false, false) => unreachable!(),
+    }
+}
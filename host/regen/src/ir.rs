@@ -6,34 +6,110 @@ pub struct Device<'a> {
     pub peripherals: Vec<Peripheral<'a>>,
 }
 
+#[derive(Clone)]
 pub enum Instances<'a> {
     Single { base_address: u64 },
     Many { instances: Vec<Instance<'a>> },
 }
 
+#[derive(Clone)]
 pub struct Instance<'a> {
     pub suffix: Cow<'a, str>,
     pub base_address: u64,
 }
 
+#[derive(Clone)]
 pub struct Peripheral<'a> {
+    /// Size of the address range that starts at the base address, in bytes, as declared in the
+    /// SVD file; `None` if unknown
+    pub block_size: Option<u64>,
     pub description: Option<Cow<'a, str>>,
+    /// The devices (Cargo features) that have this peripheral; empty if all of them have it
+    pub devices: Vec<Cow<'a, str>>,
     pub instances: Instances<'a>,
+    /// The interrupt the peripheral signals, if any
+    pub interrupt: Option<Interrupt<'a>>,
     pub name: Cow<'a, str>,
+    /// Address ranges of the peripheral other than the one that starts at its base address; may
+    /// be empty
+    pub regions: Vec<Region<'a>>,
     pub registers: Vec<Register<'a>>,
 }
 
+/// An entry of the device's interrupt vector
+#[derive(Clone, PartialEq)]
+pub struct Interrupt<'a> {
+    pub name: Cow<'a, str>,
+    /// Position in the vector table, not counting the 16 exceptions of the core
+    pub number: u8,
+}
+
+/// An address range of a peripheral that's not contiguous with the one at its base address
+#[derive(Clone, PartialEq)]
+pub struct Region<'a> {
+    pub name: Cow<'a, str>,
+    /// Distance from the base address of the peripheral, in bytes; the same for all instances
+    pub offset: u64,
+}
+
+#[derive(Clone)]
 pub struct Register<'a> {
     pub access: Access,
     pub description: Option<Cow<'a, str>>,
+    /// Oldest silicon revision that has this register; `None` means all revisions have it. The
+    /// generated code gates the register behind the `rev-N` Cargo feature
+    pub min_rev: Option<u8>,
     pub name: Cow<'a, str>,
+    /// In bytes, from the start of `region`
     pub offset: u64,
+    /// The address range the register lives in; `None` means the one at the peripheral base
+    /// address. Must be one of the regions of the peripheral
+    pub region: Option<Region<'a>>,
     pub r_fields: Vec<Bitfield<'a>>,
     pub w_fields: Vec<Bitfield<'a>>,
     /// In *bytes*; must be one of `[1, 2, 4, 8]`
     pub width: Width,
 }
 
+impl Register<'_> {
+    /// Marks writes to the bitfield `name` as `unsafe`
+    pub fn make_field_write_unsafe(&mut self, name: &str) {
+        let field = self
+            .w_fields
+            .iter_mut()
+            .find(|field| field.name == name)
+            .unwrap_or_else(|| panic!("register {} has no writable field {}", self.name, name));
+        field.unsafe_write = true;
+    }
+
+    /// Returns `true` if this is a task register: writing 1 to its single bitfield starts an
+    /// action of the peripheral
+    pub fn is_task(&self) -> bool {
+        self.name.starts_with("TASKS_")
+            && self.access.can_write()
+            && self.w_fields.len() == 1
+            && self.w_fields[0].width == 1
+    }
+
+    /// Returns `true` if this is an event register: the peripheral writes 1 to it when the event
+    /// occurs
+    pub fn is_event(&self) -> bool {
+        self.name.starts_with("EVENTS_") && self.access.can_read()
+    }
+
+    /// Marks the bitfield `name` as only present in silicon revision `rev` and newer
+    pub fn set_field_min_rev(&mut self, name: &str, rev: u8) {
+        let mut found = false;
+        for field in self.r_fields.iter_mut().chain(&mut self.w_fields) {
+            if field.name == name {
+                field.min_rev = Some(rev);
+                found = true;
+            }
+        }
+        assert!(found, "register {} has no field {}", self.name, name);
+    }
+}
+
 /// Register width
 #[derive(Clone, Copy)]
 pub enum Width {
@@ -104,10 +180,33 @@ pub struct Bitfield<'a> {
     pub offset: u8,
     /// In bits; must be greater than `0` and less than the register width
     pub width: u8,
+    /// Enumerated values of the bitfield; may be empty
+    pub values: Vec<EnumeratedValue<'a>>,
+    /// Writing to this bitfield has side effects that can break memory safety; only meaningful
+    /// for writable bitfields of registers whose writes are otherwise safe
+    pub unsafe_write: bool,
+    /// Oldest silicon revision that has this bitfield; `None` means all the revisions that have
+    /// the register have the bitfield too. The generated code gates the bitfield behind the
+    /// `rev-N` Cargo feature
+    pub min_rev: Option<u8>,
 }
 
 impl Bitfield<'_> {
     pub fn mask(&self) -> u64 {
         (1 << self.width) - 1
     }
+
+    /// Returns `true` if both bitfields occupy the same bits under the same name
+    pub fn same_bits(&self, other: &Bitfield<'_>) -> bool {
+        self.name == other.name && self.offset == other.offset && self.width == other.width
+    }
+}
+
+/// A named value of a bitfield
+#[derive(Clone, Eq, PartialEq)]
+pub struct EnumeratedValue<'a> {
+    pub description: Option<Cow<'a, str>>,
+    pub name: Cow<'a, str>,
+    /// Must fit in the bitfield
+    pub value: u64,
 }
@@ -23,7 +23,8 @@ fn main() -> Result<(), anyhow::Error> {
 
 // Audited register writes
 const AUDITED: &[&str] = &[
-    "CLOCK", "FICR", "P0", "POWER", "RADIO", "RTC0", "TWIM0", "USBD", "SPIM0",
+    "CLOCK", "ECB", "FICR", "GPIOTE", "P0", "POWER", "PPI", "RADIO", "RTC0", "TWIM0", "USBD",
+    "SPIM0",
 ];
 
 fn gen_nrf52(lib: &Path) -> Result<(), anyhow::Error> {
@@ -34,8 +35,15 @@ fn gen_nrf52(lib: &Path) -> Result<(), anyhow::Error> {
     gen(dev, lib)
 }
 
+// Peripherals that the nRF52833 lacks; the SVD file describes the nRF52840
+const NRF52840_ONLY: &[&str] = &["CC_HOST_RGF", "CRYPTOCELL", "QSPI"];
+
 fn audit_nrf52(dev: &mut ir::Device<'_>) {
     for periph in &mut dev.peripherals {
+        if NRF52840_ONLY.contains(&&*periph.name) {
+            periph.devices = vec!["nrf52840".into()];
+        }
+
         // all peripherals
         for reg in &mut periph.registers {
             match &*reg.name {
@@ -79,6 +87,30 @@ fn audit_nrf52(dev: &mut ir::Device<'_>) {
                 }
             }
         }
+
+        if periph.name == "ECB" {
+            for reg in &mut periph.registers {
+                match &*reg.name {
+                    // DMA related
+                    "ECBDATAPTR" | "TASKS_STARTECB" => {
+                        reg.access.make_write_unsafe();
+                    }
+
+                    _ => {}
+                }
+            }
+        }
+
+        if periph.name == "PPI" {
+            for reg in &mut periph.registers {
+                // the hardware triggers the connected task, which may start a DMA transfer
+                if reg.name.starts_with("CH") && reg.name.ends_with("_TEP")
+                    || reg.name.starts_with("FORK") && reg.name.ends_with("_TEP")
+                {
+                    reg.access.make_write_unsafe();
+                }
+            }
+        }
     }
 }
 
@@ -87,12 +119,189 @@ fn gen_cm(lib: &Path) -> Result<(), anyhow::Error> {
     gen(dev, lib)
 }
 
-fn gen(mut dev: ir::Device<'_>, lib: &Path) -> Result<(), anyhow::Error> {
+fn gen(dev: ir::Device<'_>, lib: &Path) -> Result<(), anyhow::Error> {
     assert!(lib.is_file());
 
-    dev.verify()?;
-    opt::device(&mut dev);
-    let krate = codegen::device(&dev);
+    let krate = krate(dev)?;
     fs::write(lib, krate)?;
     Ok(())
 }
+
+fn krate(mut dev: ir::Device<'_>) -> Result<String, anyhow::Error> {
+    dev.verify()?;
+    opt::device(&mut dev);
+    Ok(codegen::device(&dev))
+}
+
+#[cfg(test)]
+mod tests {
+    // returns the contents of the first module named `name` that appears after `from`
+    fn module<'a>(krate: &'a str, from: &str, name: &str) -> &'a str {
+        let start = krate.find(from).expect("`from` not found");
+        let krate = &krate[start..];
+        let start = krate
+            .find(&format!("pub mod {} {{", name))
+            .expect("module not found");
+        let krate = &krate[start..];
+        let end = krate[1..]
+            .find("pub type ")
+            .map(|i| i + 1)
+            .unwrap_or(krate.len());
+        &krate[..end]
+    }
+
+    // the crate generated from the TEMP SVD file
+    fn temp_krate() -> String {
+        let xml = include_str!("../tests/nrf52840-TEMP.svd");
+        let dev = svd_parser::parse(xml).unwrap();
+        let mut dev = super::translate::svd::device(&dev, &["TEMP"]);
+        super::audit_nrf52(&mut dev);
+        super::krate(dev).unwrap()
+    }
+
+    // compiles `krate` as the `pac` crate, with the Cargo `features` enabled, and then `snippet`, a
+    // library that depends on it; returns the errors reported while compiling either crate
+    // NOTE like `trybuild` but the crate under test is generated at test time so it can't be a
+    // (dev-)dependency of this crate
+    pub(crate) fn compile(
+        krate: &str,
+        features: &[&str],
+        snippet: &str,
+        name: &str,
+    ) -> Result<(), String> {
+        let dir = build(krate, features, snippet, name, "lib")?;
+        std::fs::remove_dir_all(dir).ok();
+        Ok(())
+    }
+
+    // like `compile` but `snippet` is a program (with a `main` function) that's then run on the
+    // host; returns what the program printed to stderr (e.g. a failed assertion) if it fails
+    pub(crate) fn run(
+        krate: &str,
+        features: &[&str],
+        snippet: &str,
+        name: &str,
+    ) -> Result<(), String> {
+        let dir = build(krate, features, snippet, name, "bin")?;
+        let out = std::process::Command::new(dir.join("snippet"))
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        if out.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&out.stderr).into_owned())
+        }
+    }
+
+    fn build(
+        krate: &str,
+        features: &[&str],
+        snippet: &str,
+        name: &str,
+        crate_type: &str,
+    ) -> Result<std::path::PathBuf, String> {
+        use std::{env, fs, process::Command};
+
+        let dir = env::temp_dir().join(format!("regen-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pac.rs"), krate).unwrap();
+        fs::write(dir.join("snippet.rs"), snippet).unwrap();
+
+        let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let mut pac = Command::new(&rustc);
+        pac.args(&["--edition", "2018", "--crate-type", "lib"])
+            .args(&["--crate-name", "pac"]);
+        for feature in features {
+            pac.arg("--cfg").arg(format!("feature=\"{}\"", feature));
+        }
+        let pac = pac
+            .arg("--out-dir")
+            .arg(&dir)
+            .arg(dir.join("pac.rs"))
+            .output()
+            .unwrap();
+
+        let out = if pac.status.success() {
+            Command::new(&rustc)
+                .args(&["--edition", "2018", "--crate-type", crate_type])
+                .args(&["--crate-name", "snippet", "--extern"])
+                .arg(format!("pac={}", dir.join("libpac.rlib").display()))
+                .arg("--out-dir")
+                .arg(&dir)
+                .arg(dir.join("snippet.rs"))
+                .output()
+                .unwrap()
+        } else {
+            pac
+        };
+
+        if out.status.success() {
+            Ok(dir)
+        } else {
+            fs::remove_dir_all(&dir).ok();
+            Err(String::from_utf8_lossy(&out.stderr).into_owned())
+        }
+    }
+
+    #[test]
+    fn nrf52840_temp() {
+        let krate = temp_krate();
+
+        // crate attributes
+        assert!(krate.contains("deny (missing_docs)"));
+        assert!(!krate.contains("deny (warnings)"));
+
+        // peripheral
+        assert!(krate.contains("pub type TEMP = temp :: Registers ;"));
+        assert!(krate.contains("const BASE_ADDRESS : usize = 0x4000_c000 ;"));
+        assert!(krate.contains(
+            "pub fn clear_all_events (& self) { self . EVENTS_DATARDY . zero () ; crate :: dsb () ; }"
+        ));
+        assert!(krate.contains("pub unsafe fn at (base : usize) -> Registers < crate :: At >"));
+        assert!(krate
+            .contains("pub fn try_borrow < T > (f : impl FnOnce (& Self) -> T) -> Option < T >"));
+        assert!(krate.contains("(\"INTENSET\" , BASE_ADDRESS + 0x0304)"));
+        assert!(krate.contains("pub fn release (self)"));
+        assert!(krate.contains("pub const IRQ : u8 = 12 ;"));
+        // core atomics unless the `portable-atomic` feature is enabled
+        assert!(krate.contains("use crate :: atomic :: { AtomicBool , Ordering } ;"));
+        assert!(krate.contains(
+            "# [cfg (not (feature = \"portable-atomic\"))] pub use core :: sync :: atomic"
+        ));
+        assert!(krate.contains(
+            "# [cfg (feature = \"portable-atomic\")] pub use portable_atomic :: { AtomicBool , Ordering }"
+        ));
+
+        // write-only register
+        let tasks_start = module(&krate, "pub type TASKS_START", "tasks_start");
+        assert!(tasks_start.contains("pub fn write"));
+        assert!(!tasks_start.contains("pub fn read"));
+        assert!(tasks_start.contains("Trigger = 0x01"));
+        assert!(tasks_start.contains("pub const fn address_u32 () -> u32"));
+
+        // read-write register
+        let events_datardy = module(&krate, "pub type EVENTS_DATARDY", "events_datardy");
+        assert!(events_datardy.contains("pub fn read"));
+        assert!(events_datardy.contains("pub fn write"));
+        assert!(events_datardy.contains("pub fn rmw"));
+        assert!(events_datardy.contains("pub fn update"));
+        assert!(events_datardy.contains("pub fn EVENTS_DATARDY (self) -> u8"));
+        assert!(events_datardy.contains("pub enum EVENTS_DATARDY"));
+        assert!(events_datardy.contains("NotGenerated = 0x00"));
+        assert!(events_datardy.contains("Generated = 0x01"));
+
+        // the audit makes writes to INTENSET unsafe
+        let intenset = module(&krate, "pub type INTENSET", "intenset");
+        assert!(intenset.contains("pub unsafe fn write"));
+        assert!(intenset.contains("super :: BASE_ADDRESS + 0x0304"));
+        assert!(intenset.contains("self . base . base_address () + 0x0304"));
+
+        // read-only register
+        let temp = module(&krate, "pub type TEMP = temp :: Register ;", "temp");
+        assert!(temp.contains("pub fn read"));
+        assert!(!temp.contains("pub fn write"));
+        assert!(temp.contains("pub fn TEMP (self) -> u32"));
+    }
+}
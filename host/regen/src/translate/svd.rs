@@ -29,6 +29,7 @@ pub fn peripheral<'a>(
     defaults: &svd::RegisterProperties,
 ) -> ir::Peripheral<'a> {
     assert!(p.derived_from.is_none());
+    assert!(p.interrupt.len() <= 1, "unimplemented");
 
     let mut ir_regs = vec![];
 
@@ -86,11 +87,18 @@ pub fn peripheral<'a>(
     }
 
     ir::Peripheral {
+        block_size: p.address_block.as_ref().map(|block| u64::from(block.size)),
         name: p.name.as_str().into(),
         description: p.description.as_ref().map(|s| s.into()),
+        devices: vec![],
         instances: ir::Instances::Single {
             base_address: u64::from(p.base_address),
         },
+        interrupt: p.interrupt.first().map(|i| ir::Interrupt {
+            name: i.name.as_str().into(),
+            number: i.value.try_into().expect("unimplemented"),
+        }),
+        regions: vec![],
         registers: ir_regs,
     }
 }
@@ -153,10 +161,12 @@ pub fn register<'a>(
             .map(translate::access)
             .expect("unimplemented"),
         description: r.description.as_ref().map(|s| s.as_str().into()),
+        min_rev: None,
         name,
         r_fields,
         w_fields,
         offset: u64::from(offset),
+        region: None,
         width: r
             .size
             .or_else(|| defaults.iter().filter_map(|default| default.size).next())
@@ -194,6 +204,9 @@ pub fn fields<'a>(
                     name: fi.name.as_str().into(),
                     offset,
                     width,
+                    values: translate::enumerated_values(&fi.enumerated_values),
+                    unsafe_write: false,
+                    min_rev: None,
                 };
 
                 match fi.access.or(reg.access).expect("unreachable") {
@@ -213,6 +226,32 @@ pub fn fields<'a>(
     (r_fields, w_fields)
 }
 
+// NOTE only the first set of enumerated values is used; derived sets are ignored
+fn enumerated_values(values: &[svd::EnumeratedValues]) -> Vec<ir::EnumeratedValue<'_>> {
+    let mut evs: Vec<ir::EnumeratedValue<'_>> = vec![];
+    if let Some(values) = values.iter().find(|values| values.derived_from.is_none()) {
+        for ev in &values.values {
+            // skip `isDefault` entries, which have no value
+            if let Some(value) = ev.value {
+                let value = u64::from(value);
+
+                // aliases (e.g. a deprecated name for the same encoding) are merged into the first
+                // name given to that value
+                if evs.iter().any(|prev| prev.value == value) {
+                    continue;
+                }
+
+                evs.push(ir::EnumeratedValue {
+                    description: ev.description.as_ref().map(|s| s.as_str().into()),
+                    name: ev.name.as_str().into(),
+                    value,
+                });
+            }
+        }
+    }
+    evs
+}
+
 fn register_size(size: u32) -> ir::Width {
     match size {
         8 => ir::Width::U8,
@@ -40,9 +40,25 @@ impl Peripheral<'_> {
                 Instances::Single { .. } => {}
             }
 
+            for region in &self.regions {
+                if is_invalid_ident(&region.name) {
+                    bail!("region name {} is not a valid identifier", region.name);
+                }
+            }
+
             for reg in &self.registers {
                 reg.verify()?;
 
+                if let Some(region) = &reg.region {
+                    if !self.regions.contains(region) {
+                        bail!(
+                            "register {} is in undeclared region {}",
+                            reg.name,
+                            region.name
+                        );
+                    }
+                }
+
                 // TODO check for register overlap
             }
 
@@ -65,10 +81,24 @@ impl Register<'_> {
                 bail!("name is not a valid identifier");
             }
 
+            check_min_rev(self.min_rev)?;
+
             let reg_width = self.width.bits();
             for field in self.r_fields.iter().chain(&self.w_fields) {
                 field.verify()?;
 
+                check_min_rev(field.min_rev)?;
+                if let (Some(reg_rev), Some(field_rev)) = (self.min_rev, field.min_rev) {
+                    if field_rev <= reg_rev {
+                        bail!(
+                            "bitfield {} (revision {}) is not newer than its register (revision {})",
+                            field.name,
+                            field_rev,
+                            reg_rev,
+                        )
+                    }
+                }
+
                 if field.width + field.offset > reg_width {
                     bail!(
                         "bitfield {} (offset: {}, width: {}) exceeds register width ({})",
@@ -116,10 +146,96 @@ impl Bitfield<'_> {
             bail!("bitfield {} has a width of 0 bits", self.name);
         }
 
+        for (i, ev) in self.values.iter().enumerate() {
+            if ev.value > self.mask() {
+                bail!(
+                    "enumerated value {} ({}) of bitfield {} doesn't fit in {} bits",
+                    ev.name,
+                    ev.value,
+                    self.name,
+                    self.width,
+                );
+            }
+
+            // each value becomes an enum variant (discriminant)
+            for prev in &self.values[..i] {
+                if prev.value == ev.value {
+                    bail!(
+                        "enumerated values {} and {} of bitfield {} are both {}",
+                        prev.name,
+                        ev.name,
+                        self.name,
+                        ev.value,
+                    );
+                }
+
+                if prev.name == ev.name {
+                    bail!(
+                        "enumerated value {} of bitfield {} is declared more than once",
+                        ev.name,
+                        self.name,
+                    );
+                }
+            }
+        }
+
         Ok(())
     }
 }
 
+// revision 1 is the baseline layout; it needs no gating
+fn check_min_rev(min_rev: Option<u8>) -> Result<(), anyhow::Error> {
+    match min_rev {
+        Some(rev) if rev < 2 => bail!("minimum revision must be 2 or newer; got {}", rev),
+        _ => Ok(()),
+    }
+}
+
 fn is_invalid_ident(s: &str) -> bool {
     s.contains('%')
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{Bitfield, EnumeratedValue};
+
+    fn value(name: &'static str, value: u64) -> EnumeratedValue<'static> {
+        EnumeratedValue {
+            description: None,
+            name: name.into(),
+            value,
+        }
+    }
+
+    fn txpower(values: Vec<EnumeratedValue<'static>>) -> Bitfield<'static> {
+        Bitfield {
+            description: None,
+            name: "TXPOWER".into(),
+            offset: 0,
+            width: 8,
+            values,
+            unsafe_write: false,
+            min_rev: None,
+        }
+    }
+
+    #[test]
+    fn aliased_values() {
+        // control
+        txpower(vec![value("Neg30dBm", 0xd8), value("Neg20dBm", 0xec)])
+            .verify()
+            .unwrap();
+
+        // `Neg40dBm` is a deprecated alias of `Neg30dBm`
+        let err = txpower(vec![value("Neg30dBm", 0xd8), value("Neg40dBm", 0xd8)])
+            .verify()
+            .unwrap_err();
+        assert!(err.to_string().contains("are both 216"), "{}", err);
+
+        // same name, different value
+        let err = txpower(vec![value("Neg30dBm", 0xd8), value("Neg30dBm", 0xe2)])
+            .verify()
+            .unwrap_err();
+        assert!(err.to_string().contains("more than once"), "{}", err);
+    }
+}
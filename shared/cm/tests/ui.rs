@@ -0,0 +1,6 @@
+// NOTE `cargo test --features DCB,SCB`; the crate must have been generated with `regen` first
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
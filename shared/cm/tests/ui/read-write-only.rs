@@ -0,0 +1,6 @@
+// write-only registers have no `read` method
+fn main() {}
+
+fn read(dcrsr: &cm::dcb::DCRSR) {
+    dcrsr.read();
+}
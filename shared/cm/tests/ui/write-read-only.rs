@@ -0,0 +1,6 @@
+// read-only registers have no `write` method
+fn main() {}
+
+fn write(cpuid: &cm::scb::CPUID) {
+    cpuid.write(0);
+}
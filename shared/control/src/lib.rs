@@ -0,0 +1,525 @@
+//! Control transfers (endpoint 0) of `hal::usbd`, minus the I/O
+//!
+//! `handle` decides how the device answers a SETUP packet given the current state of the device;
+//! `hal::usbd` carries out the returned `Response` on the USBD peripheral. The decisions have no
+//! side effects so `control-fuzz` can check them on the host
+
+#![deny(missing_docs)]
+#![deny(warnings)]
+#![no_std]
+
+use core::num::NonZeroU8;
+
+#[cfg(feature = "binfmt")]
+use binfmt::derive::binDebug;
+use usb2::{cdc::acm, hid, GetDescriptor, Request, StandardRequest, State};
+
+/// The `Type` bits of `bmRequestType`; `0` means a standard request
+const REQUEST_TYPE_MASK: u8 = 0b0110_0000;
+
+/// `bRequest` of GET_DESCRIPTOR
+const GET_DESCRIPTOR: u8 = 6;
+
+/// `bDescriptorType` of Interface descriptors
+const INTERFACE_DESCRIPTOR: u8 = 4;
+
+/// `bDescriptorType` of Endpoint descriptors
+const ENDPOINT_DESCRIPTOR: u8 = 5;
+
+/// `bmRequestType` of a class-specific, host-to-device request directed to an interface
+const DFU_REQUEST_TYPE: u8 = 0b0010_0001;
+
+/// `bRequest` of DFU_DETACH
+const DFU_DETACH: u8 = 0;
+
+/// The fields of a SETUP packet
+#[derive(Clone, Copy, Debug)]
+pub struct Setup {
+    /// `bmRequestType`
+    pub bmrequesttype: u8,
+    /// `bRequest`
+    pub brequest: u8,
+    /// `wValue`
+    pub wvalue: u16,
+    /// `wIndex`
+    pub windex: u16,
+    /// `wLength`
+    pub wlength: u16,
+}
+
+/// A configuration the host can select with SET_CONFIGURATION
+pub struct Config {
+    /// `bConfigurationValue`
+    pub value: NonZeroU8,
+    /// Endpoints enabled in this configuration, as `EPINEN` bits
+    pub epinen: u32,
+    /// Endpoints enabled in this configuration, as `EPOUTEN` bits
+    pub epouten: u32,
+}
+
+/// What the device looks like to the host; this doesn't change at runtime
+pub struct Descriptors<'a> {
+    /// The device descriptor
+    pub device: &'a [u8],
+    /// The configurations of the device; the first one is the default configuration
+    pub configs: &'a [Config],
+    /// The configuration descriptors; the ith entry is the descriptor of `configs[i]`
+    pub config_descs: &'a [&'a [u8]],
+    /// The string descriptors, back to back; the ith string descriptor is
+    /// `strings[string_offsets[i]..string_offsets[i + 1]]`
+    pub strings: &'a [u8],
+    /// See `strings`
+    pub string_offsets: &'a [u16],
+    /// Descriptors that take precedence over the ones above; see
+    /// `hal::usbd::set_descriptor_provider`
+    pub provider: Option<fn(GetDescriptor) -> Option<&'a [u8]>>,
+    /// The highest alternate setting of each interface
+    pub max_alt_setting: &'a [u8],
+    /// Whether the device has its own power supply
+    pub self_powered: bool,
+    /// The CDC ACM control interface; `None` if the device is not a CDC ACM device
+    pub cdc_iface: Option<u8>,
+    /// The HID interface; `None` if the device has no HID interface
+    pub hid_iface: Option<u8>,
+    /// The DFU interface; `None` if the device doesn't support DFU_DETACH
+    pub dfu_iface: Option<u8>,
+}
+
+impl<'a> Descriptors<'a> {
+    /// Returns the configuration that has the given `bConfigurationValue`
+    pub fn config(&self, value: NonZeroU8) -> Option<&'a Config> {
+        self.configs.iter().find(|config| config.value == value)
+    }
+}
+
+/// The current state of the device
+pub struct Device<'a> {
+    /// The USB state
+    pub state: &'a State,
+    /// The current alternate setting of each interface
+    pub alt_settings: &'a [u8],
+    /// Whether the host has enabled remote wakeup; `None` if the device doesn't support it
+    pub remote_wakeup: Option<bool>,
+    /// Returns `true` if the given endpoint number is halted in the given direction
+    pub is_halted: fn(u8, usb2::Direction) -> bool,
+}
+
+/// How the device answers a request
+pub enum Response<'a> {
+    /// Control read: send these bytes to the host; never longer than `wLength`
+    Data(&'a [u8]),
+
+    /// Control read of a value computed from the state of the device (GET_STATUS,
+    /// GET_CONFIGURATION and GET_INTERFACE)
+    Value(Value),
+
+    /// Control read of the current line coding (CDC ACM GET_LINE_CODING)
+    GetLineCoding,
+
+    /// Control write: receive the new line coding (CDC ACM SET_LINE_CODING)
+    SetLineCoding,
+
+    /// No data stage: apply the change then acknowledge the request with a status stage
+    Status(Change<'a>),
+
+    /// SET_ADDRESS: move to the given state; the hardware completes the status stage on its own
+    SetAddress(State),
+}
+
+/// A value of at most 2 bytes sent in response to a request
+#[derive(Clone, Copy)]
+pub struct Value {
+    bytes: [u8; 2],
+    len: u8,
+}
+
+impl Value {
+    // `bytes[..len]` truncated to `wLength`
+    fn new(bytes: [u8; 2], len: u8, wlength: u16) -> Self {
+        Value {
+            bytes,
+            len: if wlength < u16::from(len) {
+                wlength as u8
+            } else {
+                len
+            },
+        }
+    }
+
+    /// The bytes to send; never longer than `wLength`
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..usize::from(self.len)]
+    }
+}
+
+/// A change to the state of the device requested by the host
+pub enum Change<'a> {
+    /// Nothing changes
+    None,
+
+    /// SET_CONFIGURATION: move to the given, Configured, state and enable the endpoints of the
+    /// given configuration instead of the current ones. All interfaces return to their default
+    /// alternate setting
+    Configure(State, &'a Config),
+
+    /// SET_CONFIGURATION(0): return to the given, Address, state. All endpoints but the control
+    /// one are disabled and remote wakeup is disabled
+    Deconfigure(State),
+
+    /// SET_FEATURE (`true`) or CLEAR_FEATURE (`false`) DEVICE_REMOTE_WAKEUP
+    RemoteWakeup(bool),
+
+    /// SET_FEATURE (`true`) or CLEAR_FEATURE (`false`) ENDPOINT_HALT
+    Halt(usb2::Endpoint, bool),
+
+    /// SET_INTERFACE
+    AltSetting {
+        /// The interface
+        interface: u8,
+        /// Its new alternate setting
+        alternate: u8,
+    },
+
+    /// DFU_DETACH: detach from the bus, and enter the bootloader, once the host has seen the
+    /// status stage
+    DfuDetach,
+}
+
+/// Why a request was stalled
+#[cfg_attr(feature = "binfmt", derive(binDebug))]
+#[cfg_attr(feature = "ufmt", derive(ufmt::derive::uDebug))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stall {
+    /// The SETUP packet is not a request this device understands
+    UnknownRequest,
+    /// The request is valid but this device doesn't support it
+    Unsupported,
+    /// The request is not valid in the current USB state
+    InvalidState,
+    /// The requested descriptor doesn't exist; hosts probe e.g. string indices so this is not
+    /// necessarily an error
+    NoDescriptor,
+    /// The requested configuration doesn't exist
+    NoConfiguration,
+    /// The interface doesn't exist
+    NoInterface,
+    /// The interface has no such alternate setting
+    NoAltSetting,
+    /// The endpoint doesn't exist or is not enabled in the current configuration
+    NoEndpoint,
+    /// The class-specific request was sent to an interface of a different class
+    WrongInterface,
+}
+
+#[cfg(feature = "ufmt")]
+impl ufmt::uDisplay for Stall {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        ufmt::uDebug::fmt(self, f)
+    }
+}
+
+/// Decides how the device answers the SETUP packet `setup`
+///
+/// This has no side effects: the caller must carry out the returned `Response`, including any
+/// `Change` to the state of the device, and must answer an `Err` with a STALL
+pub fn handle<'a>(
+    setup: Setup,
+    descs: &Descriptors<'a>,
+    device: &Device<'_>,
+) -> Result<Response<'a>, Stall> {
+    if let Some(iface) = descs.dfu_iface {
+        if setup.bmrequesttype == DFU_REQUEST_TYPE && setup.brequest == DFU_DETACH {
+            if setup.windex != u16::from(iface) {
+                return Err(Stall::WrongInterface);
+            }
+
+            configured(device.state)?;
+            return Ok(Response::Status(Change::DfuDetach));
+        }
+    }
+
+    // Interface and Endpoint descriptors are only returned as part of the configuration
+    // descriptor; standalone requests for them are not valid and must be stalled
+    if setup.bmrequesttype & REQUEST_TYPE_MASK == 0 && setup.brequest == GET_DESCRIPTOR {
+        let ty = (setup.wvalue >> 8) as u8;
+        if ty == INTERFACE_DESCRIPTOR || ty == ENDPOINT_DESCRIPTOR {
+            return Err(Stall::NoDescriptor);
+        }
+    }
+
+    let req = Request::parse(
+        setup.bmrequesttype,
+        setup.brequest,
+        setup.wvalue,
+        setup.windex,
+        setup.wlength,
+    )
+    .map_err(|_| Stall::UnknownRequest)?;
+
+    match req {
+        Request::Standard(req) => std_req(setup, descs, device, req),
+
+        Request::Acm(req) => {
+            let iface = descs.cdc_iface.ok_or(Stall::Unsupported)?;
+            configured(device.state)?;
+            acm_req(iface, req)
+        }
+
+        Request::Hid(req) => {
+            let iface = descs.hid_iface.ok_or(Stall::Unsupported)?;
+            configured(device.state)?;
+            hid_req(iface, req)
+        }
+    }
+}
+
+fn std_req<'a>(
+    setup: Setup,
+    descs: &Descriptors<'a>,
+    device: &Device<'_>,
+    req: StandardRequest,
+) -> Result<Response<'a>, Stall> {
+    match req {
+        StandardRequest::GetDescriptor { descriptor, length } => {
+            let bytes = if let Some(bytes) = descs.provider.and_then(|f| f(descriptor)) {
+                bytes
+            } else {
+                match descriptor {
+                    GetDescriptor::Device => descs.device,
+
+                    GetDescriptor::Configuration { index } => *descs
+                        .config_descs
+                        .get(usize::from(index))
+                        .ok_or(Stall::NoDescriptor)?,
+
+                    // NOTE index 0 is the LANGID table; the language ID of the other indices is
+                    // ignored because all strings are in US English
+                    GetDescriptor::String { index, .. } => {
+                        let i = usize::from(index);
+                        let start = descs.string_offsets.get(i).ok_or(Stall::NoDescriptor)?;
+                        let end = descs.string_offsets.get(i + 1).ok_or(Stall::NoDescriptor)?;
+                        descs
+                            .strings
+                            .get(usize::from(*start)..usize::from(*end))
+                            .ok_or(Stall::NoDescriptor)?
+                    }
+
+                    // e.g. DeviceQualifier or OtherSpeedConfiguration, which a full-speed only
+                    // device doesn't have
+                    _ => return Err(Stall::Unsupported),
+                }
+            };
+
+            Ok(Response::Data(bytes.get(..length.into()).unwrap_or(bytes)))
+        }
+
+        StandardRequest::SetAddress { address } => {
+            let state = match *device.state {
+                State::Default | State::Address(_) => {
+                    if let Some(address) = address {
+                        State::Address(address)
+                    } else {
+                        State::Default
+                    }
+                }
+
+                State::Configured { .. } => return Err(Stall::InvalidState),
+            };
+
+            Ok(Response::SetAddress(state))
+        }
+
+        StandardRequest::SetConfiguration { value } => {
+            let change = match *device.state {
+                State::Default => return Err(Stall::InvalidState),
+
+                State::Address(address) => {
+                    if let Some(value) = value {
+                        let config = descs.config(value).ok_or(Stall::NoConfiguration)?;
+                        Change::Configure(State::Configured { address, value }, config)
+                    } else {
+                        // stay in the Address state
+                        Change::None
+                    }
+                }
+
+                State::Configured {
+                    address,
+                    value: curr_value,
+                } => {
+                    if let Some(value) = value {
+                        if value == curr_value {
+                            Change::None
+                        } else {
+                            let config = descs.config(value).ok_or(Stall::NoConfiguration)?;
+                            Change::Configure(State::Configured { address, value }, config)
+                        }
+                    } else {
+                        Change::Deconfigure(State::Address(address))
+                    }
+                }
+            };
+
+            Ok(Response::Status(change))
+        }
+
+        StandardRequest::ClearFeature(usb2::Feature::DeviceRemoteWakeup)
+            if device.remote_wakeup.is_some() =>
+        {
+            Ok(Response::Status(Change::RemoteWakeup(false)))
+        }
+
+        StandardRequest::ClearFeature(usb2::Feature::EndpointHalt(ep)) => {
+            if !endpoint_is_enabled(descs, device.state, ep) {
+                return Err(Stall::NoEndpoint);
+            }
+
+            Ok(Response::Status(Change::Halt(ep, false)))
+        }
+
+        StandardRequest::GetConfiguration => {
+            // 0 means not configured
+            let value = match *device.state {
+                State::Default => return Err(Stall::InvalidState),
+                State::Address(_) => 0,
+                State::Configured { value, .. } => value.get(),
+            };
+
+            Ok(Response::Value(Value::new([value, 0], 1, setup.wlength)))
+        }
+
+        StandardRequest::GetInterface { interface } => {
+            configured(device.state)?;
+
+            let alt = device
+                .alt_settings
+                .get(usize::from(interface))
+                .ok_or(Stall::NoInterface)?;
+
+            Ok(Response::Value(Value::new([*alt, 0], 1, setup.wlength)))
+        }
+
+        StandardRequest::GetStatus(usb2::GetStatus::Device) => {
+            // bit 0: self powered; bit 1: remote wakeup enabled
+            let status =
+                u8::from(descs.self_powered) | u8::from(device.remote_wakeup == Some(true)) << 1;
+
+            Ok(Response::Value(Value::new([status, 0], 2, setup.wlength)))
+        }
+
+        StandardRequest::GetStatus(usb2::GetStatus::Endpoint(ep)) => {
+            // bit 0: halted
+            let status = if ep.number == 0 {
+                0
+            } else if endpoint_is_enabled(descs, device.state, ep) {
+                u8::from((device.is_halted)(ep.number, ep.direction))
+            } else {
+                return Err(Stall::NoEndpoint);
+            };
+
+            Ok(Response::Value(Value::new([status, 0], 2, setup.wlength)))
+        }
+
+        StandardRequest::SetFeature(usb2::Feature::DeviceRemoteWakeup)
+            if device.remote_wakeup.is_some() =>
+        {
+            configured(device.state)?;
+
+            Ok(Response::Status(Change::RemoteWakeup(true)))
+        }
+
+        StandardRequest::SetFeature(usb2::Feature::EndpointHalt(ep)) => {
+            if !endpoint_is_enabled(descs, device.state, ep) {
+                return Err(Stall::NoEndpoint);
+            }
+
+            Ok(Response::Status(Change::Halt(ep, true)))
+        }
+
+        StandardRequest::SetInterface {
+            interface,
+            alternate,
+        } => {
+            configured(device.state)?;
+
+            let max = descs
+                .max_alt_setting
+                .get(usize::from(interface))
+                .ok_or(Stall::NoInterface)?;
+
+            if alternate > *max {
+                return Err(Stall::NoAltSetting);
+            }
+
+            Ok(Response::Status(Change::AltSetting {
+                interface,
+                alternate,
+            }))
+        }
+
+        StandardRequest::ClearFeature(..)
+        | StandardRequest::GetStatus(..)
+        | StandardRequest::SetDescriptor { .. }
+        | StandardRequest::SetFeature(..)
+        | StandardRequest::SynchFrame { .. } => Err(Stall::Unsupported),
+    }
+}
+
+fn acm_req<'a>(iface: u8, req: acm::Request) -> Result<Response<'a>, Stall> {
+    if req.interface != iface {
+        return Err(Stall::WrongInterface);
+    }
+
+    Ok(match req.kind {
+        acm::Kind::GetLineCoding => Response::GetLineCoding,
+        acm::Kind::SetLineCoding => Response::SetLineCoding,
+        acm::Kind::SetControlLineState { .. } => Response::Status(Change::None),
+    })
+}
+
+fn hid_req<'a>(iface: u8, req: hid::Request) -> Result<Response<'a>, Stall> {
+    if req.interface != iface {
+        return Err(Stall::WrongInterface);
+    }
+
+    match req.kind {
+        hid::Kind::SetIdle { .. } => Ok(Response::Status(Change::None)),
+
+        // FIXME we should return a valid HID report descriptor here but stalling seems enough to
+        // use `hidapi` with this device on Linux at least
+        hid::Kind::GetDescriptor { .. } => Err(Stall::Unsupported),
+    }
+}
+
+fn configured(state: &State) -> Result<(), Stall> {
+    match state {
+        State::Configured { .. } => Ok(()),
+        _ => Err(Stall::InvalidState),
+    }
+}
+
+// the ENDPOINT_HALT feature only applies to the endpoints of the current configuration
+fn endpoint_is_enabled(descs: &Descriptors<'_>, state: &State, ep: usb2::Endpoint) -> bool {
+    let value = match *state {
+        State::Configured { value, .. } => value,
+        _ => return false,
+    };
+
+    if ep.number == 0 || ep.number > 7 {
+        return false;
+    }
+
+    let config = match descs.config(value) {
+        Some(config) => config,
+        None => return false,
+    };
+
+    let enabled = match ep.direction {
+        usb2::Direction::In => config.epinen,
+        usb2::Direction::Out => config.epouten,
+    };
+    enabled & (1 << ep.number) != 0
+}